@@ -1,14 +1,19 @@
 extern crate protobuf_codegen_pure;
 
+const PROTOS: &[&str] = &[
+    "protobuf/abci.proto",
+    "protobuf/libs/kv/types.proto",
+    "protobuf/crypto/merkle/merkle.proto",
+];
+
 fn main() {
     protobuf_codegen_pure::Codegen::new()
         .out_dir("src/messages")
-        .inputs(&[
-            "protobuf/abci.proto",
-            "protobuf/libs/kv/types.proto",
-            "protobuf/crypto/merkle/merkle.proto",
-        ])
+        .inputs(PROTOS)
         .include("protobuf")
         .run()
         .expect("protoc");
+
+    #[cfg(feature = "prost-messages")]
+    prost_build::compile_protos(PROTOS, &["protobuf"]).expect("prost-build");
 }
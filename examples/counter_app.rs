@@ -2,6 +2,8 @@ extern crate abci;
 extern crate byteorder;
 extern crate env_logger;
 
+use std::fmt;
+
 use abci::*;
 use byteorder::{BigEndian, ByteOrder};
 use env_logger::Env;
@@ -29,35 +31,56 @@ fn convert_tx(tx: &[u8]) -> u64 {
     BigEndian::read_u64(tx)
 }
 
+// Returned by check_tx for out of order transactions.  The server turns it into code 1
+#[derive(Debug)]
+struct NotIncremental;
+
+impl fmt::Display for NotIncremental {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Count must be incremental!")
+    }
+}
+
+impl AbciError for NotIncremental {
+    fn code(&self) -> u32 {
+        1
+    }
+}
+
 impl abci::Application for CounterApp {
     // Validate transactions.  Rule:  Transactions must be incremental: 1,2,3,4...
-    fn check_tx(&mut self, req: &RequestCheckTx) -> ResponseCheckTx {
+    fn check_tx(
+        &mut self,
+        _ctx: &Context,
+        req: &RequestCheckTx,
+    ) -> Result<ResponseCheckTx, Box<dyn AbciError>> {
         // Get the Tx [u8] and convert to u64
         let c = convert_tx(req.get_tx());
-        let mut resp = ResponseCheckTx::new();
 
         // Validation logic
         if c != self.count + 1 {
-            resp.set_code(1);
-            resp.set_log(String::from("Count must be incremental!"));
-            return resp;
+            return Err(NotIncremental.into());
         }
 
         // Update state to keep state correct for next check_tx call
         self.count = c;
-        resp
+        Ok(ResponseCheckTx::new())
     }
 
-    fn deliver_tx(&mut self, req: &RequestDeliverTx) -> ResponseDeliverTx {
+    fn deliver_tx(
+        &mut self,
+        _ctx: &Context,
+        req: &RequestDeliverTx,
+    ) -> Result<ResponseDeliverTx, Box<dyn AbciError>> {
         // Get the Tx [u8]
         let c = convert_tx(req.get_tx());
         // Update state
         self.count = c;
         // Return default code 0 == bueno
-        ResponseDeliverTx::new()
+        Ok(ResponseDeliverTx::new())
     }
 
-    fn commit(&mut self, _req: &RequestCommit) -> ResponseCommit {
+    fn commit(&mut self, _ctx: &Context, _req: &RequestCommit) -> ResponseCommit {
         // Create the response
         let mut resp = ResponseCommit::new();
         // Convert count to bits
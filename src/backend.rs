@@ -0,0 +1,324 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::codec::WireMessage;
+use crate::context::Context;
+use crate::error::{into_response, ErrorResponse};
+use crate::messages::abci::*;
+use crate::server::ConnectionRole;
+use crate::{Application, CheckTxHandler};
+
+/// A set of generated ABCI messages the server can speak and applications can be written against.
+/// `Protobuf` is the rust-protobuf messages at the crate root, and `abci::prost::Prost` the prost
+/// ones when the `prost-messages` feature is enabled.
+///
+/// Besides the message types, a backend provides the few things the server needs to know about a
+/// request, and dispatches requests to the application.
+pub trait Backend: Sized + Send + Sync + 'static {
+    type Request: WireMessage + fmt::Debug + Send + Sync + 'static;
+    type Response: WireMessage + fmt::Debug + Send + Sync + 'static;
+
+    type RequestInfo;
+    type ResponseInfo: Default;
+    type RequestSetOption;
+    type ResponseSetOption: Default;
+    type RequestQuery;
+    type ResponseQuery: ErrorResponse + Default;
+    type RequestCheckTx;
+    type ResponseCheckTx: ErrorResponse + Default;
+    type RequestInitChain;
+    type ResponseInitChain: Default;
+    type RequestBeginBlock;
+    type ResponseBeginBlock: Default;
+    type RequestDeliverTx;
+    type ResponseDeliverTx: ErrorResponse + Default;
+    type RequestEndBlock;
+    type ResponseEndBlock: Default;
+    type RequestCommit;
+    type ResponseCommit: Default;
+
+    /// What the server tracks of `request`.
+    fn summarize(request: &Self::Request) -> RequestSummary;
+
+    /// Calls the application callback for `request` and wraps up its response. `echo` and
+    /// `flush` are answered without the application.
+    fn dispatch<A>(app: &mut A, ctx: &Context, request: &Self::Request) -> Self::Response
+    where
+        A: Application<Self> + ?Sized;
+
+    /// Runs a `check_tx` request through `handler`.
+    fn check_tx(
+        handler: &dyn CheckTxHandler<Self>,
+        ctx: &Context,
+        request: &Self::Request,
+    ) -> Self::Response;
+
+    /// A `ResponseException` carrying `error`.
+    fn exception(error: String) -> Self::Response;
+
+    fn is_flush(response: &Self::Response) -> bool;
+}
+
+/// The ABCI methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    Echo,
+    Flush,
+    Info,
+    SetOption,
+    InitChain,
+    Query,
+    BeginBlock,
+    CheckTx,
+    DeliverTx,
+    EndBlock,
+    Commit,
+    /// A request without a value, or with one this crate does not know.
+    Unknown,
+}
+
+impl Method {
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Echo => "echo",
+            Method::Flush => "flush",
+            Method::Info => "info",
+            Method::SetOption => "set_option",
+            Method::InitChain => "init_chain",
+            Method::Query => "query",
+            Method::BeginBlock => "begin_block",
+            Method::CheckTx => "check_tx",
+            Method::DeliverTx => "deliver_tx",
+            Method::EndBlock => "end_block",
+            Method::Commit => "commit",
+            Method::Unknown => "unknown request",
+        }
+    }
+
+    /// The connection the method is expected on. `echo` and `flush` are valid on every
+    /// connection and return `None`.
+    pub fn role(self) -> Option<ConnectionRole> {
+        match self {
+            Method::Info | Method::SetOption | Method::Query => Some(ConnectionRole::Query),
+            Method::CheckTx => Some(ConnectionRole::Mempool),
+            Method::InitChain
+            | Method::BeginBlock
+            | Method::DeliverTx
+            | Method::EndBlock
+            | Method::Commit => Some(ConnectionRole::Consensus),
+            Method::Echo | Method::Flush | Method::Unknown => None,
+        }
+    }
+}
+
+/// The fields of a `begin_block` header that `Context` exposes, whatever the backend.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub chain_id: String,
+    pub height: i64,
+    /// `None` if the time is unset or out of range.
+    pub time: Option<SystemTime>,
+    pub app_hash: Vec<u8>,
+    pub proposer_address: Vec<u8>,
+}
+
+impl From<&Header> for BlockHeader {
+    fn from(header: &Header) -> BlockHeader {
+        BlockHeader {
+            chain_id: header.get_chain_id().to_string(),
+            height: header.get_height(),
+            time: if header.has_time() {
+                header.system_time().ok()
+            } else {
+                None
+            },
+            app_hash: header.get_app_hash().to_vec(),
+            proposer_address: header.get_proposer_address().to_vec(),
+        }
+    }
+}
+
+/// The parts of a request the server tracks, whatever the backend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestSummary {
+    pub method: Method,
+    /// The header of a `begin_block`.
+    pub header: Option<BlockHeader>,
+    /// The height of an `end_block`.
+    pub height: Option<i64>,
+}
+
+impl RequestSummary {
+    pub fn new(method: Method) -> RequestSummary {
+        RequestSummary {
+            method,
+            header: None,
+            height: None,
+        }
+    }
+}
+
+/// The rust-protobuf messages re-exported at the crate root. This is the default backend.
+#[derive(Clone, Copy, Debug)]
+pub struct Protobuf;
+
+pub(crate) fn method_of(request: &Request) -> Method {
+    match request.value {
+        Some(Request_oneof_value::echo(_)) => Method::Echo,
+        Some(Request_oneof_value::flush(_)) => Method::Flush,
+        Some(Request_oneof_value::info(_)) => Method::Info,
+        Some(Request_oneof_value::set_option(_)) => Method::SetOption,
+        Some(Request_oneof_value::init_chain(_)) => Method::InitChain,
+        Some(Request_oneof_value::query(_)) => Method::Query,
+        Some(Request_oneof_value::begin_block(_)) => Method::BeginBlock,
+        Some(Request_oneof_value::check_tx(_)) => Method::CheckTx,
+        Some(Request_oneof_value::deliver_tx(_)) => Method::DeliverTx,
+        Some(Request_oneof_value::end_block(_)) => Method::EndBlock,
+        Some(Request_oneof_value::commit(_)) => Method::Commit,
+        None => Method::Unknown,
+    }
+}
+
+impl Backend for Protobuf {
+    type Request = Request;
+    type Response = Response;
+
+    type RequestInfo = RequestInfo;
+    type ResponseInfo = ResponseInfo;
+    type RequestSetOption = RequestSetOption;
+    type ResponseSetOption = ResponseSetOption;
+    type RequestQuery = RequestQuery;
+    type ResponseQuery = ResponseQuery;
+    type RequestCheckTx = RequestCheckTx;
+    type ResponseCheckTx = ResponseCheckTx;
+    type RequestInitChain = RequestInitChain;
+    type ResponseInitChain = ResponseInitChain;
+    type RequestBeginBlock = RequestBeginBlock;
+    type ResponseBeginBlock = ResponseBeginBlock;
+    type RequestDeliverTx = RequestDeliverTx;
+    type ResponseDeliverTx = ResponseDeliverTx;
+    type RequestEndBlock = RequestEndBlock;
+    type ResponseEndBlock = ResponseEndBlock;
+    type RequestCommit = RequestCommit;
+    type ResponseCommit = ResponseCommit;
+
+    fn summarize(request: &Request) -> RequestSummary {
+        let mut summary = RequestSummary::new(method_of(request));
+        match request.value {
+            Some(Request_oneof_value::begin_block(ref r)) => {
+                summary.header = Some(BlockHeader::from(r.get_header()))
+            }
+            Some(Request_oneof_value::end_block(ref r)) => summary.height = Some(r.get_height()),
+            _ => {}
+        }
+        summary
+    }
+
+    fn dispatch<A>(app: &mut A, ctx: &Context, request: &Request) -> Response
+    where
+        A: Application<Protobuf> + ?Sized,
+    {
+        let mut response = Response::new();
+
+        match request.value {
+            // Info
+            Some(Request_oneof_value::info(ref r)) => response.set_info(app.info(ctx, r)),
+            // Init chain
+            Some(Request_oneof_value::init_chain(ref r)) => {
+                response.set_init_chain(app.init_chain(ctx, r))
+            }
+            // Set option
+            Some(Request_oneof_value::set_option(ref r)) => {
+                response.set_set_option(app.set_option(ctx, r))
+            }
+            // Query
+            Some(Request_oneof_value::query(ref r)) => {
+                response.set_query(into_response(app.query(ctx, r)))
+            }
+            // Check tx
+            Some(Request_oneof_value::check_tx(ref r)) => {
+                response.set_check_tx(into_response(app.check_tx(ctx, r)))
+            }
+            // Begin block
+            Some(Request_oneof_value::begin_block(ref r)) => {
+                response.set_begin_block(app.begin_block(ctx, r))
+            }
+            // Deliver Tx
+            Some(Request_oneof_value::deliver_tx(ref r)) => {
+                response.set_deliver_tx(into_response(app.deliver_tx(ctx, r)))
+            }
+            // End block
+            Some(Request_oneof_value::end_block(ref r)) => {
+                response.set_end_block(app.end_block(ctx, r))
+            }
+            // Commit
+            Some(Request_oneof_value::commit(ref r)) => response.set_commit(app.commit(ctx, r)),
+            // Flush
+            Some(Request_oneof_value::flush(_)) => response.set_flush(ResponseFlush::new()),
+            // Echo
+            Some(Request_oneof_value::echo(ref r)) => {
+                let echo_msg = r.get_message().to_string();
+                let mut echo = ResponseEcho::new();
+                echo.set_message(echo_msg);
+                response.set_echo(echo);
+            }
+            _ => {
+                let mut re = ResponseException::new();
+                re.set_error(String::from("Unrecognized request"));
+                response.set_exception(re)
+            }
+        }
+        response
+    }
+
+    fn check_tx(
+        handler: &dyn CheckTxHandler<Protobuf>,
+        ctx: &Context,
+        request: &Request,
+    ) -> Response {
+        let mut response = Response::new();
+        let result = handler.check_tx(ctx, request.get_check_tx());
+        response.set_check_tx(into_response(result));
+        response
+    }
+
+    fn exception(error: String) -> Response {
+        let mut re = ResponseException::new();
+        re.set_error(error);
+        let mut response = Response::new();
+        response.set_exception(re);
+        response
+    }
+
+    fn is_flush(response: &Response) -> bool {
+        response.has_flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn should_summarize_consensus_requests() {
+        let mut request = Request::new();
+        let header = request.mut_begin_block().mut_header();
+        header.set_height(4);
+        header.set_system_time(UNIX_EPOCH).unwrap();
+        let summary = Protobuf::summarize(&request);
+        assert_eq!(summary.method, Method::BeginBlock);
+        let header = summary.header.unwrap();
+        assert_eq!(header.height, 4);
+        assert_eq!(header.time, Some(UNIX_EPOCH));
+
+        let mut request = Request::new();
+        request.mut_end_block().set_height(4);
+        assert_eq!(Protobuf::summarize(&request).height, Some(4));
+
+        let summary = Protobuf::summarize(&Request::new());
+        assert_eq!(summary, RequestSummary::new(Method::Unknown));
+        assert_eq!(summary.method.role(), None);
+        assert_eq!(Method::CheckTx.role(), Some(ConnectionRole::Mempool));
+    }
+}
@@ -0,0 +1,314 @@
+//! Hashes Tendermint computes over block data, so applications and test harnesses can check the
+//! values Tendermint sends them.
+//!
+//! The header hash is the simple Merkle root of the Amino encodings of the header fields, as in
+//! Tendermint 0.33:
+//!
+//! ```rust
+//! let mut request = abci::RequestBeginBlock::new();
+//! request.mut_header().set_chain_id(String::from("test-chain"));
+//! request.mut_header().set_validators_hash(vec![1; 32]);
+//! let hash = request.get_header().hash().unwrap();
+//! request.set_hash(hash);
+//! request.verify_hash().unwrap();
+//! ```
+//!
+//! `last_results_hash` gives the `last_results_hash` of the next block's header from the
+//! `deliver_tx` responses of a block.
+use std::error::Error;
+use std::fmt;
+
+use crate::merkle::amino::Writer;
+use crate::merkle::{simple, to_hex};
+use crate::messages::abci::*;
+
+/// Why a `RequestBeginBlock` failed the header hash check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderHashError {
+    MissingHeader,
+    /// The header has no validators hash, which Tendermint treats as a header without a hash.
+    MissingValidatorsHash,
+    HashMismatch {
+        expected: Vec<u8>,
+        computed: Vec<u8>,
+    },
+}
+
+impl fmt::Display for HeaderHashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderHashError::MissingHeader => write!(f, "request has no header"),
+            HeaderHashError::MissingValidatorsHash => {
+                write!(f, "header has no validators hash and cannot be hashed")
+            }
+            HeaderHashError::HashMismatch { expected, computed } => write!(
+                f,
+                "header hash mismatch: request has {} but header hashes to {}",
+                to_hex(expected),
+                to_hex(computed)
+            ),
+        }
+    }
+}
+
+impl Error for HeaderHashError {}
+
+/// Amino encoding of a byte slice or string as a bare value: its length, then its bytes. Empty
+/// values encode to nothing, like Tendermint's `cdcEncode`.
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.is_empty() {
+        return Vec::new();
+    }
+    let mut writer = Writer::new();
+    writer.uvarint(bytes.len() as u64);
+    let mut encoded = writer.into_vec();
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+fn encode_block_id(block_id: &BlockID) -> Vec<u8> {
+    let parts = block_id.get_parts_header();
+    let mut parts_writer = Writer::new();
+    parts_writer.varint_field(1, i64::from(parts.get_total()) as u64);
+    parts_writer.bytes_field(2, parts.get_hash());
+    let parts = parts_writer.into_vec();
+
+    let mut writer = Writer::new();
+    writer.bytes_field(1, block_id.get_hash());
+    writer.bytes_field(2, &parts);
+    writer.into_vec()
+}
+
+/// The part of a `ResponseDeliverTx` that goes into `last_results_hash`, Amino encoded. Like
+/// Tendermint 0.33, it is made of `code` and `data` only: the gas fields join them in 0.34.
+pub fn deterministic_result_bytes(response: &ResponseDeliverTx) -> Vec<u8> {
+    let mut writer = Writer::new();
+    writer.varint_field(1, u64::from(response.get_code()));
+    writer.bytes_field(2, response.get_data());
+    writer.into_vec()
+}
+
+/// The root of the simple Merkle tree over the deterministic parts of a block's `deliver_tx`
+/// responses, in order. Empty if the block has no transactions.
+pub fn last_results_hash(responses: &[ResponseDeliverTx]) -> Vec<u8> {
+    let results: Vec<Vec<u8>> = responses.iter().map(deterministic_result_bytes).collect();
+    simple::hash_from_byte_slices(&results)
+}
+
+impl Header {
+    /// The Merkle root of the header's fields, or `None` if the header has no validators hash.
+    pub fn hash(&self) -> Option<Vec<u8>> {
+        if self.get_validators_hash().is_empty() {
+            return None;
+        }
+
+        let mut version = Writer::new();
+        version.varint_field(1, self.get_version().get_Block());
+        version.varint_field(2, self.get_version().get_App());
+
+        // `cdcEncode` only leaves out values of zero length, so a zero height is still `0x00`.
+        // Zero structs encode to nothing either way.
+        let mut height = Writer::new();
+        height.uvarint(self.get_height() as u64);
+
+        let mut time = Writer::new();
+        time.varint_field(1, self.get_time().get_seconds() as u64);
+        time.varint_field(2, self.get_time().get_nanos() as u64);
+
+        let fields = [
+            version.into_vec(),
+            encode_bytes(self.get_chain_id().as_bytes()),
+            height.into_vec(),
+            time.into_vec(),
+            encode_block_id(self.get_last_block_id()),
+            encode_bytes(self.get_last_commit_hash()),
+            encode_bytes(self.get_data_hash()),
+            encode_bytes(self.get_validators_hash()),
+            encode_bytes(self.get_next_validators_hash()),
+            encode_bytes(self.get_consensus_hash()),
+            encode_bytes(self.get_app_hash()),
+            encode_bytes(self.get_last_results_hash()),
+            encode_bytes(self.get_evidence_hash()),
+            encode_bytes(self.get_proposer_address()),
+        ];
+        Some(simple::hash_from_byte_slices(&fields))
+    }
+}
+
+impl RequestBeginBlock {
+    /// Checks that `hash` is the hash of `header`.
+    pub fn verify_hash(&self) -> Result<(), HeaderHashError> {
+        if !self.has_header() {
+            return Err(HeaderHashError::MissingHeader);
+        }
+        let computed = self
+            .get_header()
+            .hash()
+            .ok_or(HeaderHashError::MissingValidatorsHash)?;
+        if computed != self.get_hash() {
+            return Err(HeaderHashError::HashMismatch {
+                expected: self.get_hash().to_vec(),
+                computed,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        let mut header = Header::new();
+        header.mut_version().set_Block(10);
+        header.mut_version().set_App(1);
+        header.set_chain_id(String::from("test-chain"));
+        header.set_height(3);
+        header.mut_time().set_seconds(1_570_983_284);
+        header.mut_time().set_nanos(5);
+        header.mut_last_block_id().set_hash(vec![0xAA; 32]);
+        header.mut_last_block_id().mut_parts_header().set_total(6);
+        header
+            .mut_last_block_id()
+            .mut_parts_header()
+            .set_hash(vec![0xBB; 32]);
+        header.set_validators_hash(vec![0xCC; 32]);
+        header.set_proposer_address(vec![0xDD; 20]);
+        header
+    }
+
+    #[test]
+    fn should_hash_amino_encoded_fields() {
+        let mut block_id = vec![0x0A, 32];
+        block_id.extend_from_slice(&[0xAA; 32]);
+        block_id.extend_from_slice(&[0x12, 36, 0x08, 6, 0x12, 32]);
+        block_id.extend_from_slice(&[0xBB; 32]);
+        let fields: Vec<Vec<u8>> = vec![
+            vec![0x08, 10, 0x10, 1],
+            [&[10][..], b"test-chain"].concat(),
+            vec![3],
+            vec![0x08, 0xF4, 0x9A, 0x8D, 0xED, 0x05, 0x10, 5],
+            block_id,
+            vec![],
+            vec![],
+            [&[32][..], &[0xCC; 32][..]].concat(),
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            [&[20][..], &[0xDD; 20][..]].concat(),
+        ];
+        assert_eq!(
+            header().hash().unwrap(),
+            simple::hash_from_byte_slices(&fields)
+        );
+        assert_eq!(Header::new().hash(), None);
+    }
+
+    #[test]
+    fn should_match_tendermint_header_hash() {
+        // `TestHeaderHash` in Tendermint 0.33's types/block_test.go.
+        let sum = simple::sha256;
+        let mut header = Header::new();
+        header.mut_version().set_Block(1);
+        header.mut_version().set_App(2);
+        header.set_chain_id(String::from("chainId"));
+        header.set_height(3);
+        header.mut_time().set_seconds(1_570_983_284);
+        header.mut_last_block_id().set_hash(vec![0; 32]);
+        header.mut_last_block_id().mut_parts_header().set_total(6);
+        header
+            .mut_last_block_id()
+            .mut_parts_header()
+            .set_hash(vec![0; 32]);
+        header.set_last_commit_hash(sum(b"last_commit_hash"));
+        header.set_data_hash(sum(b"data_hash"));
+        header.set_validators_hash(sum(b"validators_hash"));
+        header.set_next_validators_hash(sum(b"next_validators_hash"));
+        header.set_consensus_hash(sum(b"consensus_hash"));
+        header.set_app_hash(sum(b"app_hash"));
+        header.set_last_results_hash(sum(b"last_results_hash"));
+        header.set_evidence_hash(sum(b"evidence_hash"));
+        header.set_proposer_address(sum(b"proposer_address")[..20].to_vec());
+        assert_eq!(
+            to_hex(&header.hash().unwrap()),
+            "ABDC78921B18A47EE6BEF5E31637BADB0F3E587E3C0F4DB2D1E93E9FF0533862"
+        );
+    }
+
+    #[test]
+    fn should_encode_empty_fields_like_cdc_encode() {
+        let mut header = Header::new();
+        header.set_validators_hash(vec![0xCC; 32]);
+        let mut fields = vec![Vec::new(); 14];
+        fields[2] = vec![0];
+        fields[7] = [&[32][..], &[0xCC; 32][..]].concat();
+        assert_eq!(
+            header.hash().unwrap(),
+            simple::hash_from_byte_slices(&fields)
+        );
+
+        header.mut_last_block_id().mut_parts_header().set_total(1);
+        fields[4] = vec![0x12, 2, 0x08, 1];
+        assert_eq!(
+            header.hash().unwrap(),
+            simple::hash_from_byte_slices(&fields)
+        );
+    }
+
+    #[test]
+    fn should_hash_code_and_data_of_results() {
+        let mut ok = ResponseDeliverTx::new();
+        ok.set_data(b"result".to_vec());
+        ok.set_log(String::from("ignored"));
+        ok.set_gas_used(100);
+        let mut failed = ResponseDeliverTx::new();
+        failed.set_code(300);
+        failed.set_info(String::from("ignored"));
+
+        assert_eq!(
+            deterministic_result_bytes(&ok),
+            [&[0x12, 6][..], b"result"].concat()
+        );
+        assert_eq!(deterministic_result_bytes(&failed), vec![0x08, 0xAC, 0x02]);
+        assert_eq!(
+            last_results_hash(&[ok.clone(), failed.clone(), ResponseDeliverTx::new()]),
+            simple::hash_from_byte_slices(&[
+                deterministic_result_bytes(&ok),
+                deterministic_result_bytes(&failed),
+                Vec::new(),
+            ])
+        );
+        assert!(last_results_hash(&[]).is_empty());
+
+        let mut changed = ok.clone();
+        changed.set_gas_wanted(5);
+        assert_eq!(
+            last_results_hash(&[changed]),
+            last_results_hash(&[ok.clone()])
+        );
+        let mut changed = ok.clone();
+        changed.set_data(b"other".to_vec());
+        assert_ne!(last_results_hash(&[changed]), last_results_hash(&[ok]));
+    }
+
+    #[test]
+    fn should_check_begin_block_hash() {
+        let mut request = RequestBeginBlock::new();
+        assert_eq!(request.verify_hash(), Err(HeaderHashError::MissingHeader));
+
+        request.set_header(header());
+        request.set_hash(header().hash().unwrap());
+        request.verify_hash().unwrap();
+
+        request.mut_header().set_height(4);
+        match request.verify_hash() {
+            Err(HeaderHashError::HashMismatch { expected, .. }) => {
+                assert_eq!(expected, header().hash().unwrap())
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
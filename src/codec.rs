@@ -1,24 +1,72 @@
+use std::io;
+use std::marker::PhantomData;
+
 use bytes::{buf::BufMutExt, BufMut, BytesMut};
 use integer_encoding::VarInt;
-use protobuf::{Message, ProtobufError};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::messages::abci::*;
 
+/// A protobuf message the codec can read and write. Implemented for all rust-protobuf messages,
+/// and for the prost messages when the `prost-messages` feature is enabled.
+pub trait WireMessage: Sized {
+    fn encoded_len(&self) -> usize;
+
+    fn encode_to(&self, buf: &mut BytesMut) -> io::Result<()>;
+
+    fn decode_from(bytes: &[u8]) -> io::Result<Self>;
+}
+
+impl<M: protobuf::Message> WireMessage for M {
+    fn encoded_len(&self) -> usize {
+        self.compute_size() as usize
+    }
+
+    fn encode_to(&self, buf: &mut BytesMut) -> io::Result<()> {
+        self.write_to_writer(&mut buf.writer())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn decode_from(bytes: &[u8]) -> io::Result<M> {
+        protobuf::parse_from_bytes(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Converts a message to its counterpart in another backend (or any message with the same wire
+/// format) by encoding and decoding it.
+pub fn transcode<F: WireMessage, T: WireMessage>(message: &F) -> io::Result<T> {
+    let mut buf = BytesMut::with_capacity(message.encoded_len());
+    message.encode_to(&mut buf)?;
+    T::decode_from(&buf)
+}
+
+/// Length-delimited framing used on ABCI connections: decodes `D` and encodes `E`. The server
+/// uses `ABCICodec<Request, Response>` with the messages of its `Backend`, the rust-protobuf ones
+/// by default, and swapping the parameters gives the client side.
 #[derive(Debug)]
-pub struct ABCICodec;
+pub struct ABCICodec<D = Request, E = Response> {
+    messages: PhantomData<fn(E) -> D>,
+}
+
+impl<D, E> ABCICodec<D, E> {
+    pub fn new() -> ABCICodec<D, E> {
+        ABCICodec {
+            messages: PhantomData,
+        }
+    }
+}
 
-impl ABCICodec {
-    pub fn new() -> ABCICodec {
-        ABCICodec
+impl<D, E> Default for ABCICodec<D, E> {
+    fn default() -> ABCICodec<D, E> {
+        ABCICodec::new()
     }
 }
 
-impl Decoder for ABCICodec {
-    type Item = Request;
-    type Error = ProtobufError;
+impl<D: WireMessage, E> Decoder for ABCICodec<D, E> {
+    type Item = D;
+    type Error = io::Error;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Request>, ProtobufError> {
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<D>> {
         let length = buf.len();
         if length == 0 {
             return Ok(None);
@@ -27,27 +75,27 @@ impl Decoder for ABCICodec {
         if varint.0 as usize + varint.1 > length {
             return Ok(None);
         }
-        let request = protobuf::parse_from_bytes(&buf[varint.1..(varint.0 as usize + varint.1)])?;
+        let message = D::decode_from(&buf[varint.1..(varint.0 as usize + varint.1)])?;
         let _ = buf.split_to(varint.0 as usize + varint.1);
-        Ok(Some(request))
+        Ok(Some(message))
     }
 }
 
-impl Encoder<Response> for ABCICodec {
-    type Error = ProtobufError;
+impl<D, E: WireMessage> Encoder<E> for ABCICodec<D, E> {
+    type Error = io::Error;
 
-    fn encode(&mut self, msg: Response, buf: &mut BytesMut) -> Result<(), ProtobufError> {
-        let msg_len = msg.compute_size();
-        let varint = i64::encode_var_vec(i64::from(msg_len));
+    fn encode(&mut self, msg: E, buf: &mut BytesMut) -> io::Result<()> {
+        let msg_len = msg.encoded_len();
+        let varint = i64::encode_var_vec(msg_len as i64);
 
         let remaining = buf.remaining_mut();
-        let needed = msg_len as usize + varint.len();
+        let needed = msg_len + varint.len();
         if remaining < needed {
             buf.reserve(needed);
         }
 
         buf.put(varint.as_ref());
-        msg.write_to_writer(&mut buf.writer())?;
+        msg.encode_to(buf)?;
         trace!("Encode response! {:?}", &buf[..]);
         Ok(())
     }
@@ -56,6 +104,7 @@ impl Encoder<Response> for ABCICodec {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use protobuf::Message;
     use std::error::Error;
 
     fn setup_echo_request_buf() -> Result<BytesMut, Box<dyn Error>> {
@@ -104,7 +153,7 @@ mod tests {
 
     #[test]
     fn should_decode() {
-        let mut codec = ABCICodec::new();
+        let mut codec: ABCICodec = ABCICodec::new();
         let mut buf = setup_echo_request_buf().unwrap();
         let r = codec.decode(&mut buf);
         assert!(r.is_ok());
@@ -119,7 +168,7 @@ mod tests {
 
     #[test]
     fn should_decode_large_request() {
-        let mut codec = ABCICodec::new();
+        let mut codec: ABCICodec = ABCICodec::new();
         let mut buf = setup_echo_large_request_buf().unwrap();
         let r = codec.decode(&mut buf);
         assert!(r.is_ok());
@@ -133,7 +182,7 @@ mod tests {
 
     #[test]
     fn should_encode() {
-        let mut codec = ABCICodec::new();
+        let mut codec: ABCICodec = ABCICodec::new();
 
         let mut r = Response::new();
         let mut echo = ResponseEcho::new();
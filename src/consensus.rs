@@ -0,0 +1,286 @@
+use std::sync::{Arc, Mutex};
+
+use crate::backend::{BlockHeader, Method, RequestSummary};
+
+/// Where the consensus connection is in the block lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsensusPhase {
+    /// No consensus request seen yet. Tendermint either starts with `init_chain`, or with
+    /// `begin_block` when it is resuming an existing chain.
+    Idle,
+    /// `init_chain` is done and the first block has not started.
+    Initialized,
+    /// Between `begin_block` and `end_block`, when `deliver_tx` calls are expected.
+    InBlock,
+    /// Between `end_block` and `commit`.
+    EndedBlock,
+    /// `commit` is done and the next block has not started.
+    Committed,
+}
+
+/// What the server does with a consensus request that arrives out of order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderingPolicy {
+    /// Track the phase and height, but accept every request silently. This is the default.
+    Off,
+    /// Log out-of-order requests and still pass them to the application.
+    Log,
+    /// Log out-of-order requests and reply with a `ResponseException` instead of calling the
+    /// application.
+    Reject,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ConsensusState {
+    pub(crate) phase: ConsensusPhase,
+    pub(crate) height: i64,
+    /// Header from the latest `begin_block`.
+    pub(crate) header: Option<Arc<BlockHeader>>,
+    /// Number of `deliver_tx` calls since the latest `begin_block`.
+    pub(crate) txs: usize,
+}
+
+impl ConsensusState {
+    /// Computes the state after `request`, along with a description of the problem if the
+    /// request is not valid in the current state.
+    fn next(&self, request: &RequestSummary) -> (ConsensusState, Option<String>) {
+        use self::ConsensusPhase::*;
+
+        let unexpected = || {
+            Some(format!(
+                "{} is not expected while {:?} at height {}",
+                request.method.name(),
+                self.phase,
+                self.height
+            ))
+        };
+        let expect = |expected: bool| if expected { None } else { unexpected() };
+        match request.method {
+            Method::InitChain => {
+                let next = ConsensusState {
+                    phase: Initialized,
+                    height: 0,
+                    header: None,
+                    txs: 0,
+                };
+                (next, expect(self.phase == Idle))
+            }
+            Method::BeginBlock => {
+                let header = request.header.clone().unwrap_or_default();
+                let height = header.height;
+                let next = ConsensusState {
+                    phase: InBlock,
+                    height,
+                    header: Some(Arc::new(header)),
+                    txs: 0,
+                };
+                let error = match self.phase {
+                    Idle if height > 0 => None,
+                    Initialized | Committed if height == self.height + 1 => None,
+                    Idle | Initialized | Committed => Some(format!(
+                        "begin_block for height {} after height {}",
+                        height, self.height
+                    )),
+                    _ => unexpected(),
+                };
+                (next, error)
+            }
+            Method::DeliverTx => {
+                let next = ConsensusState {
+                    txs: self.txs + 1,
+                    ..self.clone()
+                };
+                (next, expect(self.phase == InBlock))
+            }
+            Method::EndBlock => {
+                let height = request.height.unwrap_or_default();
+                let next = ConsensusState {
+                    phase: EndedBlock,
+                    height,
+                    ..self.clone()
+                };
+                let error = match self.phase {
+                    InBlock if height == self.height => None,
+                    InBlock => Some(format!(
+                        "end_block for height {} inside block {}",
+                        height, self.height
+                    )),
+                    _ => unexpected(),
+                };
+                (next, error)
+            }
+            Method::Commit => {
+                let next = ConsensusState {
+                    phase: Committed,
+                    ..self.clone()
+                };
+                (next, expect(self.phase == EndedBlock))
+            }
+            _ => (self.clone(), None),
+        }
+    }
+}
+
+/// Shared view of the consensus connection's progress, kept up to date by the server. Each server
+/// tracks its own; get a handle from `Context::consensus` and hold on to it in the application to
+/// read the current phase and height at any time.
+#[derive(Clone, Debug)]
+pub struct ConsensusContext {
+    state: Arc<Mutex<ConsensusState>>,
+}
+
+impl Default for ConsensusContext {
+    fn default() -> ConsensusContext {
+        ConsensusContext {
+            state: Arc::new(Mutex::new(ConsensusState {
+                phase: ConsensusPhase::Idle,
+                height: 0,
+                header: None,
+                txs: 0,
+            })),
+        }
+    }
+}
+
+impl ConsensusContext {
+    pub fn new() -> ConsensusContext {
+        ConsensusContext::default()
+    }
+
+    /// The current phase of the block lifecycle.
+    pub fn phase(&self) -> ConsensusPhase {
+        self.state.lock().unwrap().phase
+    }
+
+    /// The height of the block in progress, or of the last committed block between blocks.
+    /// Zero until the first block starts.
+    pub fn height(&self) -> i64 {
+        self.state.lock().unwrap().height
+    }
+
+    pub(crate) fn state(&self) -> ConsensusState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Moves the state machine along for a consensus request. Returns an error message when the
+    /// request is out of order and `policy` rejects it, in which case the state is unchanged.
+    pub(crate) fn advance(
+        &self,
+        request: &RequestSummary,
+        policy: OrderingPolicy,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let (next, error) = state.next(request);
+        if let Some(error) = error {
+            match policy {
+                OrderingPolicy::Off => {}
+                OrderingPolicy::Log => warn!("Out of order consensus request: {}", error),
+                OrderingPolicy::Reject => {
+                    warn!("Rejecting out of order consensus request: {}", error);
+                    return Err(error);
+                }
+            }
+        }
+        *state = next;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_chain() -> RequestSummary {
+        RequestSummary::new(Method::InitChain)
+    }
+
+    fn begin_block(height: i64) -> RequestSummary {
+        let header = BlockHeader {
+            height,
+            ..BlockHeader::default()
+        };
+        RequestSummary {
+            header: Some(header),
+            ..RequestSummary::new(Method::BeginBlock)
+        }
+    }
+
+    fn deliver_tx() -> RequestSummary {
+        RequestSummary::new(Method::DeliverTx)
+    }
+
+    fn end_block(height: i64) -> RequestSummary {
+        RequestSummary {
+            height: Some(height),
+            ..RequestSummary::new(Method::EndBlock)
+        }
+    }
+
+    fn commit() -> RequestSummary {
+        RequestSummary::new(Method::Commit)
+    }
+
+    #[test]
+    fn should_track_block_lifecycle() {
+        let context = ConsensusContext::new();
+        let policy = OrderingPolicy::Reject;
+        assert_eq!(context.phase(), ConsensusPhase::Idle);
+
+        context.advance(&init_chain(), policy).unwrap();
+        assert_eq!(context.phase(), ConsensusPhase::Initialized);
+        for height in 1..=2 {
+            context.advance(&begin_block(height), policy).unwrap();
+            assert_eq!(context.phase(), ConsensusPhase::InBlock);
+            assert_eq!(context.height(), height);
+            context.advance(&deliver_tx(), policy).unwrap();
+            context.advance(&deliver_tx(), policy).unwrap();
+            context.advance(&end_block(height), policy).unwrap();
+            assert_eq!(context.phase(), ConsensusPhase::EndedBlock);
+            context.advance(&commit(), policy).unwrap();
+            assert_eq!(context.phase(), ConsensusPhase::Committed);
+            assert_eq!(context.height(), height);
+        }
+    }
+
+    #[test]
+    fn should_resume_at_any_height() {
+        let context = ConsensusContext::new();
+        context
+            .advance(&begin_block(42), OrderingPolicy::Reject)
+            .unwrap();
+        assert_eq!(context.height(), 42);
+    }
+
+    #[test]
+    fn should_reject_out_of_order_requests() {
+        let context = ConsensusContext::new();
+        let policy = OrderingPolicy::Reject;
+        assert_eq!(
+            context.advance(&deliver_tx(), policy),
+            Err(String::from(
+                "deliver_tx is not expected while Idle at height 0"
+            ))
+        );
+        context.advance(&begin_block(3), policy).unwrap();
+        assert_eq!(
+            context.advance(&end_block(4), policy),
+            Err(String::from("end_block for height 4 inside block 3"))
+        );
+        assert!(context.advance(&commit(), policy).is_err());
+        context.advance(&end_block(3), policy).unwrap();
+        context.advance(&commit(), policy).unwrap();
+        assert_eq!(
+            context.advance(&begin_block(5), policy),
+            Err(String::from("begin_block for height 5 after height 3"))
+        );
+        assert_eq!(context.phase(), ConsensusPhase::Committed);
+    }
+
+    #[test]
+    fn should_follow_requests_when_only_logging() {
+        let context = ConsensusContext::new();
+        context.advance(&end_block(7), OrderingPolicy::Log).unwrap();
+        assert_eq!(context.phase(), ConsensusPhase::EndedBlock);
+        assert_eq!(context.height(), 7);
+    }
+}
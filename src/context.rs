@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::backend::{BlockHeader, Method, RequestSummary};
+use crate::consensus::{ConsensusContext, ConsensusPhase};
+use crate::server::ConnectionRole;
+
+/// Set by the server once the connection a request came in on has closed, so long running
+/// callbacks (or a `CheckTxHandler` running on another thread) can give up early.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Information the server passes to every application callback alongside the request.
+#[derive(Clone, Debug)]
+pub struct Context {
+    connection_id: usize,
+    role: Option<ConnectionRole>,
+    method: Method,
+    phase: ConsensusPhase,
+    height: i64,
+    header: Option<Arc<BlockHeader>>,
+    tx_index: Option<usize>,
+    consensus: ConsensusContext,
+    cancellation: CancellationToken,
+}
+
+impl Context {
+    pub(crate) fn new(
+        connection_id: usize,
+        role: Option<ConnectionRole>,
+        consensus: &ConsensusContext,
+        request: &RequestSummary,
+        cancellation: CancellationToken,
+    ) -> Context {
+        let state = consensus.state();
+        let tx_index = if request.method == Method::DeliverTx {
+            state.txs.checked_sub(1)
+        } else {
+            None
+        };
+        Context {
+            connection_id,
+            role,
+            method: request.method,
+            phase: state.phase,
+            height: state.height,
+            header: state.header,
+            tx_index,
+            consensus: consensus.clone(),
+            cancellation,
+        }
+    }
+
+    /// Identifies the connection the request came in on. Connections are numbered in the order
+    /// they were accepted, starting at zero.
+    pub fn connection_id(&self) -> usize {
+        self.connection_id
+    }
+
+    /// The role the connection has been classified as, or `None` while only `echo` and `flush`
+    /// have been seen on it.
+    pub fn role(&self) -> Option<ConnectionRole> {
+        self.role
+    }
+
+    /// The ABCI method of the request.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The consensus phase, including the effect of the current request when it is a consensus
+    /// one (e.g. `InBlock` inside `begin_block`).
+    pub fn phase(&self) -> ConsensusPhase {
+        self.phase
+    }
+
+    /// The height of the block in progress, or of the last committed block between blocks.
+    pub fn height(&self) -> i64 {
+        self.height
+    }
+
+    /// The header passed to the latest `begin_block`.
+    pub fn header(&self) -> Option<&BlockHeader> {
+        self.header.as_deref()
+    }
+
+    /// Position of the transaction within its block, for `deliver_tx` requests.
+    pub fn tx_index(&self) -> Option<usize> {
+        self.tx_index
+    }
+
+    /// The server's consensus tracker, for applications that want to read the current phase and
+    /// height outside callbacks.
+    pub fn consensus(&self) -> &ConsensusContext {
+        &self.consensus
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Shorthand for `self.cancellation().is_cancelled()`.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::OrderingPolicy;
+
+    fn begin_block(chain_id: &str, height: i64) -> RequestSummary {
+        let header = BlockHeader {
+            chain_id: chain_id.to_string(),
+            height,
+            ..BlockHeader::default()
+        };
+        RequestSummary {
+            header: Some(header),
+            ..RequestSummary::new(Method::BeginBlock)
+        }
+    }
+
+    fn deliver_tx() -> RequestSummary {
+        RequestSummary::new(Method::DeliverTx)
+    }
+
+    #[test]
+    fn should_carry_block_position() {
+        let consensus = ConsensusContext::new();
+        let role = Some(ConnectionRole::Consensus);
+        let request = begin_block("test-chain", 3);
+        consensus.advance(&request, OrderingPolicy::Off).unwrap();
+        let ctx = Context::new(1, role, &consensus, &request, CancellationToken::new());
+        assert_eq!(ctx.header().unwrap().chain_id, "test-chain");
+        assert_eq!(ctx.height(), 3);
+        assert_eq!(ctx.tx_index(), None);
+
+        for index in 0..3 {
+            let request = deliver_tx();
+            consensus.advance(&request, OrderingPolicy::Off).unwrap();
+            let ctx = Context::new(1, role, &consensus, &request, CancellationToken::new());
+            assert_eq!(ctx.tx_index(), Some(index));
+            assert_eq!(ctx.phase(), ConsensusPhase::InBlock);
+        }
+
+        let request = begin_block("test-chain", 4);
+        consensus.advance(&request, OrderingPolicy::Off).unwrap();
+        let request = deliver_tx();
+        consensus.advance(&request, OrderingPolicy::Off).unwrap();
+        let ctx = Context::new(1, role, &consensus, &request, CancellationToken::new());
+        assert_eq!(ctx.tx_index(), Some(0));
+        assert_eq!(ctx.header().unwrap().height, 4);
+    }
+
+    #[test]
+    fn should_share_cancellation() {
+        let token = CancellationToken::new();
+        let ctx = Context::new(
+            0,
+            None,
+            &ConsensusContext::new(),
+            &RequestSummary::new(Method::Echo),
+            token.clone(),
+        );
+        assert!(!ctx.is_cancelled());
+        token.cancel();
+        assert!(ctx.is_cancelled());
+    }
+}
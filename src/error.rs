@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::messages::abci::*;
+
+/// An application error that Tendermint can report to clients. Returning one from `check_tx`,
+/// `deliver_tx` or `query` makes the server fill in the response's `code`, `codespace` and `log`.
+pub trait AbciError: fmt::Display + fmt::Debug {
+    /// The response code. Zero means success in ABCI, so errors must use a non-zero code; a zero
+    /// code is reported as `CODE_TYPE_INTERNAL`.
+    fn code(&self) -> u32;
+
+    /// Namespace for `code`, so different modules of an application can reuse code numbers.
+    fn codespace(&self) -> &str {
+        ""
+    }
+
+    /// Text for the response's `log` field. Defaults to the `Display` output.
+    fn log(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<E: AbciError + 'static> From<E> for Box<dyn AbciError> {
+    fn from(error: E) -> Box<dyn AbciError> {
+        Box::new(error)
+    }
+}
+
+/// The code responses get for errors whose `code()` is zero, as Tendermint would otherwise take
+/// them for a success.
+pub const CODE_TYPE_INTERNAL: u32 = 1;
+
+/// Responses that carry the `code`, `codespace` and `log` of an `AbciError`.
+pub trait ErrorResponse {
+    /// A response for `error`. A zero code becomes `CODE_TYPE_INTERNAL`.
+    fn from_error(error: &dyn AbciError) -> Self;
+
+    /// The response's `code`, `codespace` and `log`.
+    fn error_parts(&self) -> (u32, &str, &str);
+}
+
+macro_rules! error_responses {
+    ($($response:ident),*) => {
+        $(
+            impl ErrorResponse for $response {
+                fn from_error(error: &dyn AbciError) -> $response {
+                    let mut response = $response::new();
+                    response.set_code(error_code(error));
+                    response.set_codespace(error.codespace().to_string());
+                    response.set_log(error.log());
+                    response
+                }
+
+                fn error_parts(&self) -> (u32, &str, &str) {
+                    (self.get_code(), self.get_codespace(), self.get_log())
+                }
+            }
+        )*
+    };
+}
+
+error_responses!(ResponseCheckTx, ResponseDeliverTx, ResponseQuery);
+
+/// The code of `error`, or `CODE_TYPE_INTERNAL` if it wrongly claims success with code zero.
+pub(crate) fn error_code(error: &dyn AbciError) -> u32 {
+    match error.code() {
+        0 => CODE_TYPE_INTERNAL,
+        code => code,
+    }
+}
+
+/// An error code registered under a codespace, similar to the Cosmos SDK's `errors.Register`.
+/// Registered errors are plain values, so applications usually create them once at startup and
+/// return clones (or `wrap`ped versions) from their callbacks.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RegisteredError {
+    codespace: String,
+    code: u32,
+    description: String,
+}
+
+impl RegisteredError {
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Adds context to the error. The log becomes `"<context>: <description>"`.
+    pub fn wrap<S: Into<String>>(&self, context: S) -> WrappedError {
+        WrappedError {
+            error: self.clone(),
+            context: context.into(),
+        }
+    }
+}
+
+impl fmt::Display for RegisteredError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl Error for RegisteredError {}
+
+impl AbciError for RegisteredError {
+    fn code(&self) -> u32 {
+        self.code
+    }
+
+    fn codespace(&self) -> &str {
+        &self.codespace
+    }
+}
+
+/// A `RegisteredError` with extra context, see `RegisteredError::wrap`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WrappedError {
+    error: RegisteredError,
+    context: String,
+}
+
+impl WrappedError {
+    /// The registered error this one wraps.
+    pub fn registered(&self) -> &RegisteredError {
+        &self.error
+    }
+}
+
+impl fmt::Display for WrappedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+impl Error for WrappedError {}
+
+impl AbciError for WrappedError {
+    fn code(&self) -> u32 {
+        self.error.code
+    }
+
+    fn codespace(&self) -> &str {
+        &self.error.codespace
+    }
+}
+
+/// Why an error code could not be registered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    /// Code zero means success and cannot be registered.
+    ReservedCode { codespace: String },
+    /// The codespace already has an error with this code.
+    Duplicate(RegisteredError),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::ReservedCode { codespace } => {
+                write!(
+                    f,
+                    "code 0 is reserved for success in codespace {:?}",
+                    codespace
+                )
+            }
+            RegistryError::Duplicate(existing) => write!(
+                f,
+                "code {} is already registered in codespace {:?} as {:?}",
+                existing.code, existing.codespace, existing.description
+            ),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+/// An error read back from a response, see `Registry::parse`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResponseError {
+    /// The code is registered. `log` is the log of the response, which includes any context the
+    /// error was wrapped with.
+    Registered { error: RegisteredError, log: String },
+    /// The code is not in the registry.
+    Unregistered {
+        codespace: String,
+        code: u32,
+        log: String,
+    },
+}
+
+impl ResponseError {
+    /// Whether this is the given registered error, with or without wrapping.
+    pub fn is(&self, registered: &RegisteredError) -> bool {
+        match self {
+            ResponseError::Registered { error, .. } => error == registered,
+            ResponseError::Unregistered { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.log())
+    }
+}
+
+impl Error for ResponseError {}
+
+impl AbciError for ResponseError {
+    fn code(&self) -> u32 {
+        match self {
+            ResponseError::Registered { error, .. } => error.code,
+            ResponseError::Unregistered { code, .. } => *code,
+        }
+    }
+
+    fn codespace(&self) -> &str {
+        match self {
+            ResponseError::Registered { error, .. } => &error.codespace,
+            ResponseError::Unregistered { codespace, .. } => codespace,
+        }
+    }
+
+    fn log(&self) -> String {
+        match self {
+            ResponseError::Registered { log, .. } | ResponseError::Unregistered { log, .. } => {
+                log.clone()
+            }
+        }
+    }
+}
+
+/// Error codes known to an application, grouped by codespace. Used on the application side to
+/// define errors without clashing codes, and on the client side to turn the `code` and
+/// `codespace` of a response back into the error that produced it.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    errors: HashMap<(String, u32), RegisteredError>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Registers `code` in `codespace`. Fails if the code is zero or already taken.
+    pub fn register(
+        &mut self,
+        codespace: &str,
+        code: u32,
+        description: &str,
+    ) -> Result<RegisteredError, RegistryError> {
+        if code == 0 {
+            return Err(RegistryError::ReservedCode {
+                codespace: codespace.to_string(),
+            });
+        }
+        let key = (codespace.to_string(), code);
+        if let Some(existing) = self.errors.get(&key) {
+            return Err(RegistryError::Duplicate(existing.clone()));
+        }
+        let error = RegisteredError {
+            codespace: codespace.to_string(),
+            code,
+            description: description.to_string(),
+        };
+        self.errors.insert(key, error.clone());
+        Ok(error)
+    }
+
+    pub fn lookup(&self, codespace: &str, code: u32) -> Option<&RegisteredError> {
+        self.errors.get(&(codespace.to_string(), code))
+    }
+
+    /// Reads the error out of a `ResponseCheckTx`, `ResponseDeliverTx` or `ResponseQuery`.
+    /// Successful responses (code zero) give `Ok(())`.
+    pub fn parse<R: ErrorResponse>(&self, response: &R) -> Result<(), ResponseError> {
+        let (code, codespace, log) = response.error_parts();
+        if code == 0 {
+            return Ok(());
+        }
+        Err(match self.lookup(codespace, code) {
+            Some(error) => ResponseError::Registered {
+                error: error.clone(),
+                log: log.to_string(),
+            },
+            None => ResponseError::Unregistered {
+                codespace: codespace.to_string(),
+                code,
+                log: log.to_string(),
+            },
+        })
+    }
+}
+
+/// Unwraps a callback result, turning an error into the matching response.
+pub(crate) fn into_response<T>(result: Result<T, Box<dyn AbciError>>) -> T
+where
+    T: ErrorResponse,
+{
+    result.unwrap_or_else(|error| {
+        if error.code() == 0 {
+            warn!(
+                "Application returned an error with code 0, reported with code {}: {:?}",
+                CODE_TYPE_INTERNAL, error
+            );
+        } else {
+            debug!("Application returned an error: {:?}", error);
+        }
+        T::from_error(&*error)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct InsufficientFunds(u64);
+
+    impl fmt::Display for InsufficientFunds {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "insufficient funds: need {}", self.0)
+        }
+    }
+
+    impl AbciError for InsufficientFunds {
+        fn code(&self) -> u32 {
+            5
+        }
+
+        fn codespace(&self) -> &str {
+            "bank"
+        }
+    }
+
+    fn withdraw(amount: u64) -> Result<ResponseDeliverTx, Box<dyn AbciError>> {
+        Err(InsufficientFunds(amount).into())
+    }
+
+    #[test]
+    fn should_fill_response_from_error() {
+        let response = into_response(withdraw(10));
+        assert_eq!(response.get_code(), 5);
+        assert_eq!(response.get_codespace(), "bank");
+        assert_eq!(response.get_log(), "insufficient funds: need 10");
+
+        let response = ResponseQuery::from_error(&InsufficientFunds(3));
+        assert_eq!(response.get_code(), 5);
+        assert_eq!(response.get_codespace(), "bank");
+    }
+
+    #[derive(Debug)]
+    struct Misconfigured;
+
+    impl fmt::Display for Misconfigured {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "misconfigured")
+        }
+    }
+
+    impl AbciError for Misconfigured {
+        fn code(&self) -> u32 {
+            0
+        }
+    }
+
+    #[test]
+    fn should_never_report_errors_as_success() {
+        let response = ResponseCheckTx::from_error(&Misconfigured);
+        assert_eq!(response.get_code(), CODE_TYPE_INTERNAL);
+        assert_eq!(response.get_log(), "misconfigured");
+        assert_eq!(
+            ResponseDeliverTx::from_error(&Misconfigured).get_code(),
+            CODE_TYPE_INTERNAL
+        );
+        let response = into_response::<ResponseQuery>(Err(Misconfigured.into()));
+        assert_eq!(response.get_code(), CODE_TYPE_INTERNAL);
+    }
+
+    #[test]
+    fn should_reject_duplicate_and_reserved_codes() {
+        let mut registry = Registry::new();
+        let funds = registry.register("bank", 5, "insufficient funds").unwrap();
+        registry.register("staking", 5, "no delegation").unwrap();
+        assert_eq!(
+            registry.register("bank", 5, "account frozen"),
+            Err(RegistryError::Duplicate(funds.clone()))
+        );
+        assert_eq!(
+            registry.register("bank", 0, "ok"),
+            Err(RegistryError::ReservedCode {
+                codespace: String::from("bank")
+            })
+        );
+        assert_eq!(registry.lookup("bank", 5), Some(&funds));
+        assert_eq!(registry.lookup("bank", 6), None);
+    }
+
+    #[test]
+    fn should_round_trip_through_response() {
+        let mut registry = Registry::new();
+        let funds = registry.register("bank", 5, "insufficient funds").unwrap();
+        let frozen = registry.register("bank", 6, "account frozen").unwrap();
+
+        let response = ResponseDeliverTx::from_error(&funds.wrap("need 10atom"));
+        assert_eq!(response.get_log(), "need 10atom: insufficient funds");
+        let error = registry.parse(&response).unwrap_err();
+        assert!(error.is(&funds));
+        assert!(!error.is(&frozen));
+        assert_eq!(error.log(), "need 10atom: insufficient funds");
+
+        let mut response = ResponseCheckTx::new();
+        response.set_code(2);
+        response.set_codespace(String::from("sdk"));
+        assert_eq!(
+            registry.parse(&response),
+            Err(ResponseError::Unregistered {
+                codespace: String::from("sdk"),
+                code: 2,
+                log: String::new(),
+            })
+        );
+        assert_eq!(registry.parse(&ResponseQuery::new()), Ok(()));
+    }
+
+    #[test]
+    fn should_pass_through_success() {
+        let mut ok = ResponseCheckTx::new();
+        ok.set_gas_wanted(7);
+        let response = into_response::<ResponseCheckTx>(Ok(ok.clone()));
+        assert_eq!(response, ok);
+    }
+}
@@ -0,0 +1,236 @@
+//! Building events for `ResponseDeliverTx`, `ResponseCheckTx`, `ResponseBeginBlock` and
+//! `ResponseEndBlock`.
+//!
+//! ```rust
+//! use abci::events::EventSink;
+//! use abci::types::Event;
+//!
+//! let mut response = abci::ResponseDeliverTx::new();
+//! response
+//!     .push_event(Event::new("transfer").attr("sender", "alice").attr("amount", 10u64))
+//!     .unwrap();
+//! assert_eq!(response.get_events()[0].get_attributes()[1].get_value(), b"10");
+//! ```
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::messages::abci as wire;
+use crate::types::{Attribute, Event};
+
+/// A value that can be stored in an event attribute. Strings and byte slices are stored as is,
+/// numbers, `bool` and `char` in their `Display` form.
+///
+/// `Event::attr` takes this trait rather than any `Display` or `AsRef<[u8]>` value, because
+/// blanket impls for both would overlap on `str` and `String`, and the compiler also rejects a
+/// blanket `Display` impl next to the one for `Vec<u8>`. Other `Display` types go through
+/// `Event::attr_display`, other byte containers through `as_ref()`.
+pub trait AttributeValue {
+    fn into_bytes(self) -> Vec<u8>;
+}
+
+impl AttributeValue for Vec<u8> {
+    fn into_bytes(self) -> Vec<u8> {
+        self
+    }
+}
+
+impl AttributeValue for &Vec<u8> {
+    fn into_bytes(self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl AttributeValue for &[u8] {
+    fn into_bytes(self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl AttributeValue for String {
+    fn into_bytes(self) -> Vec<u8> {
+        self.into_bytes()
+    }
+}
+
+impl AttributeValue for &String {
+    fn into_bytes(self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl AttributeValue for &str {
+    fn into_bytes(self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+macro_rules! display_values {
+    ($($ty:ty),*) => {
+        $(
+            impl AttributeValue for $ty {
+                fn into_bytes(self) -> Vec<u8> {
+                    self.to_string().into_bytes()
+                }
+            }
+        )*
+    };
+}
+
+display_values!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool, char);
+
+/// Why an event was refused.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventError {
+    /// The event has an empty type, which Tendermint cannot index.
+    EmptyType,
+    /// A key appears more than once in an event that requires unique keys.
+    DuplicateKey { kind: String, key: Vec<u8> },
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventError::EmptyType => write!(f, "event type must not be empty"),
+            EventError::DuplicateKey { kind, key } => write!(
+                f,
+                "duplicate key {:?} in {} event",
+                String::from_utf8_lossy(key),
+                kind
+            ),
+        }
+    }
+}
+
+impl Error for EventError {}
+
+impl Event {
+    pub fn new<S: Into<String>>(kind: S) -> Event {
+        Event {
+            kind: kind.into(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Appends an attribute.
+    pub fn attr<K: AsRef<[u8]>, V: AttributeValue>(mut self, key: K, value: V) -> Event {
+        self.attributes.push(Attribute {
+            key: key.as_ref().to_vec(),
+            value: value.into_bytes(),
+        });
+        self
+    }
+
+    /// Appends an attribute holding the `Display` form of `value`, for types that do not
+    /// implement `AttributeValue`.
+    pub fn attr_display<K: AsRef<[u8]>, V: fmt::Display>(self, key: K, value: V) -> Event {
+        self.attr(key, value.to_string())
+    }
+
+    /// Checks that no key appears twice, for events whose consumers look attributes up by key.
+    pub fn ensure_unique_keys(self) -> Result<Event, EventError> {
+        let mut seen = HashSet::new();
+        for attribute in &self.attributes {
+            if !seen.insert(&attribute.key) {
+                return Err(EventError::DuplicateKey {
+                    kind: self.kind.clone(),
+                    key: attribute.key.clone(),
+                });
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Responses that carry events.
+pub trait EventSink {
+    /// Appends `event` to the response. Fails if the event type is empty.
+    fn push_event(&mut self, event: Event) -> Result<(), EventError>;
+
+    /// Builder style version of `push_event`.
+    fn with_event(mut self, event: Event) -> Result<Self, EventError>
+    where
+        Self: Sized,
+    {
+        self.push_event(event)?;
+        Ok(self)
+    }
+}
+
+macro_rules! event_sinks {
+    ($($response:ident),*) => {
+        $(
+            impl EventSink for wire::$response {
+                fn push_event(&mut self, event: Event) -> Result<(), EventError> {
+                    if event.kind.is_empty() {
+                        return Err(EventError::EmptyType);
+                    }
+                    self.mut_events().push(event.into());
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+event_sinks!(
+    ResponseCheckTx,
+    ResponseDeliverTx,
+    ResponseBeginBlock,
+    ResponseEndBlock
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_encode_attribute_values() {
+        let address = vec![0xAB, 0xCD];
+        let event = Event::new("transfer")
+            .attr("sender", &address)
+            .attr("recipient", String::from("bob"))
+            .attr("amount", 10u64)
+            .attr(b"negative", -3i32)
+            .attr("ok", true)
+            .attr_display("height", std::num::NonZeroU64::new(7).unwrap());
+        let values: Vec<&[u8]> = event.attributes.iter().map(|a| &a.value[..]).collect();
+        assert_eq!(
+            values,
+            vec![&[0xAB, 0xCD][..], b"bob", b"10", b"-3", b"true", b"7"]
+        );
+        assert_eq!(event.attributes[3].key, b"negative");
+    }
+
+    #[test]
+    fn should_reject_duplicate_keys_when_asked() {
+        let event = Event::new("transfer")
+            .attr("amount", 1u8)
+            .attr("amount", 2u8);
+        assert_eq!(
+            event.clone().ensure_unique_keys(),
+            Err(EventError::DuplicateKey {
+                kind: String::from("transfer"),
+                key: b"amount".to_vec(),
+            })
+        );
+        let mut response = wire::ResponseEndBlock::new();
+        response.push_event(event).unwrap();
+        assert_eq!(response.get_events()[0].get_attributes().len(), 2);
+    }
+
+    #[test]
+    fn should_reject_empty_types() {
+        let mut response = wire::ResponseBeginBlock::new();
+        assert_eq!(
+            response.push_event(Event::new("").attr("key", "value")),
+            Err(EventError::EmptyType)
+        );
+        assert!(response.get_events().is_empty());
+
+        let response = wire::ResponseDeliverTx::new()
+            .with_event(Event::new("message").attr("action", "send"))
+            .unwrap();
+        assert_eq!(response.get_events()[0].get_field_type(), "message");
+    }
+}
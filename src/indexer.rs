@@ -0,0 +1,480 @@
+//! An in-process index of the events returned by `begin_block`, `deliver_tx` and `end_block`,
+//! searchable with the event query language. It lets an application answer historic queries
+//! from its own `query` callback without relying on Tendermint's indexer.
+//!
+//! Wrap the application in `Indexed` to record every block as the server dispatches it, and keep
+//! a handle to the same indexer in the application:
+//!
+//! ```rust,no_run
+//! use std::sync::{Arc, Mutex};
+//! use abci::indexer::{EventIndexer, Indexed};
+//!
+//! struct App {
+//!     indexer: Arc<Mutex<EventIndexer>>,
+//! }
+//!
+//! impl abci::Application for App {}
+//!
+//! let indexer = Arc::new(Mutex::new(EventIndexer::new()));
+//! let app = App { indexer: indexer.clone() };
+//! abci::run_local(Indexed::new(app, indexer));
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::DerefMut;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::error::into_response;
+use crate::messages::abci as wire;
+use crate::query::{Attributes, Operand, Operator, Query};
+use crate::{AbciError, Application, CheckTxHandler, ConnectionRole, Context};
+
+/// A delivered transaction and the application's response to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxRecord {
+    pub height: i64,
+    /// Position of the transaction within its block.
+    pub index: u32,
+    pub tx: Vec<u8>,
+    pub result: wire::ResponseDeliverTx,
+}
+
+/// The events of a block, from `begin_block` and `end_block`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockRecord {
+    pub height: i64,
+    pub begin_block: Vec<wire::Event>,
+    pub end_block: Vec<wire::Event>,
+}
+
+/// Stores transactions by height and index, and blocks by height, along with a
+/// `type.key=value` index of their events.
+///
+/// Transactions are searchable by their events plus `tm.event = 'Tx'` and `tx.height`, blocks by
+/// their events plus `tm.event = 'NewBlock'` and `block.height`. Indexing a position again
+/// replaces what was stored there, so replayed blocks do not show up twice.
+#[derive(Debug, Default)]
+pub struct EventIndexer {
+    txs: BTreeMap<(i64, u32), (TxRecord, Attributes)>,
+    blocks: BTreeMap<i64, (BlockRecord, Attributes)>,
+    tx_values: BTreeMap<(String, String), BTreeSet<(i64, u32)>>,
+    block_values: BTreeMap<(String, String), BTreeSet<i64>>,
+}
+
+impl EventIndexer {
+    pub fn new() -> EventIndexer {
+        EventIndexer::default()
+    }
+
+    pub fn index_begin_block(&mut self, height: i64, response: &wire::ResponseBeginBlock) {
+        let mut block = self.remove_block(height).unwrap_or_default();
+        block.height = height;
+        block.begin_block = response.get_events().to_vec();
+        self.insert_block(block);
+    }
+
+    pub fn index_deliver_tx(
+        &mut self,
+        height: i64,
+        index: u32,
+        tx: &[u8],
+        response: &wire::ResponseDeliverTx,
+    ) {
+        self.remove_tx(height, index);
+        let mut attributes = Attributes::from_events(response.get_events());
+        attributes.insert("tm.event", "Tx");
+        attributes.insert("tx.height", height.to_string());
+        for (key, values) in attributes.iter() {
+            for value in values {
+                self.tx_values
+                    .entry((key.to_string(), value.clone()))
+                    .or_default()
+                    .insert((height, index));
+            }
+        }
+        let record = TxRecord {
+            height,
+            index,
+            tx: tx.to_vec(),
+            result: response.clone(),
+        };
+        self.txs.insert((height, index), (record, attributes));
+    }
+
+    pub fn index_end_block(&mut self, height: i64, response: &wire::ResponseEndBlock) {
+        let mut block = self.remove_block(height).unwrap_or_default();
+        block.height = height;
+        block.end_block = response.get_events().to_vec();
+        self.insert_block(block);
+    }
+
+    pub fn tx(&self, height: i64, index: u32) -> Option<&TxRecord> {
+        self.txs.get(&(height, index)).map(|(record, _)| record)
+    }
+
+    pub fn block(&self, height: i64) -> Option<&BlockRecord> {
+        self.blocks.get(&height).map(|(record, _)| record)
+    }
+
+    /// Transactions matching `query`, ordered by height and index.
+    pub fn search_txs(&self, query: &Query) -> Vec<&TxRecord> {
+        match candidates(&self.tx_values, query) {
+            Some(keys) => keys
+                .iter()
+                .filter_map(|key| self.txs.get(key).and_then(|entry| matching(entry, query)))
+                .collect(),
+            None => self
+                .txs
+                .values()
+                .filter_map(|entry| matching(entry, query))
+                .collect(),
+        }
+    }
+
+    /// Blocks matching `query`, ordered by height.
+    pub fn search_blocks(&self, query: &Query) -> Vec<&BlockRecord> {
+        match candidates(&self.block_values, query) {
+            Some(heights) => heights
+                .iter()
+                .filter_map(|height| {
+                    self.blocks
+                        .get(height)
+                        .and_then(|entry| matching(entry, query))
+                })
+                .collect(),
+            None => self
+                .blocks
+                .values()
+                .filter_map(|entry| matching(entry, query))
+                .collect(),
+        }
+    }
+
+    fn insert_block(&mut self, block: BlockRecord) {
+        let mut attributes =
+            Attributes::from_events(block.begin_block.iter().chain(&block.end_block));
+        attributes.insert("tm.event", "NewBlock");
+        attributes.insert("block.height", block.height.to_string());
+        for (key, values) in attributes.iter() {
+            for value in values {
+                self.block_values
+                    .entry((key.to_string(), value.clone()))
+                    .or_default()
+                    .insert(block.height);
+            }
+        }
+        self.blocks.insert(block.height, (block, attributes));
+    }
+
+    fn remove_block(&mut self, height: i64) -> Option<BlockRecord> {
+        let (block, attributes) = self.blocks.remove(&height)?;
+        unindex(&mut self.block_values, &attributes, &height);
+        Some(block)
+    }
+
+    fn remove_tx(&mut self, height: i64, index: u32) {
+        if let Some((_, attributes)) = self.txs.remove(&(height, index)) {
+            unindex(&mut self.tx_values, &attributes, &(height, index));
+        }
+    }
+}
+
+fn matching<'a, R>((record, attributes): &'a (R, Attributes), query: &Query) -> Option<&'a R> {
+    if query.matches(attributes) {
+        Some(record)
+    } else {
+        None
+    }
+}
+
+/// Positions that can match `query`, from the intersection of its `key = 'value'` conditions,
+/// or `None` if it has no such condition and every position has to be checked.
+fn candidates<T: Ord + Copy>(
+    values: &BTreeMap<(String, String), BTreeSet<T>>,
+    query: &Query,
+) -> Option<BTreeSet<T>> {
+    let mut result: Option<BTreeSet<T>> = None;
+    for condition in query.conditions() {
+        let value = match (condition.operator(), condition.operand()) {
+            (Operator::Eq, Some(Operand::String(value))) => value,
+            _ => continue,
+        };
+        let positions = values
+            .get(&(condition.key().to_string(), value.clone()))
+            .cloned()
+            .unwrap_or_default();
+        result = Some(match result {
+            Some(result) => result.intersection(&positions).copied().collect(),
+            None => positions,
+        });
+    }
+    result
+}
+
+fn unindex<T: Ord>(
+    values: &mut BTreeMap<(String, String), BTreeSet<T>>,
+    attributes: &Attributes,
+    position: &T,
+) {
+    for (key, key_values) in attributes.iter() {
+        for value in key_values {
+            let entry = (key.to_string(), value.clone());
+            if let Some(positions) = values.get_mut(&entry) {
+                positions.remove(position);
+                if positions.is_empty() {
+                    values.remove(&entry);
+                }
+            }
+        }
+    }
+}
+
+/// An application that records the responses of the wrapped one in an `EventIndexer`. Failed
+/// transactions are indexed too, with the code and log the server would send.
+pub struct Indexed<A> {
+    app: A,
+    indexer: Arc<Mutex<EventIndexer>>,
+}
+
+impl<A: Application> Indexed<A> {
+    pub fn new(app: A, indexer: Arc<Mutex<EventIndexer>>) -> Indexed<A> {
+        Indexed { app, indexer }
+    }
+
+    pub fn indexer(&self) -> &Arc<Mutex<EventIndexer>> {
+        &self.indexer
+    }
+
+    pub fn into_inner(self) -> A {
+        self.app
+    }
+
+    fn with_indexer<F: FnOnce(&mut EventIndexer)>(&self, update: F) {
+        let mut indexer = self.indexer.lock().unwrap_or_else(PoisonError::into_inner);
+        update(indexer.deref_mut());
+    }
+}
+
+impl<A: Application> Application for Indexed<A> {
+    fn connection_established(&mut self, role: ConnectionRole) {
+        self.app.connection_established(role)
+    }
+
+    fn info(&mut self, ctx: &Context, req: &wire::RequestInfo) -> wire::ResponseInfo {
+        self.app.info(ctx, req)
+    }
+
+    fn set_option(
+        &mut self,
+        ctx: &Context,
+        req: &wire::RequestSetOption,
+    ) -> wire::ResponseSetOption {
+        self.app.set_option(ctx, req)
+    }
+
+    fn query(
+        &mut self,
+        ctx: &Context,
+        req: &wire::RequestQuery,
+    ) -> Result<wire::ResponseQuery, Box<dyn AbciError>> {
+        self.app.query(ctx, req)
+    }
+
+    fn check_tx(
+        &mut self,
+        ctx: &Context,
+        req: &wire::RequestCheckTx,
+    ) -> Result<wire::ResponseCheckTx, Box<dyn AbciError>> {
+        self.app.check_tx(ctx, req)
+    }
+
+    fn check_tx_handler(&self) -> Option<Arc<dyn CheckTxHandler>> {
+        self.app.check_tx_handler()
+    }
+
+    fn init_chain(
+        &mut self,
+        ctx: &Context,
+        req: &wire::RequestInitChain,
+    ) -> wire::ResponseInitChain {
+        self.app.init_chain(ctx, req)
+    }
+
+    fn begin_block(
+        &mut self,
+        ctx: &Context,
+        req: &wire::RequestBeginBlock,
+    ) -> wire::ResponseBeginBlock {
+        let response = self.app.begin_block(ctx, req);
+        self.with_indexer(|indexer| indexer.index_begin_block(ctx.height(), &response));
+        response
+    }
+
+    fn deliver_tx(
+        &mut self,
+        ctx: &Context,
+        req: &wire::RequestDeliverTx,
+    ) -> Result<wire::ResponseDeliverTx, Box<dyn AbciError>> {
+        let response = into_response(self.app.deliver_tx(ctx, req));
+        let index = ctx.tx_index().unwrap_or_default() as u32;
+        self.with_indexer(|indexer| {
+            indexer.index_deliver_tx(ctx.height(), index, req.get_tx(), &response)
+        });
+        Ok(response)
+    }
+
+    fn end_block(&mut self, ctx: &Context, req: &wire::RequestEndBlock) -> wire::ResponseEndBlock {
+        let response = self.app.end_block(ctx, req);
+        self.with_indexer(|indexer| indexer.index_end_block(ctx.height(), &response));
+        response
+    }
+
+    fn commit(&mut self, ctx: &Context, req: &wire::RequestCommit) -> wire::ResponseCommit {
+        self.app.commit(ctx, req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Backend, Protobuf};
+    use crate::consensus::{ConsensusContext, OrderingPolicy};
+    use crate::events::EventSink;
+    use crate::types::Event;
+    use crate::CancellationToken;
+
+    fn deliver_tx(sender: &str, amount: u64) -> wire::ResponseDeliverTx {
+        wire::ResponseDeliverTx::new()
+            .with_event(
+                Event::new("transfer")
+                    .attr("sender", sender)
+                    .attr("amount", amount),
+            )
+            .unwrap()
+    }
+
+    fn search_txs(indexer: &EventIndexer, query: &str) -> Vec<(i64, u32)> {
+        indexer
+            .search_txs(&query.parse().unwrap())
+            .iter()
+            .map(|record| (record.height, record.index))
+            .collect()
+    }
+
+    #[test]
+    fn should_search_indexed_txs() {
+        let mut indexer = EventIndexer::new();
+        indexer.index_deliver_tx(1, 0, b"a", &deliver_tx("alice", 10));
+        indexer.index_deliver_tx(1, 1, b"b", &deliver_tx("bob", 5));
+        indexer.index_deliver_tx(2, 0, b"c", &deliver_tx("alice", 3));
+
+        assert_eq!(
+            search_txs(&indexer, "transfer.sender = 'alice'"),
+            vec![(1, 0), (2, 0)]
+        );
+        assert_eq!(
+            search_txs(&indexer, "transfer.sender = 'alice' AND tx.height > 1"),
+            vec![(2, 0)]
+        );
+        assert_eq!(
+            search_txs(&indexer, "tm.event = 'Tx' AND transfer.amount >= 5"),
+            vec![(1, 0), (1, 1)]
+        );
+        assert!(search_txs(&indexer, "transfer.sender = 'carol'").is_empty());
+        assert_eq!(indexer.tx(1, 1).unwrap().tx, b"b");
+
+        indexer.index_deliver_tx(1, 0, b"a", &deliver_tx("carol", 10));
+        assert_eq!(
+            search_txs(&indexer, "transfer.sender = 'alice'"),
+            vec![(2, 0)]
+        );
+        assert_eq!(
+            search_txs(&indexer, "transfer.sender = 'carol'"),
+            vec![(1, 0)]
+        );
+    }
+
+    #[test]
+    fn should_search_indexed_blocks() {
+        let mut indexer = EventIndexer::new();
+        for height in 1..=3 {
+            let begin_block = wire::ResponseBeginBlock::new()
+                .with_event(Event::new("rewards").attr("validator", "val1"))
+                .unwrap();
+            indexer.index_begin_block(height, &begin_block);
+            let end_block = wire::ResponseEndBlock::new()
+                .with_event(Event::new("inflation").attr("rate", height))
+                .unwrap();
+            indexer.index_end_block(height, &end_block);
+        }
+
+        let heights = |query: &str| -> Vec<i64> {
+            indexer
+                .search_blocks(&query.parse().unwrap())
+                .iter()
+                .map(|block| block.height)
+                .collect()
+        };
+        assert_eq!(
+            heights("rewards.validator = 'val1' AND inflation.rate >= 2"),
+            vec![2, 3]
+        );
+        assert_eq!(heights("block.height = 1"), vec![1]);
+        assert_eq!(indexer.block(3).unwrap().end_block.len(), 1);
+    }
+
+    struct TransferApp;
+
+    impl Application for TransferApp {
+        fn deliver_tx(
+            &mut self,
+            _ctx: &Context,
+            req: &wire::RequestDeliverTx,
+        ) -> Result<wire::ResponseDeliverTx, Box<dyn AbciError>> {
+            if req.get_tx().is_empty() {
+                let error = crate::Registry::new()
+                    .register("transfer", 1, "empty transaction")
+                    .unwrap();
+                return Err(Box::new(error));
+            }
+            Ok(deliver_tx(&String::from_utf8_lossy(req.get_tx()), 1))
+        }
+    }
+
+    #[test]
+    fn should_index_blocks_as_they_are_dispatched() {
+        let indexer = Arc::new(Mutex::new(EventIndexer::new()));
+        let mut app = Indexed::new(TransferApp, indexer.clone());
+        let consensus = ConsensusContext::new();
+        let mut dispatch = |request: wire::Request| {
+            let summary = Protobuf::summarize(&request);
+            consensus.advance(&summary, OrderingPolicy::Off).unwrap();
+            let ctx = Context::new(0, None, &consensus, &summary, CancellationToken::new());
+            match request.value {
+                Some(wire::Request_oneof_value::begin_block(ref r)) => {
+                    app.begin_block(&ctx, r);
+                }
+                Some(wire::Request_oneof_value::deliver_tx(ref r)) => {
+                    app.deliver_tx(&ctx, r).unwrap();
+                }
+                _ => unreachable!(),
+            }
+        };
+
+        let mut request = wire::Request::new();
+        request.mut_begin_block().mut_header().set_height(4);
+        dispatch(request);
+        for tx in &[&b"alice"[..], b""] {
+            let mut request = wire::Request::new();
+            request.mut_deliver_tx().set_tx(tx.to_vec());
+            dispatch(request);
+        }
+
+        let indexer = indexer.lock().unwrap();
+        assert_eq!(
+            search_txs(&indexer, "transfer.sender = 'alice'"),
+            vec![(4, 0)]
+        );
+        assert_eq!(indexer.tx(4, 1).unwrap().result.get_code(), 1);
+        assert!(indexer.block(4).is_some());
+    }
+}
@@ -0,0 +1,687 @@
+//! Serde support for the ABCI messages, enabled by the `serde` feature.
+//!
+//! The encoding follows Tendermint's JSON output, which is go-amino's: fields are named as in the
+//! protobuf definitions and left out when empty, 64-bit integers are strings, bytes are base64,
+//! timestamps are RFC 3339 strings and durations are nanosecond counts. `Request` and `Response` are
+//! objects with a single key naming the method, e.g. `{"echo": {"message": "hello"}}`.
+//!
+//! Tendermint overrides this for `ResponseSetOption`, `ResponseCheckTx`, `ResponseDeliverTx`,
+//! `ResponseQuery` and `ResponseCommit`, which go through Go's jsonpb with defaults emitted: fields
+//! have their camel-case JSON names (`gasWanted`), and empty ones are written as `0`, `""`, `[]` or
+//! `null`. Either name is accepted when decoding.
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use protobuf::well_known_types::{Duration, Timestamp};
+use protobuf::{ProtobufEnum, RepeatedField, SingularPtrField};
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::messages::abci::*;
+use crate::messages::merkle::*;
+use crate::messages::types::*;
+use crate::time;
+
+/// How a field is written to and read from JSON.
+trait JsonField: Sized {
+    /// Whether the field is left out of the output, like Go's `omitempty`.
+    fn is_empty(&self) -> bool;
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    /// Writes the field as jsonpb does with `EmitDefaults`. Only bytes, lists and messages differ
+    /// from `to_json`.
+    fn to_jsonpb<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json(serializer)
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+struct Ser<'a, T>(&'a T);
+
+impl<'a, T: JsonField> Serialize for Ser<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.to_json(serializer)
+    }
+}
+
+struct Jsonpb<'a, T>(&'a T);
+
+impl<'a, T: JsonField> Serialize for Jsonpb<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.to_jsonpb(serializer)
+    }
+}
+
+struct De<T>(T);
+
+impl<'de, T: JsonField> Deserialize<'de> for De<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<De<T>, D::Error> {
+        T::from_json(deserializer).map(De)
+    }
+}
+
+macro_rules! plain_fields {
+    ($($ty:ty),*) => {
+        $(
+            impl JsonField for $ty {
+                fn is_empty(&self) -> bool {
+                    *self == <$ty>::default()
+                }
+
+                fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.serialize(serializer)
+                }
+
+                fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<$ty, D::Error> {
+                    <$ty>::deserialize(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+plain_fields!(bool, u32, i32, String);
+
+/// Reads an integer written either as a number or as a string.
+struct IntegerVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for IntegerVisitor<T>
+where
+    T: FromStr + TryFrom<i64> + TryFrom<u64>,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer, or a string holding one")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<T, E> {
+        T::try_from(value).map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<T, E> {
+        T::try_from(value).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        value
+            .parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+// Go's JSON encoding quotes 64-bit integers, which do not fit in a JavaScript number.
+macro_rules! quoted_fields {
+    ($($ty:ty),*) => {
+        $(
+            impl JsonField for $ty {
+                fn is_empty(&self) -> bool {
+                    *self == 0
+                }
+
+                fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+
+                fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<$ty, D::Error> {
+                    deserializer.deserialize_any(IntegerVisitor(PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+quoted_fields!(i64, u64);
+
+impl JsonField for Vec<u8> {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(self))
+    }
+
+    // Go decodes empty bytes to a nil slice, which encoding/json writes as `null`.
+    fn to_jsonpb<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_empty() {
+            serializer.serialize_none()
+        } else {
+            self.to_json(serializer)
+        }
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(encoded) => base64::decode(&encoded).map_err(de::Error::custom),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+impl<T: JsonField> JsonField for RepeatedField<T> {
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(Ser))
+    }
+
+    fn to_jsonpb<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(Jsonpb))
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Option::<Vec<De<T>>>::deserialize(deserializer)?;
+        Ok(items
+            .unwrap_or_default()
+            .into_iter()
+            .map(|De(item)| item)
+            .collect())
+    }
+}
+
+impl<T: JsonField> JsonField for SingularPtrField<T> {
+    fn is_empty(&self) -> bool {
+        self.is_none()
+    }
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.as_ref() {
+            Some(value) => value.to_json(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn to_jsonpb<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.as_ref() {
+            Some(value) => value.to_jsonpb(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Option::<De<T>>::deserialize(deserializer)?;
+        Ok(SingularPtrField::from_option(value.map(|De(value)| value)))
+    }
+}
+
+impl JsonField for CheckTxType {
+    fn is_empty(&self) -> bool {
+        *self == CheckTxType::New
+    }
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.value())
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CheckTxType, D::Error> {
+        let value = i32::deserialize(deserializer)?;
+        CheckTxType::from_i32(value).ok_or_else(|| {
+            de::Error::invalid_value(de::Unexpected::Signed(value.into()), &"a CheckTxType")
+        })
+    }
+}
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+impl JsonField for Timestamp {
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time::format_rfc3339(self))
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        time::parse_rfc3339(&text).ok_or_else(|| {
+            de::Error::invalid_value(de::Unexpected::Str(&text), &"an RFC 3339 timestamp")
+        })
+    }
+}
+
+impl JsonField for Duration {
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let nanos = self
+            .get_seconds()
+            .checked_mul(NANOS_PER_SECOND)
+            .and_then(|nanos| nanos.checked_add(i64::from(self.get_nanos())))
+            .ok_or_else(|| serde::ser::Error::custom("duration overflows i64 nanoseconds"))?;
+        nanos.to_json(serializer)
+    }
+
+    fn from_json<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let nanos = i64::from_json(deserializer)?;
+        let mut duration = Duration::new();
+        duration.set_seconds(nanos / NANOS_PER_SECOND);
+        duration.set_nanos((nanos % NANOS_PER_SECOND) as i32);
+        Ok(duration)
+    }
+}
+
+/// The name protoc gives a field in JSON, which jsonpb writes: `gas_wanted` becomes `gasWanted`.
+fn json_name(name: &str) -> String {
+    let mut json = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            json.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            json.push(c);
+        }
+    }
+    json
+}
+
+macro_rules! json_messages {
+    (@serialize amino, $message:ident, $self:ident, $serializer:ident, $($field:ident: $name:literal),*) => {{
+        #[allow(unused_mut)]
+        let mut map = $serializer.serialize_map(None)?;
+        $(
+            if !JsonField::is_empty(&$self.$field) {
+                map.serialize_entry($name, &Ser(&$self.$field))?;
+            }
+        )*
+        map.end()
+    }};
+    (@serialize jsonpb, $message:ident, $self:ident, $serializer:ident, $($field:ident: $name:literal),*) => {
+        $self.to_jsonpb($serializer)
+    };
+    ($style:ident: $($message:ident { $($field:ident: $name:literal),* $(,)? })*) => {
+        $(
+            impl Serialize for $message {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    json_messages!(@serialize $style, $message, self, serializer, $($field: $name),*)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $message {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$message, D::Error> {
+                    struct MessageVisitor;
+
+                    impl<'de> Visitor<'de> for MessageVisitor {
+                        type Value = $message;
+
+                        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                            write!(f, "a {} object", stringify!($message))
+                        }
+
+                        fn visit_map<A: MapAccess<'de>>(
+                            self,
+                            mut map: A,
+                        ) -> Result<$message, A::Error> {
+                            #[allow(unused_mut)]
+                            let mut message = $message::new();
+                            while let Some(key) = map.next_key::<String>()? {
+                                match key.as_str() {
+                                    $(
+                                        key if key == $name || key == json_name($name) => {
+                                            message.$field = map.next_value::<De<_>>()?.0
+                                        }
+                                    )*
+                                    _ => {
+                                        map.next_value::<IgnoredAny>()?;
+                                    }
+                                }
+                            }
+                            Ok(message)
+                        }
+                    }
+
+                    deserializer.deserialize_map(MessageVisitor)
+                }
+            }
+
+            impl JsonField for $message {
+                fn is_empty(&self) -> bool {
+                    false
+                }
+
+                fn to_json<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.serialize(serializer)
+                }
+
+                fn to_jsonpb<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    #[allow(unused_mut)]
+                    let mut map = serializer.serialize_map(None)?;
+                    $(map.serialize_entry(&json_name($name), &Jsonpb(&self.$field))?;)*
+                    map.end()
+                }
+
+                fn from_json<'de, D: Deserializer<'de>>(
+                    deserializer: D,
+                ) -> Result<$message, D::Error> {
+                    $message::deserialize(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+json_messages! {
+    amino:
+    RequestEcho { message: "message" }
+    RequestFlush {}
+    RequestInfo { version: "version", block_version: "block_version" }
+    RequestSetOption { key: "key", value: "value" }
+    RequestInitChain {
+        time: "time",
+        chain_id: "chain_id",
+        consensus_params: "consensus_params",
+        validators: "validators",
+        app_state_bytes: "app_state_bytes",
+    }
+    RequestQuery { data: "data", path: "path", height: "height", prove: "prove" }
+    RequestBeginBlock {
+        hash: "hash",
+        header: "header",
+        last_commit_info: "last_commit_info",
+        byzantine_validators: "byzantine_validators",
+    }
+    RequestCheckTx { tx: "tx", field_type: "type" }
+    RequestDeliverTx { tx: "tx" }
+    RequestEndBlock { height: "height" }
+    RequestCommit {}
+    ResponseException { error: "error" }
+    ResponseEcho { message: "message" }
+    ResponseFlush {}
+    ResponseInfo {
+        data: "data",
+        version: "version",
+        app_version: "app_version",
+        last_block_height: "last_block_height",
+        last_block_app_hash: "last_block_app_hash",
+    }
+    ResponseInitChain { consensus_params: "consensus_params", validators: "validators" }
+    ResponseBeginBlock { events: "events" }
+    ResponseEndBlock {
+        validator_updates: "validator_updates",
+        consensus_param_updates: "consensus_param_updates",
+        events: "events",
+    }
+    ConsensusParams { block: "block", evidence: "evidence", validator: "validator" }
+    BlockParams { max_bytes: "max_bytes", max_gas: "max_gas" }
+    EvidenceParams {
+        max_age_num_blocks: "max_age_num_blocks",
+        max_age_duration: "max_age_duration",
+    }
+    ValidatorParams { pub_key_types: "pub_key_types" }
+    LastCommitInfo { round: "round", votes: "votes" }
+    Event { field_type: "type", attributes: "attributes" }
+    Header {
+        version: "version",
+        chain_id: "chain_id",
+        height: "height",
+        time: "time",
+        last_block_id: "last_block_id",
+        last_commit_hash: "last_commit_hash",
+        data_hash: "data_hash",
+        validators_hash: "validators_hash",
+        next_validators_hash: "next_validators_hash",
+        consensus_hash: "consensus_hash",
+        app_hash: "app_hash",
+        last_results_hash: "last_results_hash",
+        evidence_hash: "evidence_hash",
+        proposer_address: "proposer_address",
+    }
+    Version { Block: "Block", App: "App" }
+    BlockID { hash: "hash", parts_header: "parts_header" }
+    PartSetHeader { total: "total", hash: "hash" }
+    Validator { address: "address", power: "power" }
+    ValidatorUpdate { pub_key: "pub_key", power: "power" }
+    VoteInfo { validator: "validator", signed_last_block: "signed_last_block" }
+    PubKey { field_type: "type", data: "data" }
+    Evidence {
+        field_type: "type",
+        validator: "validator",
+        height: "height",
+        time: "time",
+        total_voting_power: "total_voting_power",
+    }
+    ProofOp { field_type: "type", key: "key", data: "data" }
+    Proof { ops: "ops" }
+    Pair { key: "key", value: "value" }
+}
+
+// Tendermint marshals these with jsonpb, to write out their defaults.
+json_messages! {
+    jsonpb:
+    ResponseSetOption { code: "code", log: "log", info: "info" }
+    ResponseQuery {
+        code: "code",
+        log: "log",
+        info: "info",
+        index: "index",
+        key: "key",
+        value: "value",
+        proof: "proof",
+        height: "height",
+        codespace: "codespace",
+    }
+    ResponseCheckTx {
+        code: "code",
+        data: "data",
+        log: "log",
+        info: "info",
+        gas_wanted: "gas_wanted",
+        gas_used: "gas_used",
+        events: "events",
+        codespace: "codespace",
+    }
+    ResponseDeliverTx {
+        code: "code",
+        data: "data",
+        log: "log",
+        info: "info",
+        gas_wanted: "gas_wanted",
+        gas_used: "gas_used",
+        events: "events",
+        codespace: "codespace",
+    }
+    ResponseCommit { data: "data", retain_height: "retain_height" }
+}
+
+macro_rules! json_oneofs {
+    ($($message:ident($oneof:ident) { $($variant:ident: $name:literal),* $(,)? })*) => {
+        $(
+            impl Serialize for $message {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let mut map = serializer.serialize_map(Some(self.value.is_some() as usize))?;
+                    match self.value {
+                        $(Some($oneof::$variant(ref value)) => map.serialize_entry($name, value)?,)*
+                        None => {}
+                    }
+                    map.end()
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $message {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$message, D::Error> {
+                    struct OneofVisitor;
+
+                    impl<'de> Visitor<'de> for OneofVisitor {
+                        type Value = $message;
+
+                        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                            write!(f, "a {} object", stringify!($message))
+                        }
+
+                        fn visit_map<A: MapAccess<'de>>(
+                            self,
+                            mut map: A,
+                        ) -> Result<$message, A::Error> {
+                            let mut message = $message::new();
+                            while let Some(key) = map.next_key::<String>()? {
+                                match key.as_str() {
+                                    $($name => message.value = Some($oneof::$variant(map.next_value()?)),)*
+                                    _ => {
+                                        map.next_value::<IgnoredAny>()?;
+                                    }
+                                }
+                            }
+                            Ok(message)
+                        }
+                    }
+
+                    deserializer.deserialize_map(OneofVisitor)
+                }
+            }
+        )*
+    };
+}
+
+json_oneofs! {
+    Request(Request_oneof_value) {
+        echo: "echo",
+        flush: "flush",
+        info: "info",
+        set_option: "set_option",
+        init_chain: "init_chain",
+        query: "query",
+        begin_block: "begin_block",
+        check_tx: "check_tx",
+        deliver_tx: "deliver_tx",
+        end_block: "end_block",
+        commit: "commit",
+    }
+    Response(Response_oneof_value) {
+        exception: "exception",
+        echo: "echo",
+        flush: "flush",
+        info: "info",
+        set_option: "set_option",
+        init_chain: "init_chain",
+        query: "query",
+        begin_block: "begin_block",
+        check_tx: "check_tx",
+        deliver_tx: "deliver_tx",
+        end_block: "end_block",
+        commit: "commit",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// Checks that `json` decodes, and that encoding the result gives back the same JSON.
+    fn round_trip<'a, T>(json: &'a str) -> T
+    where
+        T: Serialize + Deserialize<'a>,
+    {
+        let message: T = serde_json::from_str(json).unwrap();
+        let expected: Value = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_value(&message).unwrap(), expected);
+        message
+    }
+
+    // The fixtures are Tendermint's own encoding of the values in tests/fixtures/gen.
+    #[test]
+    fn should_round_trip_deliver_tx_fixture() {
+        let response: ResponseDeliverTx =
+            round_trip(include_str!("../tests/fixtures/response_deliver_tx.json"));
+        assert_eq!(response.get_gas_wanted(), 120_000);
+        assert_eq!(response.get_data(), b"ok");
+        let event = &response.get_events()[0];
+        assert_eq!(event.get_field_type(), "transfer");
+        assert_eq!(event.get_attributes()[0].get_key(), b"recipient");
+    }
+
+    #[test]
+    fn should_round_trip_begin_block_fixture() {
+        let request: RequestBeginBlock =
+            round_trip(include_str!("../tests/fixtures/request_begin_block.json"));
+        let header = request.get_header();
+        assert_eq!(header.get_version().get_Block(), 10);
+        assert_eq!(header.get_height(), 1_204);
+        assert_eq!(header.get_time().get_seconds(), 1_595_000_000);
+        assert_eq!(header.get_time().get_nanos(), 123_450_000);
+        assert!(request.get_last_commit_info().get_votes()[0].get_signed_last_block());
+        assert_eq!(
+            request.get_byzantine_validators()[0].get_field_type(),
+            "duplicate/vote"
+        );
+    }
+
+    #[test]
+    fn should_round_trip_end_block_fixture() {
+        let response: ResponseEndBlock =
+            round_trip(include_str!("../tests/fixtures/response_end_block.json"));
+        let params = response.get_consensus_param_updates();
+        assert_eq!(params.get_block().get_max_gas(), -1);
+        let duration = params.get_evidence().get_max_age_duration();
+        assert_eq!(duration.get_seconds(), 172_800);
+        assert_eq!(
+            response.get_validator_updates()[0]
+                .get_pub_key()
+                .get_field_type(),
+            "ed25519"
+        );
+    }
+
+    #[test]
+    fn should_round_trip_query_fixture() {
+        let response: ResponseQuery =
+            round_trip(include_str!("../tests/fixtures/response_query.json"));
+        assert_eq!(response.get_proof().get_ops()[0].get_field_type(), "iavl:v");
+        assert_eq!(response.get_height(), 1_204);
+    }
+
+    #[test]
+    fn should_write_defaults_like_jsonpb() {
+        let response: ResponseCheckTx = round_trip(
+            r#"{"code": 0, "data": null, "log": "", "info": "", "gasWanted": "0", "gasUsed": "0",
+                "events": [], "codespace": ""}"#,
+        );
+        assert_eq!(response, ResponseCheckTx::new());
+
+        let mut response = Response::new();
+        response.mut_commit().set_retain_height(3);
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"commit":{"data":null,"retainHeight":"3"}}"#
+        );
+    }
+
+    #[test]
+    fn should_key_requests_by_method() {
+        let request: Request = round_trip(r#"{"check_tx": {"tx": "AQI=", "type": 1}}"#);
+        assert_eq!(request.get_check_tx().get_tx(), &[1, 2]);
+        assert_eq!(
+            request.get_check_tx().get_field_type(),
+            CheckTxType::Recheck
+        );
+
+        let response: Response = round_trip(r#"{"flush": {}}"#);
+        assert!(response.has_flush());
+        assert_eq!(serde_json::to_string(&Response::new()).unwrap(), "{}");
+    }
+
+    #[test]
+    fn should_accept_numbers_and_offsets() {
+        let response: ResponseCheckTx =
+            serde_json::from_str(r#"{"gas_wanted": 10, "events": null, "extra": [1]}"#).unwrap();
+        assert_eq!(response.get_gas_wanted(), 10);
+
+        let evidence: Evidence =
+            serde_json::from_str(r#"{"time": "2020-07-17T17:33:20+02:00"}"#).unwrap();
+        assert_eq!(evidence.get_time().get_seconds(), 1_595_000_000);
+
+        let error = serde_json::from_str::<Evidence>(r#"{"time": "2020-02-30T00:00:00Z"}"#);
+        assert!(error.is_err());
+    }
+}
@@ -0,0 +1,721 @@
+//! A versioned, authenticated key-value store, enabled by the `kvstore` feature.
+//!
+//! The store is an IAVL tree, the AVL+ tree of Cosmos SDK stores. Each `commit` saves a version
+//! named after the block height, whose root hash is meant for `ResponseCommit.data`, and `prove`
+//! gives the `ProofOp` for `ResponseQuery.proof`: an `iavl:v` op for present keys and an `iavl:a`
+//! op for absent ones, as checked by `ProofRuntime::with_cosmos_ops`. A `RequestQuery.height` of
+//! zero asks for the latest version, `version()`.
+//!
+//! Versions share the nodes they have in common, and every node keeps its hash, so a commit only
+//! hashes the paths to the keys it changes. Every version stays in memory until it is deleted
+//! with `delete_version`; storage still keeps the changes of deleted versions.
+//!
+//! ```rust
+//! use abci::kvstore::KvStore;
+//! use abci::merkle::ProofRuntime;
+//!
+//! let mut store = KvStore::new();
+//! store.set(b"alice".to_vec(), b"10".to_vec());
+//! let root = store.commit(1).unwrap();
+//!
+//! let runtime = ProofRuntime::with_cosmos_ops();
+//! let mut proof = abci::Proof::new();
+//!
+//! let (value, op) = store.prove(b"alice", 1).unwrap();
+//! proof.mut_ops().push(op);
+//! runtime.verify_value(&proof, &root, "/alice", &value.unwrap()).unwrap();
+//!
+//! let (value, op) = store.prove(b"bob", 1).unwrap();
+//! proof.mut_ops()[0] = op;
+//! assert_eq!(value, None);
+//! runtime.verify_absence(&proof, &root, "/bob").unwrap();
+//! ```
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+
+use crate::merkle::iavl::{
+    IavlAbsenceOp, IavlValueOp, PathToLeaf, ProofInnerNode, ProofLeafNode, RangeProof,
+};
+use crate::merkle::simple::sha256;
+use crate::merkle::ProofOperator;
+use crate::messages::merkle::ProofOp;
+
+/// The writes of one version: the new value of each key, or `None` for removed keys.
+pub type ChangeSet = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Where a `KvStore` persists its versions, e.g. a file or an embedded database.
+pub trait Storage {
+    /// Persists the changes committed as `version`.
+    fn save_version(&mut self, version: i64, changes: &ChangeSet) -> io::Result<()>;
+
+    /// All saved versions, oldest first, including deleted ones: later versions are rebuilt
+    /// from their changes.
+    fn load_versions(&self) -> io::Result<Vec<(i64, ChangeSet)>>;
+
+    /// Records that `version` was deleted, so it is not loaded back.
+    fn delete_version(&mut self, version: i64) -> io::Result<()>;
+
+    /// The versions deleted so far.
+    fn deleted_versions(&self) -> io::Result<Vec<i64>>;
+}
+
+/// Keeps versions in memory only.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    versions: Vec<(i64, ChangeSet)>,
+    deleted: Vec<i64>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn save_version(&mut self, version: i64, changes: &ChangeSet) -> io::Result<()> {
+        self.versions.push((version, changes.clone()));
+        Ok(())
+    }
+
+    fn load_versions(&self) -> io::Result<Vec<(i64, ChangeSet)>> {
+        Ok(self.versions.clone())
+    }
+
+    fn delete_version(&mut self, version: i64) -> io::Result<()> {
+        self.deleted.push(version);
+        Ok(())
+    }
+
+    fn deleted_versions(&self) -> io::Result<Vec<i64>> {
+        Ok(self.deleted.clone())
+    }
+}
+
+/// A node of the tree. Inner nodes are keyed by the first key of their right subtree.
+#[derive(Debug)]
+struct Node {
+    key: Vec<u8>,
+    /// The version that created the node.
+    version: i64,
+    height: i8,
+    size: i64,
+    hash: Vec<u8>,
+    kind: NodeKind,
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Leaf { value: Vec<u8> },
+    Inner { left: Arc<Node>, right: Arc<Node> },
+}
+
+impl Node {
+    fn leaf(key: Vec<u8>, value: Vec<u8>, version: i64) -> Arc<Node> {
+        let mut node = Node {
+            key,
+            version,
+            height: 0,
+            size: 1,
+            hash: Vec::new(),
+            kind: NodeKind::Leaf { value },
+        };
+        node.hash = node.proof_leaf().hash();
+        Arc::new(node)
+    }
+
+    fn inner(key: Vec<u8>, version: i64, left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+        let mut node = Node {
+            key,
+            version,
+            height: left.height.max(right.height) + 1,
+            size: left.size + right.size,
+            hash: Vec::new(),
+            kind: NodeKind::Inner { left, right },
+        };
+        let (left, right) = node.children().unwrap();
+        let hash = node.proof_inner(None, Some(right)).hash(&left.hash);
+        node.hash = hash;
+        Arc::new(node)
+    }
+
+    fn children(&self) -> Option<(&Arc<Node>, &Arc<Node>)> {
+        match self.kind {
+            NodeKind::Leaf { .. } => None,
+            NodeKind::Inner {
+                ref left,
+                ref right,
+            } => Some((left, right)),
+        }
+    }
+
+    /// The value of a leaf.
+    fn value(&self) -> Option<&[u8]> {
+        match self.kind {
+            NodeKind::Leaf { ref value } => Some(value),
+            NodeKind::Inner { .. } => None,
+        }
+    }
+
+    fn balance(&self) -> i8 {
+        self.children()
+            .map_or(0, |(left, right)| left.height - right.height)
+    }
+
+    /// The node as it appears on a path to a leaf below the child that is not given.
+    fn proof_inner(&self, left: Option<&Node>, right: Option<&Node>) -> ProofInnerNode {
+        ProofInnerNode {
+            height: self.height,
+            size: self.size,
+            version: self.version,
+            left: left.map(|node| node.hash.clone()).unwrap_or_default(),
+            right: right.map(|node| node.hash.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn proof_leaf(&self) -> ProofLeafNode {
+        ProofLeafNode {
+            key: self.key.clone(),
+            value_hash: sha256(self.value().expect("not a leaf")),
+            version: self.version,
+        }
+    }
+}
+
+fn rotate_left(key: Vec<u8>, version: i64, left: Arc<Node>, right: &Node) -> Arc<Node> {
+    let (middle, right_right) = right.children().expect("rotating a leaf");
+    let left = Node::inner(key, version, left, middle.clone());
+    Node::inner(right.key.clone(), version, left, right_right.clone())
+}
+
+fn rotate_right(key: Vec<u8>, version: i64, left: &Node, right: Arc<Node>) -> Arc<Node> {
+    let (left_left, middle) = left.children().expect("rotating a leaf");
+    let right = Node::inner(key, version, middle.clone(), right);
+    Node::inner(left.key.clone(), version, left_left.clone(), right)
+}
+
+/// An inner node over `left` and `right`, rotated as IAVL does if their heights differ by more
+/// than one.
+fn balanced(key: Vec<u8>, version: i64, left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+    let balance = left.height - right.height;
+    if balance > 1 {
+        if left.balance() >= 0 {
+            return rotate_right(key, version, &left, right);
+        }
+        let (left_left, middle) = left.children().unwrap();
+        let left = rotate_left(left.key.clone(), version, left_left.clone(), middle);
+        rotate_right(key, version, &left, right)
+    } else if balance < -1 {
+        if right.balance() <= 0 {
+            return rotate_left(key, version, left, &right);
+        }
+        let (middle, right_right) = right.children().unwrap();
+        let right = rotate_right(right.key.clone(), version, middle, right_right.clone());
+        rotate_left(key, version, left, &right)
+    } else {
+        Node::inner(key, version, left, right)
+    }
+}
+
+/// The subtree `node` with `key` set to `value`.
+fn set(node: &Arc<Node>, key: Vec<u8>, value: Vec<u8>, version: i64) -> Arc<Node> {
+    match node.children() {
+        None => match key.cmp(&node.key) {
+            Ordering::Less => Node::inner(
+                node.key.clone(),
+                version,
+                Node::leaf(key, value, version),
+                node.clone(),
+            ),
+            Ordering::Greater => Node::inner(
+                key.clone(),
+                version,
+                node.clone(),
+                Node::leaf(key, value, version),
+            ),
+            Ordering::Equal => Node::leaf(key, value, version),
+        },
+        Some((left, right)) => {
+            if key < node.key {
+                let left = set(left, key, value, version);
+                balanced(node.key.clone(), version, left, right.clone())
+            } else {
+                let right = set(right, key, value, version);
+                balanced(node.key.clone(), version, left.clone(), right)
+            }
+        }
+    }
+}
+
+/// The node taking the place of a subtree a key was removed from, if any, and the new first key
+/// of the subtree if it changed.
+type Removal = (Option<Arc<Node>>, Option<Vec<u8>>);
+
+/// The subtree `node` without `key`, `None` if `key` is not in it.
+fn remove(node: &Arc<Node>, key: &[u8], version: i64) -> Option<Removal> {
+    let (left, right) = match node.children() {
+        None if node.key == key => return Some((None, None)),
+        None => return None,
+        Some(children) => children,
+    };
+    if key < &node.key[..] {
+        let (left, first_key) = remove(left, key, version)?;
+        Some(match left {
+            None => (Some(right.clone()), Some(node.key.clone())),
+            Some(left) => (
+                Some(balanced(node.key.clone(), version, left, right.clone())),
+                first_key,
+            ),
+        })
+    } else {
+        let (right, first_key) = remove(right, key, version)?;
+        Some(match right {
+            None => (Some(left.clone()), None),
+            Some(right) => {
+                let key = first_key.unwrap_or_else(|| node.key.clone());
+                (Some(balanced(key, version, left.clone(), right)), None)
+            }
+        })
+    }
+}
+
+/// The number of keys before `key` under `node`, and the leaf of `key` if it is there.
+fn find<'a>(node: &'a Node, key: &[u8]) -> (i64, Option<&'a Node>) {
+    match node.children() {
+        None => match key.cmp(&node.key) {
+            Ordering::Less => (0, None),
+            Ordering::Equal => (0, Some(node)),
+            Ordering::Greater => (1, None),
+        },
+        Some((left, right)) => {
+            if key < &node.key[..] {
+                find(left, key)
+            } else {
+                let (index, leaf) = find(right, key);
+                (index + left.size, leaf)
+            }
+        }
+    }
+}
+
+/// The path from `node` to the leaf at `index`, and the leaf.
+fn path_to(mut node: &Node, mut index: i64) -> (PathToLeaf, &Node) {
+    let mut path = PathToLeaf::new();
+    while let Some((left, right)) = node.children() {
+        if index < left.size {
+            path.push(node.proof_inner(None, Some(right)));
+            node = left;
+        } else {
+            path.push(node.proof_inner(Some(left), None));
+            index -= left.size;
+            node = right;
+        }
+    }
+    (path, node)
+}
+
+/// Proves the leaves at `index` and the one after, if there is one.
+fn range_proof(root: &Node, index: i64, adjacent: bool) -> RangeProof {
+    let (left_path, leaf) = path_to(root, index);
+    let mut proof = RangeProof {
+        left_path,
+        inner_nodes: Vec::new(),
+        leaves: vec![leaf.proof_leaf()],
+    };
+    if adjacent {
+        let (path, leaf) = path_to(root, index + 1);
+        let shared = proof
+            .left_path
+            .iter()
+            .zip(&path)
+            .take_while(|(left, right)| left == right)
+            .count();
+        proof.inner_nodes.push(path[shared + 1..].to_vec());
+        proof.leaves.push(leaf.proof_leaf());
+    }
+    proof
+}
+
+/// The store. Reads see uncommitted writes; proofs are only available for committed versions.
+#[derive(Debug)]
+pub struct KvStore<S = MemoryStorage> {
+    storage: S,
+    /// The root of each version, `None` for an empty tree.
+    roots: BTreeMap<i64, Option<Arc<Node>>>,
+    pending: ChangeSet,
+}
+
+impl KvStore<MemoryStorage> {
+    pub fn new() -> KvStore<MemoryStorage> {
+        KvStore::load(MemoryStorage::new()).unwrap()
+    }
+}
+
+impl Default for KvStore<MemoryStorage> {
+    fn default() -> KvStore<MemoryStorage> {
+        KvStore::new()
+    }
+}
+
+impl<S: Storage> KvStore<S> {
+    /// Opens a store with the versions saved in `storage`.
+    pub fn load(storage: S) -> io::Result<KvStore<S>> {
+        let mut store = KvStore {
+            storage,
+            roots: BTreeMap::new(),
+            pending: ChangeSet::new(),
+        };
+        for (version, changes) in store.storage.load_versions()? {
+            store.apply(version, changes);
+        }
+        for version in store.storage.deleted_versions()? {
+            store.roots.remove(&version);
+        }
+        Ok(store)
+    }
+
+    /// The latest committed version, zero before the first commit.
+    pub fn version(&self) -> i64 {
+        self.roots.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.pending.insert(key, Some(value));
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.pending.insert(key.to_vec(), None);
+    }
+
+    /// The current value of `key`, including uncommitted writes.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        match self.pending.get(key) {
+            Some(value) => value.as_deref(),
+            None => self.get_versioned(key, self.version()),
+        }
+    }
+
+    /// The value `key` had in `version`.
+    pub fn get_versioned(&self, key: &[u8], version: i64) -> Option<&[u8]> {
+        let root = self.roots.get(&version)?.as_ref()?;
+        find(root, key).1?.value()
+    }
+
+    /// The root hash of `version`, or `None` if it was never committed. The root of an empty
+    /// tree is empty.
+    pub fn root_hash(&self, version: i64) -> Option<&[u8]> {
+        self.roots
+            .get(&version)
+            .map(|root| root.as_ref().map_or(&[][..], |root| &root.hash[..]))
+    }
+
+    /// Saves the pending writes as the version of the block at `height` and returns its root
+    /// hash. Heights must increase from one commit to the next.
+    pub fn commit(&mut self, height: i64) -> io::Result<Vec<u8>> {
+        if height <= self.version() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot commit height {} after height {}",
+                    height,
+                    self.version()
+                ),
+            ));
+        }
+        self.storage.save_version(height, &self.pending)?;
+        let changes = std::mem::take(&mut self.pending);
+        self.apply(height, changes);
+        Ok(self.root_hash(height).unwrap().to_vec())
+    }
+
+    /// Deletes `version`, freeing the nodes no other version uses. The latest version cannot be
+    /// deleted.
+    pub fn delete_version(&mut self, version: i64) -> io::Result<()> {
+        if version == self.version() || !self.roots.contains_key(&version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot delete version {}", version),
+            ));
+        }
+        self.storage.delete_version(version)?;
+        self.roots.remove(&version);
+        Ok(())
+    }
+
+    /// The value of `key` in `version` and the op proving it, or proving that `key` has no
+    /// value. `None` if the version was never committed.
+    pub fn prove(&self, key: &[u8], version: i64) -> Option<(Option<Vec<u8>>, ProofOp)> {
+        let root = match self.roots.get(&version)? {
+            Some(root) => root,
+            // IAVL proves absence from an empty tree without a range.
+            None => {
+                let op = IavlAbsenceOp::new(key.to_vec(), RangeProof::default());
+                return Some((None, op.proof_op()));
+            }
+        };
+        match find(root, key) {
+            (index, Some(leaf)) => {
+                let value = leaf.value().map(<[u8]>::to_vec);
+                let op = IavlValueOp::new(key.to_vec(), range_proof(root, index, false));
+                Some((value, op.proof_op()))
+            }
+            // The leaves around the key, or the first or last leaf if it is outside the tree.
+            (index, None) => {
+                let proof = if index == 0 {
+                    range_proof(root, 0, false)
+                } else {
+                    range_proof(root, index - 1, index < root.size)
+                };
+                Some((None, IavlAbsenceOp::new(key.to_vec(), proof).proof_op()))
+            }
+        }
+    }
+
+    fn apply(&mut self, version: i64, changes: ChangeSet) {
+        let mut root = self.roots.values().next_back().cloned().flatten();
+        for (key, value) in changes {
+            root = match (root, value) {
+                (None, Some(value)) => Some(Node::leaf(key, value, version)),
+                (Some(node), Some(value)) => Some(set(&node, key, value, version)),
+                (Some(node), None) => match remove(&node, &key, version) {
+                    Some((node, _)) => node,
+                    None => Some(node),
+                },
+                (None, None) => None,
+            };
+        }
+        self.roots.insert(version, root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{ProofError, ProofRuntime};
+    use crate::messages::merkle::Proof;
+
+    fn verify(store: &KvStore, key: &[u8], version: i64) -> Result<(), ProofError> {
+        let (value, op) = store.prove(key, version).unwrap();
+        let mut proof = Proof::new();
+        proof.mut_ops().push(op);
+        let root = store.root_hash(version).unwrap();
+        let keypath = format!("/x:{}", crate::merkle::to_hex(key));
+        let runtime = ProofRuntime::with_cosmos_ops();
+        match value {
+            Some(value) => runtime.verify_value(&proof, root, &keypath, &value),
+            None => runtime.verify_absence(&proof, root, &keypath),
+        }
+    }
+
+    /// Checks the heights, sizes and keys of the tree under `node`, and that it is balanced.
+    fn check(node: &Node) {
+        if let Some((left, right)) = node.children() {
+            check(left);
+            check(right);
+            assert!(node.balance().abs() <= 1);
+            assert_eq!(node.height, left.height.max(right.height) + 1);
+            assert_eq!(node.size, left.size + right.size);
+            let mut first = &**right;
+            while let Some((left, _)) = first.children() {
+                first = left;
+            }
+            assert_eq!(node.key, first.key);
+        }
+    }
+
+    fn count_version(node: &Node, version: i64) -> usize {
+        let own = (node.version == version) as usize;
+        own + node.children().map_or(0, |(left, right)| {
+            count_version(left, version) + count_version(right, version)
+        })
+    }
+
+    #[test]
+    fn should_version_values() {
+        let mut store = KvStore::new();
+        assert_eq!(store.version(), 0);
+        store.set(b"a".to_vec(), b"1".to_vec());
+        assert_eq!(store.get(b"a"), Some(&b"1"[..]));
+        assert_eq!(store.prove(b"a", 1), None);
+        let first = store.commit(1).unwrap();
+
+        store.set(b"a".to_vec(), b"2".to_vec());
+        store.set(b"b".to_vec(), b"3".to_vec());
+        let second = store.commit(2).unwrap();
+        store.remove(b"b");
+        assert_eq!(store.get(b"b"), None);
+        let third = store.commit(4).unwrap();
+
+        assert_eq!(store.version(), 4);
+        assert_eq!(store.get_versioned(b"a", 1), Some(&b"1"[..]));
+        assert_eq!(store.get_versioned(b"b", 2), Some(&b"3"[..]));
+        assert_eq!(store.get_versioned(b"b", 4), None);
+        assert_eq!(store.get_versioned(b"a", 3), None);
+        assert_eq!(store.root_hash(3), None);
+        assert_ne!(first, second);
+        assert_ne!(first, third);
+        assert_eq!(store.root_hash(1), Some(&first[..]));
+        let leaf = ProofLeafNode {
+            key: b"a".to_vec(),
+            value_hash: sha256(b"1"),
+            version: 1,
+        };
+        assert_eq!(first, leaf.hash());
+
+        let error = store.commit(4).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(store.version(), 4);
+    }
+
+    #[test]
+    fn should_prove_presence_and_absence() {
+        let mut store = KvStore::new();
+        store.commit(1).unwrap();
+        assert_eq!(store.root_hash(1), Some(&[][..]));
+        for key in &[&b"b"[..], b"d", b"f", b"h", b"j"] {
+            store.set(key.to_vec(), key.repeat(2));
+        }
+        store.commit(2).unwrap();
+        store.remove(b"f");
+        store.commit(3).unwrap();
+
+        verify(&store, b"a", 1).unwrap();
+        for version in 2..=3 {
+            for key in b"abcdefghijk".chunks(1) {
+                verify(&store, key, version).unwrap();
+            }
+        }
+        assert_eq!(store.prove(b"f", 3).unwrap().0, None);
+
+        let (_, op) = store.prove(b"d", 2).unwrap();
+        let op = IavlValueOp::decode(&op).unwrap();
+        let root = store.root_hash(3).unwrap().to_vec();
+        assert_ne!(op.run(vec![b"dd".to_vec()]).unwrap(), vec![root]);
+        assert!(op.run(vec![b"d".to_vec()]).is_err());
+    }
+
+    #[test]
+    fn should_stay_balanced_and_match_a_map() {
+        let mut store = KvStore::new();
+        let mut expected = BTreeMap::new();
+        let mut seed = 7u32;
+        for height in 1..=20 {
+            for _ in 0..40 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                let key = vec![b'k', (seed >> 16) as u8 % 64];
+                if seed % 3 == 2 {
+                    store.remove(&key);
+                    expected.remove(&key);
+                } else {
+                    let value = height.to_string().into_bytes();
+                    store.set(key.clone(), value.clone());
+                    expected.insert(key, value);
+                }
+            }
+            store.commit(height).unwrap();
+
+            let root = store.roots[&height].as_ref().unwrap();
+            check(root);
+            assert_eq!(root.size, expected.len() as i64);
+            for key in (0..66).map(|i| vec![b'k', i]) {
+                assert_eq!(store.get(&key), expected.get(&key).map(|v| &v[..]));
+                verify(&store, &key, height).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn should_hash_only_changed_paths() {
+        let mut store = KvStore::new();
+        for i in 0..1000u32 {
+            store.set(i.to_be_bytes().to_vec(), b"value".to_vec());
+        }
+        store.commit(1).unwrap();
+        store.set(500u32.to_be_bytes().to_vec(), b"other".to_vec());
+        store.commit(2).unwrap();
+
+        let root = store.roots[&2].as_ref().unwrap();
+        assert_eq!(count_version(root, 2), root.height as usize + 1);
+    }
+
+    #[test]
+    fn should_reload_from_storage() {
+        let mut store = KvStore::load(MemoryStorage::new()).unwrap();
+        store.set(b"a".to_vec(), b"1".to_vec());
+        store.commit(5).unwrap();
+        store.set(b"b".to_vec(), b"2".to_vec());
+        let root = store.commit(6).unwrap();
+
+        let reloaded = KvStore::load(store.storage.clone()).unwrap();
+        assert_eq!(reloaded.version(), 6);
+        assert_eq!(reloaded.root_hash(6), Some(&root[..]));
+        assert_eq!(reloaded.get(b"a"), Some(&b"1"[..]));
+    }
+
+    #[test]
+    fn should_delete_versions() {
+        let mut store = KvStore::new();
+        store.set(b"a".to_vec(), b"1".to_vec());
+        store.commit(1).unwrap();
+        store.set(b"a".to_vec(), b"2".to_vec());
+        let root = store.commit(2).unwrap();
+
+        assert!(store.delete_version(2).is_err());
+        assert!(store.delete_version(3).is_err());
+        store.delete_version(1).unwrap();
+        assert_eq!(store.root_hash(1), None);
+        assert_eq!(store.get_versioned(b"a", 1), None);
+        assert_eq!(store.get(b"a"), Some(&b"2"[..]));
+        assert!(store.delete_version(1).is_err());
+
+        let reloaded = KvStore::load(store.storage.clone()).unwrap();
+        assert_eq!(reloaded.root_hash(1), None);
+        assert_eq!(reloaded.root_hash(2), Some(&root[..]));
+    }
+
+    #[test]
+    fn should_convert_proofs_to_ics23() {
+        use crate::merkle::ics23::{iavl_spec, ExistenceProof, NonExistenceProof};
+
+        let mut store = KvStore::new();
+        for version in 1..=3 {
+            for i in (0..60u8).step_by(version as usize) {
+                store.set(vec![b'k', i * 2], vec![version as u8, i]);
+            }
+            store.commit(version).unwrap();
+        }
+        let root = store.roots[&3].as_ref().unwrap();
+        let hash = store.root_hash(3).unwrap();
+        let spec = iavl_spec();
+        let proofs: Vec<ExistenceProof> = (0..root.size)
+            .map(|index| {
+                let (_, leaf) = path_to(root, index);
+                let proof = range_proof(root, index, false);
+                ExistenceProof::from_iavl_range_proof(&proof, leaf.value().unwrap()).unwrap()
+            })
+            .collect();
+        for proof in &proofs {
+            proof.verify(&spec, hash, &proof.key, &proof.value).unwrap();
+        }
+
+        let absent = |key: &[u8], left: Option<&ExistenceProof>, right: Option<&ExistenceProof>| {
+            NonExistenceProof {
+                key: key.to_vec(),
+                left: left.cloned(),
+                right: right.cloned(),
+            }
+            .verify(&spec, hash, key)
+        };
+        for pair in proofs.windows(2) {
+            let mut key = pair[0].key.clone();
+            key.push(0);
+            absent(&key, Some(&pair[0]), Some(&pair[1])).unwrap();
+        }
+        absent(b"a", None, proofs.first()).unwrap();
+        absent(b"z", proofs.last(), None).unwrap();
+        assert!(absent(&[b'k', 3], Some(&proofs[0]), Some(&proofs[2])).is_err());
+        assert!(absent(b"z", Some(&proofs[0]), None).is_err());
+    }
+}
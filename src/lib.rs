@@ -26,54 +26,115 @@ extern crate futures;
 extern crate integer_encoding;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "chrono")]
+extern crate chrono;
 extern crate core;
 extern crate protobuf;
+#[cfg(feature = "serde")]
+extern crate serde_crate as serde;
+extern crate sha2;
 extern crate tokio;
 
 use std::net::SocketAddr;
+use std::sync::Arc;
 
+pub use crate::backend::{Backend, BlockHeader, Method, Protobuf, RequestSummary};
+pub use crate::codec::{transcode, ABCICodec, WireMessage};
+pub use crate::consensus::{ConsensusContext, ConsensusPhase, OrderingPolicy};
+pub use crate::context::{CancellationToken, Context};
+pub use crate::error::{
+    AbciError, ErrorResponse, RegisteredError, Registry, RegistryError, ResponseError,
+    WrappedError, CODE_TYPE_INTERNAL,
+};
 pub use crate::messages::abci::*;
 pub use crate::messages::merkle::*;
 pub use crate::messages::types::*;
 use crate::server::serve;
+pub use crate::server::{ConnectionRole, PanicPolicy, ServerConfig};
 
+mod backend;
+pub mod block;
 mod codec;
+mod consensus;
+mod context;
+mod error;
+pub mod events;
+pub mod indexer;
+#[cfg(feature = "serde")]
+mod json;
+#[cfg(feature = "kvstore")]
+pub mod kvstore;
+pub mod merkle;
 mod messages;
+#[cfg(feature = "prost-messages")]
+pub mod prost;
+pub mod query;
 mod server;
+pub mod time;
+pub mod types;
+pub mod validators;
 
 /// Main Trait for an ABCI application. Provides generic responses for all callbacks
 /// Override desired callbacks as needed.  Tendermint makes 3 TCP connections to the
 /// application and does so in a synchonized manner.
-pub trait Application {
+///
+/// Every callback gets a `Context` alongside the request, carrying the connection it came in on,
+/// the header and height of the block in progress and the transaction's index within it.
+///
+/// `check_tx`, `deliver_tx` and `query` may fail with any `AbciError`; the server turns the error
+/// into the response's `code`, `codespace` and `log`.
+///
+/// Callbacks take the rust-protobuf messages by default. Applications written against another
+/// `Backend`, such as `abci::prost::Prost`, implement `Application<Prost>` and are started with
+/// `run_with_backend`.
+pub trait Application<B: Backend = Protobuf> {
+    /// Called when the server has classified a new connection by its traffic. Tendermint opens
+    /// one connection per role.
+    fn connection_established(&mut self, _role: ConnectionRole) {}
+
     /// Query Connection: Called on startup from Tendermint.  The application should normally
     /// return the last know state so Tendermint can determine if it needs to replay blocks
     /// to the application.
-    fn info(&mut self, _req: &RequestInfo) -> ResponseInfo {
-        ResponseInfo::new()
+    fn info(&mut self, _ctx: &Context, _req: &B::RequestInfo) -> B::ResponseInfo {
+        Default::default()
     }
 
     /// Query Connection: Set options on the application (rarely used)
-    fn set_option(&mut self, _req: &RequestSetOption) -> ResponseSetOption {
-        ResponseSetOption::new()
+    fn set_option(&mut self, _ctx: &Context, _req: &B::RequestSetOption) -> B::ResponseSetOption {
+        Default::default()
     }
 
     /// Query Connection: Query your application. This usually resolves through a merkle tree holding
     /// the state of the app.
-    fn query(&mut self, _req: &RequestQuery) -> ResponseQuery {
-        ResponseQuery::new()
+    fn query(
+        &mut self,
+        _ctx: &Context,
+        _req: &B::RequestQuery,
+    ) -> Result<B::ResponseQuery, Box<dyn AbciError>> {
+        Ok(Default::default())
     }
 
     /// Mempool Connection:  Used to validate incoming transactions.  If the application reponds
     /// with a non-zero value, the transaction is added to Tendermint's mempool for processing
     /// on the deliver_tx call below.
-    fn check_tx(&mut self, _req: &RequestCheckTx) -> ResponseCheckTx {
-        ResponseCheckTx::new()
+    fn check_tx(
+        &mut self,
+        _ctx: &Context,
+        _req: &B::RequestCheckTx,
+    ) -> Result<B::ResponseCheckTx, Box<dyn AbciError>> {
+        Ok(Default::default())
+    }
+
+    /// Mempool Connection: Optional handler used instead of `check_tx` when the server is
+    /// configured with `ServerConfig::pipeline_check_tx`. Called once per mempool connection.
+    fn check_tx_handler(&self) -> Option<Arc<dyn CheckTxHandler<B>>> {
+        None
     }
 
     /// Consensus Connection:  Called once on startup. Usually used to establish initial (genesis)
     /// state.
-    fn init_chain(&mut self, _req: &RequestInitChain) -> ResponseInitChain {
-        ResponseInitChain::new()
+    fn init_chain(&mut self, _ctx: &Context, _req: &B::RequestInitChain) -> B::ResponseInitChain {
+        Default::default()
     }
 
     /// Consensus Connection: Called at the start of processing a block of transactions
@@ -82,27 +143,46 @@ pub trait Application {
     ///   deliver_tx()  for each transaction in the block
     /// end_block()
     /// commit()
-    fn begin_block(&mut self, _req: &RequestBeginBlock) -> ResponseBeginBlock {
-        ResponseBeginBlock::new()
+    fn begin_block(
+        &mut self,
+        _ctx: &Context,
+        _req: &B::RequestBeginBlock,
+    ) -> B::ResponseBeginBlock {
+        Default::default()
     }
 
     /// Consensus Connection: Actually processing the transaction, performing some form of a
     /// state transistion.
-    fn deliver_tx(&mut self, _p: &RequestDeliverTx) -> ResponseDeliverTx {
-        ResponseDeliverTx::new()
+    fn deliver_tx(
+        &mut self,
+        _ctx: &Context,
+        _p: &B::RequestDeliverTx,
+    ) -> Result<B::ResponseDeliverTx, Box<dyn AbciError>> {
+        Ok(Default::default())
     }
 
     /// Consensus Connection: Called at the end of the block.  Often used to update the validator set.
-    fn end_block(&mut self, _req: &RequestEndBlock) -> ResponseEndBlock {
-        ResponseEndBlock::new()
+    fn end_block(&mut self, _ctx: &Context, _req: &B::RequestEndBlock) -> B::ResponseEndBlock {
+        Default::default()
     }
 
     /// Consensus Connection: Commit the block with the latest state from the application.
-    fn commit(&mut self, _req: &RequestCommit) -> ResponseCommit {
-        ResponseCommit::new()
+    fn commit(&mut self, _ctx: &Context, _req: &B::RequestCommit) -> B::ResponseCommit {
+        Default::default()
     }
 }
 
+/// Validates mempool transactions without exclusive access to the application, so the server can
+/// check several of them in parallel (e.g. to spread signature verification across CPU cores).
+/// Responses are still written back in request order.
+pub trait CheckTxHandler<B: Backend = Protobuf>: Send + Sync {
+    fn check_tx(
+        &self,
+        ctx: &Context,
+        req: &B::RequestCheckTx,
+    ) -> Result<B::ResponseCheckTx, Box<dyn AbciError>>;
+}
+
 /// Setup the app and start the server using localhost and default tendermint port 26658
 pub fn run_local<A>(app: A)
 where
@@ -117,5 +197,24 @@ pub fn run<A>(listen_addr: SocketAddr, app: A)
 where
     A: Application + 'static + Send + Sync,
 {
-    serve(app, listen_addr).unwrap();
+    run_with_config(listen_addr, app, ServerConfig::default());
+}
+
+/// Setup the application and start the server with custom settings, e.g. a panic policy for
+/// the mempool and query connections.
+pub fn run_with_config<A>(listen_addr: SocketAddr, app: A, config: ServerConfig)
+where
+    A: Application + 'static + Send + Sync,
+{
+    run_with_backend::<Protobuf, A>(listen_addr, app, config);
+}
+
+/// Setup an application written against another message backend and start the server, e.g.
+/// `run_with_backend::<abci::prost::Prost, _>(addr, app, config)`.
+pub fn run_with_backend<B, A>(listen_addr: SocketAddr, app: A, config: ServerConfig)
+where
+    B: Backend,
+    A: Application<B> + 'static + Send + Sync,
+{
+    serve(app, listen_addr, config).unwrap();
 }
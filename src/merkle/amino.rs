@@ -0,0 +1,216 @@
+//! Just enough of Amino's binary encoding to read and write the structures Tendermint hashes and
+//! puts in proof ops. For these structures it matches protobuf: varints, fields keyed by
+//! `number << 3 | wire type`, and zero values left out. Small signed integers are zigzag encoded.
+use integer_encoding::VarInt;
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const BYTES: u8 = 2;
+const FIXED32: u8 = 5;
+
+#[derive(Default)]
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub(crate) fn uvarint(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.encode_var_vec());
+    }
+
+    /// Writes a signed varint, zigzag encoded as Amino's `EncodeVarint` and `EncodeInt8` do.
+    pub(crate) fn svarint(&mut self, value: i64) {
+        self.uvarint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    /// Writes a byte slice as a bare value: its length, then its bytes.
+    pub(crate) fn byte_slice(&mut self, bytes: &[u8]) {
+        self.uvarint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.uvarint(u64::from(field) << 3 | u64::from(wire_type));
+    }
+
+    /// Writes a varint field, unless `value` is zero. Signed integers are cast, as Amino does.
+    pub(crate) fn varint_field(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, VARINT);
+            self.uvarint(value);
+        }
+    }
+
+    /// Writes a zigzag encoded varint field, unless `value` is zero. Amino uses it for `int8` and
+    /// `int16` fields.
+    pub(crate) fn svarint_field(&mut self, field: u32, value: i64) {
+        if value != 0 {
+            self.key(field, VARINT);
+            self.svarint(value);
+        }
+    }
+
+    /// Writes a length-delimited field, unless `bytes` is empty.
+    pub(crate) fn bytes_field(&mut self, field: u32, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            self.repeated_bytes_field(field, bytes);
+        }
+    }
+
+    /// Writes a length-delimited field even if `bytes` is empty, as for elements of repeated
+    /// fields.
+    pub(crate) fn repeated_bytes_field(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, BYTES);
+        self.byte_slice(bytes);
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// The encoding prefixed with its length, as `MarshalBinaryLengthPrefixed` produces.
+    pub(crate) fn into_length_prefixed(self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.uvarint(self.buf.len() as u64);
+        writer.buf.extend_from_slice(&self.buf);
+        writer.buf
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    /// Reads an encoding produced by `MarshalBinaryLengthPrefixed`.
+    pub(crate) fn length_prefixed(bytes: &'a [u8]) -> Result<Reader<'a>, String> {
+        let mut reader = Reader::new(bytes);
+        let len = reader.uvarint()?;
+        if len != reader.bytes.len() as u64 {
+            return Err(format!(
+                "length prefix {} does not match the {} bytes that follow",
+                len,
+                reader.bytes.len()
+            ));
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn uvarint(&mut self) -> Result<u64, String> {
+        let (value, len) = u64::decode_var(self.bytes);
+        if len == 0 || len > 10 || self.bytes[len - 1] & 0x80 != 0 {
+            return Err(String::from("invalid varint"));
+        }
+        self.bytes = &self.bytes[len..];
+        Ok(value)
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8], String> {
+        if len > self.bytes.len() as u64 {
+            return Err(String::from("unexpected end of input"));
+        }
+        let (taken, rest) = self.bytes.split_at(len as usize);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Reads a field key, returning the field number and wire type.
+    pub(crate) fn key(&mut self) -> Result<(u32, u8), String> {
+        let key = self.uvarint()?;
+        Ok(((key >> 3) as u32, (key & 7) as u8))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.uvarint()?;
+        self.take(len)
+    }
+
+    /// Checks that a field has the wire type its value is read as.
+    fn check_wire_type(&self, field: u32, wire_type: u8, expected: u8) -> Result<(), String> {
+        if wire_type == expected {
+            Ok(())
+        } else {
+            Err(format!("field {} has wire type {}", field, wire_type))
+        }
+    }
+
+    pub(crate) fn varint_value(&mut self, field: u32, wire_type: u8) -> Result<u64, String> {
+        self.check_wire_type(field, wire_type, VARINT)?;
+        self.uvarint()
+    }
+
+    pub(crate) fn svarint_value(&mut self, field: u32, wire_type: u8) -> Result<i64, String> {
+        let value = self.varint_value(field, wire_type)?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    pub(crate) fn bytes_value(&mut self, field: u32, wire_type: u8) -> Result<&'a [u8], String> {
+        self.check_wire_type(field, wire_type, BYTES)?;
+        self.bytes()
+    }
+
+    /// Skips the value of a field this reader does not know.
+    pub(crate) fn skip(&mut self, wire_type: u8) -> Result<(), String> {
+        match wire_type {
+            VARINT => self.uvarint().map(drop),
+            FIXED64 => self.take(8).map(drop),
+            BYTES => self.bytes().map(drop),
+            FIXED32 => self.take(4).map(drop),
+            _ => Err(format!("unsupported wire type {}", wire_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_round_trip_fields() {
+        let mut writer = Writer::new();
+        writer.varint_field(1, 300);
+        writer.varint_field(2, 0);
+        writer.bytes_field(3, b"abc");
+        writer.repeated_bytes_field(4, b"");
+        writer.varint_field(5, -1i64 as u64);
+        writer.svarint_field(6, -2);
+        let bytes = writer.into_length_prefixed();
+        assert_eq!(&bytes[..8], &[23, 0x08, 0xAC, 0x02, 0x1A, 3, b'a', b'b']);
+        assert_eq!(&bytes[22..], &[0x30, 3]);
+
+        let mut reader = Reader::length_prefixed(&bytes).unwrap();
+        let mut fields = Vec::new();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key().unwrap();
+            match field {
+                1 | 5 => fields.push(reader.varint_value(field, wire_type).unwrap() as i64),
+                6 => fields.push(reader.svarint_value(field, wire_type).unwrap()),
+                _ => fields.push(reader.bytes_value(field, wire_type).unwrap().len() as i64),
+            }
+        }
+        assert_eq!(fields, vec![300, 3, 0, -1, -2]);
+    }
+
+    #[test]
+    fn should_reject_truncated_input() {
+        assert!(Reader::new(&[0x80]).uvarint().is_err());
+        assert!(Reader::new(&[2, 1]).bytes().is_err());
+        assert!(Reader::length_prefixed(&[3, 1]).is_err());
+        assert_eq!(
+            Reader::new(&[0x08, 1]).bytes_value(1, 0),
+            Err(String::from("field 1 has wire type 0"))
+        );
+    }
+}
@@ -0,0 +1,572 @@
+//! Proofs of IAVL trees, the stores of Cosmos SDK applications, as returned in
+//! `ResponseQuery.proof` with ops of type `iavl:v` (a value) and `iavl:a` (an absence).
+//!
+//! Both ops carry a `RangeProof`: the path from the root to the leftmost leaf of a range of
+//! adjacent leaves, and the paths to the other leaves of the range. IAVL hashes a node as
+//! `SHA-256` of its height, size and version, zigzag encoded, followed by the length-prefixed
+//! child hashes, or the key and value hash for a leaf.
+use std::cmp::Ordering;
+
+use super::amino::{Reader, Writer};
+use super::simple::sha256;
+use super::{to_hex, ProofError, ProofOperator};
+use crate::messages::merkle::ProofOp;
+
+/// `ProofOp.type` of `IavlValueOp`.
+pub const PROOF_OP_IAVL_VALUE: &str = "iavl:v";
+/// `ProofOp.type` of `IavlAbsenceOp`.
+pub const PROOF_OP_IAVL_ABSENCE: &str = "iavl:a";
+
+/// An inner node on the path to a leaf. The side the path goes down is empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofInnerNode {
+    pub height: i8,
+    pub size: i64,
+    pub version: i64,
+    pub left: Vec<u8>,
+    pub right: Vec<u8>,
+}
+
+impl ProofInnerNode {
+    /// The hash of the node, given the hash of the child the path goes down.
+    pub fn hash(&self, child_hash: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.svarint(i64::from(self.height));
+        writer.svarint(self.size);
+        writer.svarint(self.version);
+        if self.left.is_empty() {
+            writer.byte_slice(child_hash);
+            writer.byte_slice(&self.right);
+        } else {
+            writer.byte_slice(&self.left);
+            writer.byte_slice(child_hash);
+        }
+        sha256(&writer.into_vec())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.svarint_field(1, i64::from(self.height));
+        writer.varint_field(2, self.size as u64);
+        writer.varint_field(3, self.version as u64);
+        writer.bytes_field(4, &self.left);
+        writer.bytes_field(5, &self.right);
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<ProofInnerNode, String> {
+        let mut reader = Reader::new(bytes);
+        let mut node = ProofInnerNode::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => node.height = reader.svarint_value(field, wire_type)? as i8,
+                2 => node.size = reader.varint_value(field, wire_type)? as i64,
+                3 => node.version = reader.varint_value(field, wire_type)? as i64,
+                4 => node.left = reader.bytes_value(field, wire_type)?.to_vec(),
+                5 => node.right = reader.bytes_value(field, wire_type)?.to_vec(),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(node)
+    }
+}
+
+/// A leaf of the proven range.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofLeafNode {
+    pub key: Vec<u8>,
+    /// `SHA-256` of the value.
+    pub value_hash: Vec<u8>,
+    pub version: i64,
+}
+
+impl ProofLeafNode {
+    pub fn hash(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.svarint(0);
+        writer.svarint(1);
+        writer.svarint(self.version);
+        writer.byte_slice(&self.key);
+        writer.byte_slice(&self.value_hash);
+        sha256(&writer.into_vec())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.bytes_field(1, &self.key);
+        writer.bytes_field(2, &self.value_hash);
+        writer.varint_field(3, self.version as u64);
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<ProofLeafNode, String> {
+        let mut reader = Reader::new(bytes);
+        let mut leaf = ProofLeafNode::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => leaf.key = reader.bytes_value(field, wire_type)?.to_vec(),
+                2 => leaf.value_hash = reader.bytes_value(field, wire_type)?.to_vec(),
+                3 => leaf.version = reader.varint_value(field, wire_type)? as i64,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(leaf)
+    }
+}
+
+/// Inner nodes from the root down to a leaf.
+pub type PathToLeaf = Vec<ProofInnerNode>;
+
+fn path_root_hash(path: &[ProofInnerNode], leaf_hash: Vec<u8>) -> Vec<u8> {
+    path.iter()
+        .rev()
+        .fold(leaf_hash, |hash, node| node.hash(&hash))
+}
+
+fn is_leftmost(path: &[ProofInnerNode]) -> bool {
+    path.iter().all(|node| node.left.is_empty())
+}
+
+fn is_rightmost(path: &[ProofInnerNode]) -> bool {
+    path.iter().all(|node| node.right.is_empty())
+}
+
+fn encode_path(path: &[ProofInnerNode]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    for node in path {
+        writer.repeated_bytes_field(1, &node.encode());
+    }
+    writer.into_vec()
+}
+
+fn decode_path(bytes: &[u8]) -> Result<PathToLeaf, String> {
+    let mut reader = Reader::new(bytes);
+    let mut path = PathToLeaf::new();
+    while !reader.is_empty() {
+        let (field, wire_type) = reader.key()?;
+        if field == 1 {
+            path.push(ProofInnerNode::decode(
+                reader.bytes_value(field, wire_type)?,
+            )?);
+        } else {
+            reader.skip(wire_type)?;
+        }
+    }
+    Ok(path)
+}
+
+/// Proves a range of adjacent leaves of an IAVL tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangeProof {
+    pub left_path: PathToLeaf,
+    /// The paths to the leaves after the first, each starting below the node where it leaves the
+    /// path to the previous leaf.
+    pub inner_nodes: Vec<PathToLeaf>,
+    /// The leaves of the range, sorted by key.
+    pub leaves: Vec<ProofLeafNode>,
+}
+
+/// The state of `RangeProof::root_and_tree_end`: the leaves and paths not consumed yet.
+struct RootComputation<'a> {
+    leaves: &'a [ProofLeafNode],
+    inner_nodes: &'a [PathToLeaf],
+}
+
+impl<'a> RootComputation<'a> {
+    /// Hashes the next leaf up `path`, then proves the right siblings along `path` with the
+    /// following leaves. Returns the hash, whether the last leaf ends the tree, and whether all
+    /// leaves were consumed.
+    fn compute(
+        &mut self,
+        mut path: &'a [ProofInnerNode],
+        rightmost: bool,
+    ) -> Result<(Vec<u8>, bool, bool), ProofError> {
+        let (leaf, leaves) = self
+            .leaves
+            .split_first()
+            .ok_or_else(|| ProofError::InvalidProof(String::from("missing leaves")))?;
+        self.leaves = leaves;
+        let hash = path_root_hash(path, leaf.hash());
+        if self.leaves.is_empty() {
+            return Ok((hash, rightmost && is_rightmost(path), true));
+        }
+
+        while let Some((last, rest)) = path.split_last() {
+            path = rest;
+            if last.right.is_empty() {
+                continue;
+            }
+            let (inner, inner_nodes) = self
+                .inner_nodes
+                .split_first()
+                .ok_or_else(|| ProofError::InvalidProof(String::from("missing inner nodes")))?;
+            self.inner_nodes = inner_nodes;
+            let (derived, tree_end, done) = self.compute(inner, rightmost && is_rightmost(path))?;
+            if derived != last.right {
+                return Err(ProofError::InvalidProof(format!(
+                    "intermediate root hash {} doesn't match, got {}",
+                    to_hex(&last.right),
+                    to_hex(&derived)
+                )));
+            }
+            if done {
+                return Ok((hash, tree_end, true));
+            }
+        }
+        Ok((hash, false, false))
+    }
+}
+
+impl RangeProof {
+    /// The root of the tree, and whether the last leaf of the range is the last of the tree.
+    fn root_and_tree_end(&self) -> Result<(Vec<u8>, bool), ProofError> {
+        if self.leaves.is_empty() {
+            return Err(ProofError::InvalidProof(String::from("no leaves")));
+        }
+        if self.inner_nodes.len() + 1 != self.leaves.len() {
+            return Err(ProofError::InvalidProof(String::from(
+                "there must be one more leaf than inner node paths",
+            )));
+        }
+        let mut computation = RootComputation {
+            leaves: &self.leaves,
+            inner_nodes: &self.inner_nodes,
+        };
+        let (root, tree_end, done) = computation.compute(&self.left_path, true)?;
+        if !done {
+            return Err(ProofError::InvalidProof(String::from(
+                "left over leaves -- malformed proof",
+            )));
+        }
+        Ok((root, tree_end))
+    }
+
+    /// The root the range proves its leaves under.
+    pub fn compute_root_hash(&self) -> Result<Vec<u8>, ProofError> {
+        self.root_and_tree_end().map(|(root, _)| root)
+    }
+
+    /// Checks that the range has a leaf for `value` stored under `key`. The caller checks the
+    /// root.
+    pub fn verify_item(&self, key: &[u8], value: &[u8]) -> Result<(), ProofError> {
+        let leaf = self
+            .leaves
+            .binary_search_by(|leaf| leaf.key.as_slice().cmp(key))
+            .map(|i| &self.leaves[i])
+            .map_err(|_| ProofError::InvalidProof(String::from("leaf key not found in proof")))?;
+        if leaf.value_hash != sha256(value) {
+            return Err(ProofError::InvalidProof(String::from(
+                "leaf value hash not same",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the range shows `key` is not in the tree, i.e. that it falls between two
+    /// adjacent leaves or beyond either end of the tree. Returns the root.
+    pub fn verify_absence(&self, key: &[u8]) -> Result<Vec<u8>, ProofError> {
+        let (root, tree_end) = self.root_and_tree_end()?;
+        let invalid = |reason: String| Err(ProofError::InvalidProof(reason));
+        match key.cmp(&self.leaves[0].key) {
+            Ordering::Less if is_leftmost(&self.left_path) => return Ok(root),
+            Ordering::Less => return invalid(String::from("absence not proved by left path")),
+            Ordering::Equal => return invalid(String::from("absence disproved via item #0")),
+            Ordering::Greater => {}
+        }
+        if self.left_path.is_empty() || is_rightmost(&self.left_path) {
+            return Ok(root);
+        }
+        for (i, leaf) in self.leaves.iter().enumerate().skip(1) {
+            match key.cmp(&leaf.key) {
+                Ordering::Less => return Ok(root),
+                Ordering::Equal => return invalid(format!("absence disproved via item #{}", i)),
+                Ordering::Greater => {}
+            }
+        }
+        if tree_end {
+            Ok(root)
+        } else {
+            invalid(String::from("absence not proved by right leaf"))
+        }
+    }
+
+    /// The Amino encoding of the proof.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for node in &self.left_path {
+            writer.repeated_bytes_field(1, &node.encode());
+        }
+        for path in &self.inner_nodes {
+            writer.repeated_bytes_field(2, &encode_path(path));
+        }
+        for leaf in &self.leaves {
+            writer.repeated_bytes_field(3, &leaf.encode());
+        }
+        writer.into_vec()
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Result<RangeProof, String> {
+        let mut reader = Reader::new(bytes);
+        let mut proof = RangeProof::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => proof.left_path.push(ProofInnerNode::decode(
+                    reader.bytes_value(field, wire_type)?,
+                )?),
+                2 => proof
+                    .inner_nodes
+                    .push(decode_path(reader.bytes_value(field, wire_type)?)?),
+                3 => proof.leaves.push(ProofLeafNode::decode(
+                    reader.bytes_value(field, wire_type)?,
+                )?),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(proof)
+    }
+}
+
+/// Decodes the `RangeProof` in the data of an op of type `op_type`.
+fn decode_op(op: &ProofOp, op_type: &str) -> Result<RangeProof, ProofError> {
+    let invalid = |reason: String| ProofError::InvalidOp {
+        op_type: op.get_field_type().to_string(),
+        reason,
+    };
+    if op.get_field_type() != op_type {
+        return Err(invalid(format!("expected type {}", op_type)));
+    }
+    let mut reader = Reader::length_prefixed(op.get_data()).map_err(invalid)?;
+    let mut proof = RangeProof::default();
+    while !reader.is_empty() {
+        let (field, wire_type) = reader.key().map_err(invalid)?;
+        if field == 1 {
+            let bytes = reader.bytes_value(field, wire_type).map_err(invalid)?;
+            proof = RangeProof::decode(bytes).map_err(invalid)?;
+        } else {
+            reader.skip(wire_type).map_err(invalid)?;
+        }
+    }
+    Ok(proof)
+}
+
+fn encode_op(op_type: &str, key: &[u8], proof: &RangeProof) -> ProofOp {
+    let mut writer = Writer::new();
+    // An empty proof stands for Go's nil one, which Amino leaves out.
+    if *proof != RangeProof::default() {
+        writer.repeated_bytes_field(1, &proof.encode());
+    }
+    let mut op = ProofOp::new();
+    op.set_field_type(op_type.to_string());
+    op.set_key(key.to_vec());
+    op.set_data(writer.into_length_prefixed());
+    op
+}
+
+/// Proves a value stored under a key of an IAVL tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IavlValueOp {
+    pub key: Vec<u8>,
+    pub proof: RangeProof,
+}
+
+impl IavlValueOp {
+    pub fn new(key: Vec<u8>, proof: RangeProof) -> IavlValueOp {
+        IavlValueOp { key, proof }
+    }
+
+    /// Decodes an op of type `iavl:v`. Registered with `ProofRuntime::register_op_decoder`.
+    pub fn decode(op: &ProofOp) -> Result<Box<dyn ProofOperator>, ProofError> {
+        let proof = decode_op(op, PROOF_OP_IAVL_VALUE)?;
+        Ok(Box::new(IavlValueOp::new(op.get_key().to_vec(), proof)))
+    }
+}
+
+impl ProofOperator for IavlValueOp {
+    fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+        if args.len() != 1 {
+            return Err(ProofError::InvalidProof(format!(
+                "expected 1 arg, got {}",
+                args.len()
+            )));
+        }
+        let root = self.proof.compute_root_hash()?;
+        self.proof.verify_item(&self.key, &args[0])?;
+        Ok(vec![root])
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn proof_op(&self) -> ProofOp {
+        encode_op(PROOF_OP_IAVL_VALUE, &self.key, &self.proof)
+    }
+}
+
+/// Proves that nothing is stored under a key of an IAVL tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IavlAbsenceOp {
+    pub key: Vec<u8>,
+    pub proof: RangeProof,
+}
+
+impl IavlAbsenceOp {
+    pub fn new(key: Vec<u8>, proof: RangeProof) -> IavlAbsenceOp {
+        IavlAbsenceOp { key, proof }
+    }
+
+    /// Decodes an op of type `iavl:a`. Registered with `ProofRuntime::register_op_decoder`.
+    pub fn decode(op: &ProofOp) -> Result<Box<dyn ProofOperator>, ProofError> {
+        let proof = decode_op(op, PROOF_OP_IAVL_ABSENCE)?;
+        Ok(Box::new(IavlAbsenceOp::new(op.get_key().to_vec(), proof)))
+    }
+}
+
+impl ProofOperator for IavlAbsenceOp {
+    fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+        if !args.is_empty() {
+            return Err(ProofError::InvalidProof(format!(
+                "expected 0 args, got {}",
+                args.len()
+            )));
+        }
+        // IAVL sends no proof for an empty tree, whose root is empty.
+        if self.proof == RangeProof::default() {
+            return Ok(vec![Vec::new()]);
+        }
+        Ok(vec![self.proof.verify_absence(&self.key)?])
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn proof_op(&self) -> ProofOp {
+        encode_op(PROOF_OP_IAVL_ABSENCE, &self.key, &self.proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(key: &[u8], value: &[u8]) -> ProofLeafNode {
+        ProofLeafNode {
+            key: key.to_vec(),
+            value_hash: sha256(value),
+            version: 1,
+        }
+    }
+
+    fn root_node(left: Vec<u8>, right: Vec<u8>) -> ProofInnerNode {
+        ProofInnerNode {
+            height: 1,
+            size: 2,
+            version: 1,
+            left,
+            right,
+        }
+    }
+
+    /// Proofs of the tree with `a = 1` and `b = 2`: a range with leaf `a`, one with leaf `b`,
+    /// and one with both.
+    fn proofs() -> (Vec<u8>, RangeProof, RangeProof, RangeProof) {
+        let (a, b) = (leaf(b"a", b"1"), leaf(b"b", b"2"));
+        let root = root_node(Vec::new(), b.hash()).hash(&a.hash());
+        let first = RangeProof {
+            left_path: vec![root_node(Vec::new(), b.hash())],
+            inner_nodes: Vec::new(),
+            leaves: vec![a.clone()],
+        };
+        let last = RangeProof {
+            left_path: vec![root_node(a.hash(), Vec::new())],
+            inner_nodes: Vec::new(),
+            leaves: vec![b.clone()],
+        };
+        let both = RangeProof {
+            left_path: first.left_path.clone(),
+            inner_nodes: vec![Vec::new()],
+            leaves: vec![a, b],
+        };
+        (root, first, last, both)
+    }
+
+    #[test]
+    fn should_hash_nodes_like_iavl() {
+        let leaf = leaf(b"a", b"1");
+        let mut encoded = vec![0, 2, 2, 1, b'a', 32];
+        encoded.extend_from_slice(&sha256(b"1"));
+        assert_eq!(leaf.hash(), sha256(&encoded));
+
+        let node = root_node(Vec::new(), vec![7; 2]);
+        assert_eq!(
+            node.hash(&[9]),
+            sha256(&[2, 4, 2, 1, 9, 2, 7, 7]),
+            "the child hash comes first when the left side is empty"
+        );
+    }
+
+    #[test]
+    fn should_compute_the_same_root_for_every_range() {
+        let (root, first, last, both) = proofs();
+        assert_eq!(first.compute_root_hash().unwrap(), root);
+        assert_eq!(last.compute_root_hash().unwrap(), root);
+        assert_eq!(both.compute_root_hash().unwrap(), root);
+
+        both.verify_item(b"b", b"2").unwrap();
+        assert!(both.verify_item(b"b", b"1").is_err());
+        assert!(first.verify_item(b"b", b"2").is_err());
+
+        let mut tampered = both.clone();
+        tampered.leaves[1].value_hash = sha256(b"3");
+        assert!(tampered.compute_root_hash().is_err());
+        tampered.inner_nodes.clear();
+        assert!(tampered.compute_root_hash().is_err());
+    }
+
+    #[test]
+    fn should_verify_absence() {
+        let (root, first, last, both) = proofs();
+        assert_eq!(first.verify_absence(b"0").unwrap(), root);
+        assert_eq!(last.verify_absence(b"c").unwrap(), root);
+        assert_eq!(both.verify_absence(b"ab").unwrap(), root);
+
+        assert!(first.verify_absence(b"a").is_err());
+        assert!(first.verify_absence(b"ab").is_err());
+        assert!(last.verify_absence(b"ab").is_err());
+        assert!(both.verify_absence(b"b").is_err());
+    }
+
+    #[test]
+    fn should_round_trip_ops() {
+        let (root, first, last, both) = proofs();
+        let value = IavlValueOp::new(b"a".to_vec(), first).proof_op();
+        assert_eq!(value.get_field_type(), "iavl:v");
+        let decoded = IavlValueOp::decode(&value).unwrap();
+        assert_eq!(decoded.proof_op(), value);
+        assert_eq!(
+            decoded.run(vec![b"1".to_vec()]).unwrap(),
+            vec![root.clone()]
+        );
+        assert!(decoded.run(vec![b"2".to_vec()]).is_err());
+
+        let absence = IavlAbsenceOp::new(b"ab".to_vec(), both).proof_op();
+        let decoded = IavlAbsenceOp::decode(&absence).unwrap();
+        assert_eq!(decoded.proof_op(), absence);
+        assert_eq!(decoded.run(Vec::new()).unwrap(), vec![root]);
+
+        let empty = IavlAbsenceOp::new(b"a".to_vec(), RangeProof::default()).proof_op();
+        assert_eq!(empty.get_data(), &[0]);
+        let decoded = IavlAbsenceOp::decode(&empty).unwrap();
+        assert_eq!(decoded.run(Vec::new()).unwrap(), vec![Vec::<u8>::new()]);
+
+        let mut deep = last;
+        deep.left_path[0].height = -3;
+        let op = IavlAbsenceOp::new(b"c".to_vec(), deep.clone()).proof_op();
+        assert_eq!(decode_op(&op, PROOF_OP_IAVL_ABSENCE), Ok(deep));
+        assert!(IavlValueOp::decode(&op).is_err());
+    }
+}
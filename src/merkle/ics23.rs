@@ -0,0 +1,993 @@
+//! ICS-23 commitment proofs, as IBC-enabled chains put them in `ProofOp.data` with types
+//! `ics23:iavl` and `ics23:simple`.
+//!
+//! An existence proof hashes the key and value with a `LeafOp`, then applies the `InnerOp`s of
+//! its path, from the leaf up to the root. A non-existence proof is made of the existence proofs
+//! of the neighbours of the missing key. A `ProofSpec` describes the trees a proof may come from,
+//! so that a proof cannot pass an inner node off as a leaf or skip a neighbour.
+//!
+//! Only existence and non-existence proofs are supported, not batches. Hashes are SHA-256 and
+//! SHA-512 based.
+use std::cmp::Ordering;
+
+use sha2::{Digest, Sha256, Sha512, Sha512Trunc256};
+
+use super::amino::{Reader, Writer};
+use super::iavl::RangeProof;
+use super::simple::{self, SimpleProof};
+use super::{to_hex, ProofError, ProofOperator};
+use crate::messages::merkle::ProofOp;
+
+/// `ProofOp.type` of a `CommitmentOp` over an IAVL tree.
+pub const PROOF_OP_ICS23_IAVL: &str = "ics23:iavl";
+/// `ProofOp.type` of a `CommitmentOp` over a simple Merkle tree.
+pub const PROOF_OP_ICS23_SIMPLE: &str = "ics23:simple";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashOp {
+    #[default]
+    NoHash = 0,
+    Sha256 = 1,
+    Sha512 = 2,
+    Keccak = 3,
+    Ripemd160 = 4,
+    Bitcoin = 5,
+    Sha512_256 = 6,
+}
+
+impl HashOp {
+    fn from_u64(value: u64) -> Result<HashOp, String> {
+        Ok(match value {
+            0 => HashOp::NoHash,
+            1 => HashOp::Sha256,
+            2 => HashOp::Sha512,
+            3 => HashOp::Keccak,
+            4 => HashOp::Ripemd160,
+            5 => HashOp::Bitcoin,
+            6 => HashOp::Sha512_256,
+            _ => return Err(format!("unknown hash op {}", value)),
+        })
+    }
+
+    fn apply(self, data: &[u8]) -> Result<Vec<u8>, ProofError> {
+        match self {
+            HashOp::NoHash => Ok(data.to_vec()),
+            HashOp::Sha256 => Ok(Sha256::digest(data).to_vec()),
+            HashOp::Sha512 => Ok(Sha512::digest(data).to_vec()),
+            HashOp::Sha512_256 => Ok(Sha512Trunc256::digest(data).to_vec()),
+            _ => Err(ProofError::InvalidProof(format!(
+                "unsupported hash op {:?}",
+                self
+            ))),
+        }
+    }
+}
+
+/// How a key or value is length-prefixed before being hashed into a leaf.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LengthOp {
+    #[default]
+    NoPrefix = 0,
+    VarProto = 1,
+    VarRlp = 2,
+    Fixed32Big = 3,
+    Fixed32Little = 4,
+    Fixed64Big = 5,
+    Fixed64Little = 6,
+    Require32Bytes = 7,
+    Require64Bytes = 8,
+}
+
+impl LengthOp {
+    fn from_u64(value: u64) -> Result<LengthOp, String> {
+        Ok(match value {
+            0 => LengthOp::NoPrefix,
+            1 => LengthOp::VarProto,
+            2 => LengthOp::VarRlp,
+            3 => LengthOp::Fixed32Big,
+            4 => LengthOp::Fixed32Little,
+            5 => LengthOp::Fixed64Big,
+            6 => LengthOp::Fixed64Little,
+            7 => LengthOp::Require32Bytes,
+            8 => LengthOp::Require64Bytes,
+            _ => return Err(format!("unknown length op {}", value)),
+        })
+    }
+
+    fn apply(self, data: &[u8]) -> Result<Vec<u8>, ProofError> {
+        let prefix = match self {
+            LengthOp::NoPrefix => Vec::new(),
+            LengthOp::VarProto => {
+                let mut writer = Writer::new();
+                writer.uvarint(data.len() as u64);
+                writer.into_vec()
+            }
+            LengthOp::Fixed32Big => (data.len() as u32).to_be_bytes().to_vec(),
+            LengthOp::Fixed32Little => (data.len() as u32).to_le_bytes().to_vec(),
+            LengthOp::Fixed64Big => (data.len() as u64).to_be_bytes().to_vec(),
+            LengthOp::Fixed64Little => (data.len() as u64).to_le_bytes().to_vec(),
+            LengthOp::Require32Bytes | LengthOp::Require64Bytes => {
+                let required = if self == LengthOp::Require32Bytes {
+                    32
+                } else {
+                    64
+                };
+                if data.len() != required {
+                    return Err(ProofError::InvalidProof(format!(
+                        "data was {} bytes, not {}",
+                        data.len(),
+                        required
+                    )));
+                }
+                Vec::new()
+            }
+            LengthOp::VarRlp => {
+                return Err(ProofError::InvalidProof(format!(
+                    "unsupported length op {:?}",
+                    self
+                )))
+            }
+        };
+        Ok([prefix, data.to_vec()].concat())
+    }
+}
+
+/// Hashes a key and value into a leaf.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LeafOp {
+    pub hash: HashOp,
+    pub prehash_key: HashOp,
+    pub prehash_value: HashOp,
+    pub length: LengthOp,
+    pub prefix: Vec<u8>,
+}
+
+impl LeafOp {
+    pub fn apply(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, ProofError> {
+        if key.is_empty() {
+            return Err(ProofError::InvalidProof(String::from("leaf op needs key")));
+        }
+        if value.is_empty() {
+            return Err(ProofError::InvalidProof(String::from(
+                "leaf op needs value",
+            )));
+        }
+        let key = self.length.apply(&self.prehash_key.apply(key)?)?;
+        let value = self.length.apply(&self.prehash_value.apply(value)?)?;
+        self.hash.apply(&[&self.prefix[..], &key, &value].concat())
+    }
+
+    fn check_against_spec(&self, spec: &ProofSpec) -> Result<(), ProofError> {
+        let leaf_spec = &spec.leaf_spec;
+        let invalid = |reason: String| Err(ProofError::InvalidProof(reason));
+        if self.hash != leaf_spec.hash {
+            return invalid(format!("unexpected leaf hash op {:?}", self.hash));
+        }
+        if self.prehash_key != leaf_spec.prehash_key {
+            return invalid(format!("unexpected prehash key op {:?}", self.prehash_key));
+        }
+        if self.prehash_value != leaf_spec.prehash_value {
+            return invalid(format!(
+                "unexpected prehash value op {:?}",
+                self.prehash_value
+            ));
+        }
+        if self.length != leaf_spec.length {
+            return invalid(format!("unexpected length op {:?}", self.length));
+        }
+        if !self.prefix.starts_with(&leaf_spec.prefix) {
+            return invalid(format!(
+                "leaf prefix doesn't start with {}",
+                to_hex(&leaf_spec.prefix)
+            ));
+        }
+        Ok(())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.varint_field(1, self.hash as u64);
+        writer.varint_field(2, self.prehash_key as u64);
+        writer.varint_field(3, self.prehash_value as u64);
+        writer.varint_field(4, self.length as u64);
+        writer.bytes_field(5, &self.prefix);
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<LeafOp, String> {
+        let mut reader = Reader::new(bytes);
+        let mut op = LeafOp::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => op.hash = HashOp::from_u64(reader.varint_value(field, wire_type)?)?,
+                2 => op.prehash_key = HashOp::from_u64(reader.varint_value(field, wire_type)?)?,
+                3 => op.prehash_value = HashOp::from_u64(reader.varint_value(field, wire_type)?)?,
+                4 => op.length = LengthOp::from_u64(reader.varint_value(field, wire_type)?)?,
+                5 => op.prefix = reader.bytes_value(field, wire_type)?.to_vec(),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(op)
+    }
+}
+
+/// Hashes a child into its parent, with the hashes of the other children in `prefix` and
+/// `suffix`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InnerOp {
+    pub hash: HashOp,
+    pub prefix: Vec<u8>,
+    pub suffix: Vec<u8>,
+}
+
+impl InnerOp {
+    pub fn apply(&self, child: &[u8]) -> Result<Vec<u8>, ProofError> {
+        if child.is_empty() {
+            return Err(ProofError::InvalidProof(String::from(
+                "inner op needs child value",
+            )));
+        }
+        self.hash
+            .apply(&[&self.prefix[..], child, &self.suffix[..]].concat())
+    }
+
+    fn check_against_spec(&self, spec: &ProofSpec) -> Result<(), ProofError> {
+        let inner_spec = &spec.inner_spec;
+        let invalid = |reason: String| Err(ProofError::InvalidProof(reason));
+        if self.hash != inner_spec.hash {
+            return invalid(format!("unexpected inner hash op {:?}", self.hash));
+        }
+        if self.prefix.starts_with(&spec.leaf_spec.prefix) {
+            return invalid(format!(
+                "inner prefix starts with {}",
+                to_hex(&spec.leaf_spec.prefix)
+            ));
+        }
+        if self.prefix.len() < inner_spec.min_prefix_length as usize {
+            return invalid(String::from("inner prefix too short"));
+        }
+        let max_left_child_bytes =
+            (inner_spec.child_order.len().saturating_sub(1)) * inner_spec.child_size as usize;
+        if self.prefix.len() > inner_spec.max_prefix_length as usize + max_left_child_bytes {
+            return invalid(String::from("inner prefix too long"));
+        }
+        Ok(())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.varint_field(1, self.hash as u64);
+        writer.bytes_field(2, &self.prefix);
+        writer.bytes_field(3, &self.suffix);
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<InnerOp, String> {
+        let mut reader = Reader::new(bytes);
+        let mut op = InnerOp::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => op.hash = HashOp::from_u64(reader.varint_value(field, wire_type)?)?,
+                2 => op.prefix = reader.bytes_value(field, wire_type)?.to_vec(),
+                3 => op.suffix = reader.bytes_value(field, wire_type)?.to_vec(),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(op)
+    }
+}
+
+/// The shape of the inner nodes of a tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InnerSpec {
+    /// The order in which the children of a node are hashed, e.g. `[0, 1]` for left then right.
+    pub child_order: Vec<i32>,
+    pub child_size: i32,
+    pub min_prefix_length: i32,
+    pub max_prefix_length: i32,
+    pub hash: HashOp,
+}
+
+impl InnerSpec {
+    /// The bounds of the prefix length and the suffix length of a node whose child on the path
+    /// is `branch`.
+    fn padding(&self, branch: i32) -> Option<(usize, usize, usize)> {
+        let position = self.child_order.iter().position(|&b| b == branch)?;
+        let prefix = position * self.child_size as usize;
+        let suffix = (self.child_order.len() - 1 - position) * self.child_size as usize;
+        Some((
+            prefix + self.min_prefix_length as usize,
+            prefix + self.max_prefix_length as usize,
+            suffix,
+        ))
+    }
+
+    fn has_padding(&self, op: &InnerOp, branch: i32) -> bool {
+        match self.padding(branch) {
+            Some((min_prefix, max_prefix, suffix)) => {
+                op.prefix.len() >= min_prefix
+                    && op.prefix.len() <= max_prefix
+                    && op.suffix.len() == suffix
+            }
+            None => false,
+        }
+    }
+
+    /// The branch a path goes down through `op`.
+    fn branch(&self, op: &InnerOp) -> Option<i32> {
+        (0..self.child_order.len() as i32).find(|&branch| self.has_padding(op, branch))
+    }
+
+    fn is_left_most(&self, path: &[InnerOp]) -> bool {
+        path.iter().all(|op| self.has_padding(op, 0))
+    }
+
+    fn is_right_most(&self, path: &[InnerOp]) -> bool {
+        let last = self.child_order.len() as i32 - 1;
+        path.iter().all(|op| self.has_padding(op, last))
+    }
+
+    /// Whether `left` and `right`, leaf first, are the paths of adjacent leaves.
+    fn is_left_neighbor(&self, mut left: &[InnerOp], mut right: &[InnerOp]) -> bool {
+        loop {
+            match (left.split_last(), right.split_last()) {
+                (Some((top_left, rest_left)), Some((top_right, rest_right))) => {
+                    left = rest_left;
+                    right = rest_right;
+                    if top_left.prefix == top_right.prefix && top_left.suffix == top_right.suffix {
+                        continue;
+                    }
+                    let adjacent = match (self.branch(top_left), self.branch(top_right)) {
+                        (Some(l), Some(r)) => r == l + 1,
+                        _ => false,
+                    };
+                    return adjacent && self.is_right_most(left) && self.is_left_most(right);
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// What an ICS-23 proof must look like to be accepted for a tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProofSpec {
+    pub leaf_spec: LeafOp,
+    pub inner_spec: InnerSpec,
+    /// The maximum number of inner ops, or zero for no limit.
+    pub max_depth: i32,
+    pub min_depth: i32,
+}
+
+/// The spec of IAVL trees, for `ics23:iavl` ops.
+pub fn iavl_spec() -> ProofSpec {
+    ProofSpec {
+        leaf_spec: LeafOp {
+            hash: HashOp::Sha256,
+            prehash_key: HashOp::NoHash,
+            prehash_value: HashOp::Sha256,
+            length: LengthOp::VarProto,
+            prefix: vec![0],
+        },
+        inner_spec: InnerSpec {
+            child_order: vec![0, 1],
+            child_size: 33,
+            min_prefix_length: 4,
+            max_prefix_length: 12,
+            hash: HashOp::Sha256,
+        },
+        max_depth: 0,
+        min_depth: 0,
+    }
+}
+
+/// The spec of Tendermint's simple Merkle trees over `SimpleValueOp` leaves, for `ics23:simple`
+/// ops.
+pub fn tendermint_spec() -> ProofSpec {
+    ProofSpec {
+        leaf_spec: LeafOp {
+            hash: HashOp::Sha256,
+            prehash_key: HashOp::NoHash,
+            prehash_value: HashOp::Sha256,
+            length: LengthOp::VarProto,
+            prefix: vec![0],
+        },
+        inner_spec: InnerSpec {
+            child_order: vec![0, 1],
+            child_size: 32,
+            min_prefix_length: 1,
+            max_prefix_length: 1,
+            hash: HashOp::Sha256,
+        },
+        max_depth: 0,
+        min_depth: 0,
+    }
+}
+
+/// Proves that `value` is stored under `key`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExistenceProof {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub leaf: LeafOp,
+    /// The inner ops from the leaf up to the root.
+    pub path: Vec<InnerOp>,
+}
+
+impl ExistenceProof {
+    /// The root the proof leads to.
+    pub fn calculate(&self) -> Result<Vec<u8>, ProofError> {
+        let leaf = self.leaf.apply(&self.key, &self.value)?;
+        self.path.iter().try_fold(leaf, |hash, op| op.apply(&hash))
+    }
+
+    /// Checks that the proof fits `spec` and shows `value` stored under `key` under `root`.
+    pub fn verify(
+        &self,
+        spec: &ProofSpec,
+        root: &[u8],
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), ProofError> {
+        self.check_against_spec(spec)?;
+        if key != self.key.as_slice() {
+            return Err(ProofError::InvalidProof(format!(
+                "provided key doesn't match proof: {}",
+                to_hex(key)
+            )));
+        }
+        if value != self.value.as_slice() {
+            return Err(ProofError::InvalidProof(format!(
+                "provided value doesn't match proof: {}",
+                to_hex(value)
+            )));
+        }
+        let calculated = self.calculate()?;
+        if calculated != root {
+            return Err(ProofError::RootMismatch {
+                expected: root.to_vec(),
+                actual: calculated,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_against_spec(&self, spec: &ProofSpec) -> Result<(), ProofError> {
+        self.leaf.check_against_spec(spec)?;
+        if spec.min_depth > 0 && self.path.len() < spec.min_depth as usize {
+            return Err(ProofError::InvalidProof(format!(
+                "inner op depth too short: {}",
+                self.path.len()
+            )));
+        }
+        if spec.max_depth > 0 && self.path.len() > spec.max_depth as usize {
+            return Err(ProofError::InvalidProof(format!(
+                "inner op depth too long: {}",
+                self.path.len()
+            )));
+        }
+        self.path
+            .iter()
+            .try_for_each(|op| op.check_against_spec(spec))
+    }
+
+    /// The existence proof of item `proof.index` of a simple tree of `SimpleValueOp` leaves,
+    /// which is `value` stored under `key`. `None` if the aunts do not fit the index and total.
+    pub fn from_simple_proof(
+        key: &[u8],
+        value: &[u8],
+        proof: &SimpleProof,
+    ) -> Option<ExistenceProof> {
+        if proof.index < 0 || proof.total <= 0 || proof.index >= proof.total {
+            return None;
+        }
+        let mut path = Vec::new();
+        simple_path(
+            proof.index as usize,
+            proof.total as usize,
+            &proof.aunts,
+            &mut path,
+        )?;
+        Some(ExistenceProof {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            leaf: tendermint_spec().leaf_spec,
+            path,
+        })
+    }
+
+    /// The existence proof of the only leaf of an IAVL range proof, whose value is `value`.
+    /// `None` if the range has more than one leaf.
+    pub fn from_iavl_range_proof(proof: &RangeProof, value: &[u8]) -> Option<ExistenceProof> {
+        if proof.leaves.len() != 1 || !proof.inner_nodes.is_empty() {
+            return None;
+        }
+        let leaf = &proof.leaves[0];
+        let mut prefix = Writer::new();
+        prefix.svarint(0);
+        prefix.svarint(1);
+        prefix.svarint(leaf.version);
+        let path = proof
+            .left_path
+            .iter()
+            .rev()
+            .map(|node| {
+                let mut prefix = Writer::new();
+                prefix.svarint(i64::from(node.height));
+                prefix.svarint(node.size);
+                prefix.svarint(node.version);
+                let mut suffix = Writer::new();
+                if node.left.is_empty() {
+                    prefix.uvarint(32);
+                    suffix.byte_slice(&node.right);
+                } else {
+                    prefix.byte_slice(&node.left);
+                    prefix.uvarint(32);
+                }
+                InnerOp {
+                    hash: HashOp::Sha256,
+                    prefix: prefix.into_vec(),
+                    suffix: suffix.into_vec(),
+                }
+            })
+            .collect();
+        Some(ExistenceProof {
+            key: leaf.key.clone(),
+            value: value.to_vec(),
+            leaf: LeafOp {
+                prefix: prefix.into_vec(),
+                ..iavl_spec().leaf_spec
+            },
+            path,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.bytes_field(1, &self.key);
+        writer.bytes_field(2, &self.value);
+        writer.repeated_bytes_field(3, &self.leaf.encode());
+        for op in &self.path {
+            writer.repeated_bytes_field(4, &op.encode());
+        }
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<ExistenceProof, String> {
+        let mut reader = Reader::new(bytes);
+        let mut proof = ExistenceProof::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => proof.key = reader.bytes_value(field, wire_type)?.to_vec(),
+                2 => proof.value = reader.bytes_value(field, wire_type)?.to_vec(),
+                3 => proof.leaf = LeafOp::decode(reader.bytes_value(field, wire_type)?)?,
+                4 => proof
+                    .path
+                    .push(InnerOp::decode(reader.bytes_value(field, wire_type)?)?),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(proof)
+    }
+}
+
+/// Pushes the inner ops of a simple tree proof onto `path`, leaf first.
+fn simple_path(
+    index: usize,
+    total: usize,
+    aunts: &[Vec<u8>],
+    path: &mut Vec<InnerOp>,
+) -> Option<()> {
+    if total == 1 {
+        return if aunts.is_empty() { Some(()) } else { None };
+    }
+    let (last, rest) = aunts.split_last()?;
+    let k = simple::split_point(total);
+    let (prefix, suffix) = if index < k {
+        simple_path(index, k, rest, path)?;
+        (vec![1], last.clone())
+    } else {
+        simple_path(index - k, total - k, rest, path)?;
+        ([&[1][..], last].concat(), Vec::new())
+    };
+    path.push(InnerOp {
+        hash: HashOp::Sha256,
+        prefix,
+        suffix,
+    });
+    Some(())
+}
+
+/// Proves that nothing is stored under `key`, with the existence proofs of the keys around it.
+/// At the ends of the tree, one of them is missing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NonExistenceProof {
+    pub key: Vec<u8>,
+    pub left: Option<ExistenceProof>,
+    pub right: Option<ExistenceProof>,
+}
+
+impl NonExistenceProof {
+    /// Checks that the neighbours are in the tree under `root`, around `key` and adjacent.
+    pub fn verify(&self, spec: &ProofSpec, root: &[u8], key: &[u8]) -> Result<(), ProofError> {
+        let invalid = |reason: &str| Err(ProofError::InvalidProof(reason.to_string()));
+        for neighbour in self.left.iter().chain(&self.right) {
+            neighbour.verify(spec, root, &neighbour.key, &neighbour.value)?;
+        }
+        if let Some(right) = &self.right {
+            if key.cmp(&right.key) != Ordering::Less {
+                return invalid("key is not left of right proof");
+            }
+        }
+        if let Some(left) = &self.left {
+            if key.cmp(&left.key) != Ordering::Greater {
+                return invalid("key is not right of left proof");
+            }
+        }
+        let inner_spec = &spec.inner_spec;
+        match (&self.left, &self.right) {
+            (None, None) => invalid("both left and right proofs missing"),
+            (None, Some(right)) if !inner_spec.is_left_most(&right.path) => {
+                invalid("left proof missing, right proof must be left-most")
+            }
+            (Some(left), None) if !inner_spec.is_right_most(&left.path) => {
+                invalid("right proof missing, left proof must be right-most")
+            }
+            (Some(left), Some(right)) if !inner_spec.is_left_neighbor(&left.path, &right.path) => {
+                invalid("left and right proofs are not adjacent")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.bytes_field(1, &self.key);
+        if let Some(left) = &self.left {
+            writer.repeated_bytes_field(2, &left.encode());
+        }
+        if let Some(right) = &self.right {
+            writer.repeated_bytes_field(3, &right.encode());
+        }
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<NonExistenceProof, String> {
+        let mut reader = Reader::new(bytes);
+        let mut proof = NonExistenceProof::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => proof.key = reader.bytes_value(field, wire_type)?.to_vec(),
+                2 => {
+                    let bytes = reader.bytes_value(field, wire_type)?;
+                    proof.left = Some(ExistenceProof::decode(bytes)?);
+                }
+                3 => {
+                    let bytes = reader.bytes_value(field, wire_type)?;
+                    proof.right = Some(ExistenceProof::decode(bytes)?);
+                }
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(proof)
+    }
+}
+
+/// An ICS-23 `CommitmentProof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitmentProof {
+    Exist(ExistenceProof),
+    Nonexist(NonExistenceProof),
+}
+
+impl CommitmentProof {
+    /// The root the proof leads to.
+    pub fn calculate(&self) -> Result<Vec<u8>, ProofError> {
+        match self {
+            CommitmentProof::Exist(proof) => proof.calculate(),
+            CommitmentProof::Nonexist(proof) => {
+                match proof.left.as_ref().or(proof.right.as_ref()) {
+                    Some(neighbour) => neighbour.calculate(),
+                    None => Err(ProofError::InvalidProof(String::from(
+                        "non-existence proof has no neighbours",
+                    ))),
+                }
+            }
+        }
+    }
+
+    /// The protobuf encoding of the proof.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        match self {
+            CommitmentProof::Exist(proof) => writer.repeated_bytes_field(1, &proof.encode()),
+            CommitmentProof::Nonexist(proof) => writer.repeated_bytes_field(2, &proof.encode()),
+        }
+        writer.into_vec()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<CommitmentProof, String> {
+        let mut reader = Reader::new(bytes);
+        let mut proof = None;
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => {
+                    let bytes = reader.bytes_value(field, wire_type)?;
+                    proof = Some(CommitmentProof::Exist(ExistenceProof::decode(bytes)?));
+                }
+                2 => {
+                    let bytes = reader.bytes_value(field, wire_type)?;
+                    proof = Some(CommitmentProof::Nonexist(NonExistenceProof::decode(bytes)?));
+                }
+                3 | 4 => return Err(String::from("batch proofs are not supported")),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        proof.ok_or_else(|| String::from("empty commitment proof"))
+    }
+}
+
+/// Proves a value, or the absence of a value, under a key of the tree its type names.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentOp {
+    pub op_type: String,
+    pub spec: ProofSpec,
+    pub key: Vec<u8>,
+    pub proof: CommitmentProof,
+}
+
+impl CommitmentOp {
+    /// An op over an IAVL tree.
+    pub fn iavl(key: Vec<u8>, proof: CommitmentProof) -> CommitmentOp {
+        CommitmentOp {
+            op_type: PROOF_OP_ICS23_IAVL.to_string(),
+            spec: iavl_spec(),
+            key,
+            proof,
+        }
+    }
+
+    /// An op over a simple Merkle tree.
+    pub fn simple(key: Vec<u8>, proof: CommitmentProof) -> CommitmentOp {
+        CommitmentOp {
+            op_type: PROOF_OP_ICS23_SIMPLE.to_string(),
+            spec: tendermint_spec(),
+            key,
+            proof,
+        }
+    }
+
+    /// Decodes an op of type `ics23:iavl` or `ics23:simple`. Registered with
+    /// `ProofRuntime::register_op_decoder`.
+    pub fn decode(op: &ProofOp) -> Result<Box<dyn ProofOperator>, ProofError> {
+        let invalid = |reason: String| ProofError::InvalidOp {
+            op_type: op.get_field_type().to_string(),
+            reason,
+        };
+        let proof = CommitmentProof::decode(op.get_data()).map_err(invalid)?;
+        let key = op.get_key().to_vec();
+        match op.get_field_type() {
+            PROOF_OP_ICS23_IAVL => Ok(Box::new(CommitmentOp::iavl(key, proof))),
+            PROOF_OP_ICS23_SIMPLE => Ok(Box::new(CommitmentOp::simple(key, proof))),
+            _ => Err(invalid(format!(
+                "expected type {} or {}",
+                PROOF_OP_ICS23_IAVL, PROOF_OP_ICS23_SIMPLE
+            ))),
+        }
+    }
+}
+
+impl ProofOperator for CommitmentOp {
+    fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+        let root = self.proof.calculate()?;
+        match (args.len(), &self.proof) {
+            (0, CommitmentProof::Nonexist(proof)) => proof.verify(&self.spec, &root, &self.key)?,
+            (1, CommitmentProof::Exist(proof)) => {
+                proof.verify(&self.spec, &root, &self.key, &args[0])?
+            }
+            (0, _) | (1, _) => {
+                return Err(ProofError::InvalidProof(format!(
+                    "expected a {} proof",
+                    if args.is_empty() {
+                        "non-existence"
+                    } else {
+                        "existence"
+                    }
+                )))
+            }
+            (len, _) => {
+                return Err(ProofError::InvalidProof(format!(
+                    "expected 0 or 1 args, got {}",
+                    len
+                )))
+            }
+        }
+        Ok(vec![root])
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn proof_op(&self) -> ProofOp {
+        let mut op = ProofOp::new();
+        op.set_field_type(self.op_type.clone());
+        op.set_key(self.key.clone());
+        op.set_data(self.proof.encode());
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::iavl::{ProofInnerNode, ProofLeafNode};
+    use crate::merkle::simple::{sha256, SimpleValueOp};
+    use crate::merkle::ProofRuntime;
+    use crate::messages::merkle::Proof;
+
+    /// A simple tree over `a = 1`, `c = 3` and `e = 5`, with an existence proof of each key.
+    fn simple_tree() -> (Vec<u8>, Vec<ExistenceProof>) {
+        let pairs: Vec<(&[u8], &[u8])> = vec![(b"a", b"1"), (b"c", b"3"), (b"e", b"5")];
+        let leaves: Vec<Vec<u8>> = pairs
+            .iter()
+            .map(|(key, value)| SimpleValueOp::leaf(key, value))
+            .collect();
+        let (root, proofs) = simple::proofs_from_byte_slices(&leaves);
+        let proofs = pairs
+            .iter()
+            .zip(&proofs)
+            .map(|((key, value), proof)| {
+                ExistenceProof::from_simple_proof(key, value, proof).unwrap()
+            })
+            .collect();
+        (root, proofs)
+    }
+
+    fn non_existence(
+        key: &[u8],
+        left: Option<&ExistenceProof>,
+        right: Option<&ExistenceProof>,
+    ) -> NonExistenceProof {
+        NonExistenceProof {
+            key: key.to_vec(),
+            left: left.cloned(),
+            right: right.cloned(),
+        }
+    }
+
+    #[test]
+    fn should_match_simple_tree_roots() {
+        let (root, proofs) = simple_tree();
+        let spec = tendermint_spec();
+        for proof in &proofs {
+            assert_eq!(proof.calculate().unwrap(), root);
+            proof
+                .verify(&spec, &root, &proof.key, &proof.value)
+                .unwrap();
+        }
+        assert!(proofs[0].verify(&spec, &root, b"a", b"2").is_err());
+        assert!(proofs[0].verify(&iavl_spec(), &root, b"a", b"1").is_err());
+
+        let mut leaf_as_inner = proofs[1].clone();
+        leaf_as_inner.path[0].prefix = vec![0];
+        assert!(leaf_as_inner.verify(&spec, &root, b"c", b"3").is_err());
+    }
+
+    #[test]
+    fn should_verify_non_existence_between_neighbours() {
+        let (root, proofs) = simple_tree();
+        let spec = tendermint_spec();
+        let (a, c, e) = (&proofs[0], &proofs[1], &proofs[2]);
+
+        non_existence(b"b", Some(a), Some(c))
+            .verify(&spec, &root, b"b")
+            .unwrap();
+        non_existence(b"d", Some(c), Some(e))
+            .verify(&spec, &root, b"d")
+            .unwrap();
+        non_existence(b"0", None, Some(a))
+            .verify(&spec, &root, b"0")
+            .unwrap();
+        non_existence(b"f", Some(e), None)
+            .verify(&spec, &root, b"f")
+            .unwrap();
+
+        assert!(non_existence(b"b", Some(a), Some(e))
+            .verify(&spec, &root, b"b")
+            .is_err());
+        assert!(non_existence(b"c", Some(a), Some(e))
+            .verify(&spec, &root, b"c")
+            .is_err());
+        assert!(non_existence(b"b", None, Some(c))
+            .verify(&spec, &root, b"b")
+            .is_err());
+        assert!(non_existence(b"d", Some(c), None)
+            .verify(&spec, &root, b"d")
+            .is_err());
+        assert!(non_existence(b"b", None, None)
+            .verify(&spec, &root, b"b")
+            .is_err());
+    }
+
+    #[test]
+    fn should_match_iavl_roots() {
+        let a = ProofLeafNode {
+            key: b"a".to_vec(),
+            value_hash: sha256(b"1"),
+            version: 1,
+        };
+        let b = ProofLeafNode {
+            key: b"b".to_vec(),
+            value_hash: sha256(b"2"),
+            version: 1,
+        };
+        let node = |left: Vec<u8>, right: Vec<u8>| ProofInnerNode {
+            height: 1,
+            size: 2,
+            version: 1,
+            left,
+            right,
+        };
+        let range = |left_path, leaf| RangeProof {
+            left_path: vec![left_path],
+            inner_nodes: Vec::new(),
+            leaves: vec![leaf],
+        };
+        let first = range(node(Vec::new(), b.hash()), a.clone());
+        let last = range(node(a.hash(), Vec::new()), b);
+        let root = first.compute_root_hash().unwrap();
+
+        let spec = iavl_spec();
+        let a = ExistenceProof::from_iavl_range_proof(&first, b"1").unwrap();
+        let b = ExistenceProof::from_iavl_range_proof(&last, b"2").unwrap();
+        a.verify(&spec, &root, b"a", b"1").unwrap();
+        b.verify(&spec, &root, b"b", b"2").unwrap();
+        assert!(b.verify(&tendermint_spec(), &root, b"b", b"2").is_err());
+
+        non_existence(b"ab", Some(&a), Some(&b))
+            .verify(&spec, &root, b"ab")
+            .unwrap();
+        non_existence(b"c", Some(&b), None)
+            .verify(&spec, &root, b"c")
+            .unwrap();
+        assert!(non_existence(b"c", Some(&a), None)
+            .verify(&spec, &root, b"c")
+            .is_err());
+    }
+
+    #[test]
+    fn should_run_commitment_ops() {
+        let (root, proofs) = simple_tree();
+        let mut proof = Proof::new();
+        proof.mut_ops().push(
+            CommitmentOp::simple(b"c".to_vec(), CommitmentProof::Exist(proofs[1].clone()))
+                .proof_op(),
+        );
+        let runtime = ProofRuntime::with_cosmos_ops();
+        runtime.verify_value(&proof, &root, "/c", b"3").unwrap();
+        assert!(runtime.verify_value(&proof, &root, "/c", b"4").is_err());
+        assert!(runtime.verify_absence(&proof, &root, "/c").is_err());
+        let decoded = runtime.decode(&proof).unwrap();
+        assert_eq!(decoded[0].proof_op(), proof.get_ops()[0]);
+
+        let absence =
+            CommitmentProof::Nonexist(non_existence(b"b", Some(&proofs[0]), Some(&proofs[1])));
+        let mut proof = Proof::new();
+        proof
+            .mut_ops()
+            .push(CommitmentOp::simple(b"b".to_vec(), absence.clone()).proof_op());
+        runtime.verify_absence(&proof, &root, "/b").unwrap();
+        assert_eq!(CommitmentProof::decode(&absence.encode()), Ok(absence));
+
+        let mut op = proof.get_ops()[0].clone();
+        op.set_field_type(PROOF_OP_ICS23_IAVL.to_string());
+        assert!(CommitmentOp::decode(&op).unwrap().run(Vec::new()).is_err());
+        assert!(CommitmentProof::decode(&[0x1A, 0]).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Keypaths such as `/bank/x:0A0B`, the paths of keys through nested stores that proofs are
+//! verified against and that `RequestQuery.path` routes on. They format and parse like Go's
+//! `merkle.KeyPath`: each key is either URL-escaped or, after an `x:` prefix, hex encoded.
+//!
+//! ```rust
+//! use abci::merkle::{KeyEncoding, KeyPath};
+//!
+//! let path = KeyPath::new()
+//!     .append_key(b"bank".to_vec(), KeyEncoding::Url)
+//!     .append_key(vec![0xDE, 0xAD], KeyEncoding::Hex);
+//! assert_eq!(path.to_string(), "/bank/x:DEAD");
+//! assert_eq!("/bank/x:dead".parse::<KeyPath>().unwrap(), path);
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+use super::{to_hex, ProofError};
+use crate::messages::abci::RequestQuery;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEncoding {
+    Url,
+    Hex,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub name: Vec<u8>,
+    pub encoding: KeyEncoding,
+}
+
+/// The keys of a keypath, outermost first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyPath {
+    keys: Vec<Key>,
+}
+
+impl KeyPath {
+    pub fn new() -> KeyPath {
+        KeyPath::default()
+    }
+
+    pub fn append_key(mut self, name: Vec<u8>, encoding: KeyEncoding) -> KeyPath {
+        self.keys.push(Key { name, encoding });
+        self
+    }
+
+    pub fn keys(&self) -> &[Key] {
+        &self.keys
+    }
+
+    /// The names of the keys, outermost first.
+    pub fn names(&self) -> Vec<&[u8]> {
+        self.keys.iter().map(|key| &key.name[..]).collect()
+    }
+
+    pub fn into_names(self) -> Vec<Vec<u8>> {
+        self.keys.into_iter().map(|key| key.name).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Formats the keypath as Go does, except that a URL-escaped key starting with `x:` has its
+/// colon escaped, so that it does not parse back as hex. An empty keypath formats as an empty
+/// string, which does not parse.
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for key in &self.keys {
+            match key.encoding {
+                KeyEncoding::Url => write!(f, "/{}", path_escape(&key.name))?,
+                KeyEncoding::Hex => write!(f, "/x:{}", to_hex(&key.name))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for KeyPath {
+    type Err = ProofError;
+
+    fn from_str(path: &str) -> Result<KeyPath, ProofError> {
+        let parts = path.strip_prefix('/').ok_or_else(|| {
+            ProofError::InvalidKeyPath(String::from(
+                "key path string must start with a forward slash '/'",
+            ))
+        })?;
+        let mut key_path = KeyPath::new();
+        for (i, part) in parts.split('/').enumerate() {
+            let (name, encoding) = match part.strip_prefix("x:") {
+                Some(hex) => (from_hex(hex), KeyEncoding::Hex),
+                None => (path_unescape(part), KeyEncoding::Url),
+            };
+            let name = name.ok_or_else(|| {
+                ProofError::InvalidKeyPath(format!("decoding part #{}: /{}", i, part))
+            })?;
+            key_path = key_path.append_key(name, encoding);
+        }
+        Ok(key_path)
+    }
+}
+
+impl RequestQuery {
+    /// Parses `path` as a keypath, for applications that route queries on its keys.
+    pub fn key_path(&self) -> Result<KeyPath, ProofError> {
+        self.get_path().parse()
+    }
+}
+
+/// Escapes a key like Go's `url.PathEscape`. A leading `x:` is escaped too.
+fn path_escape(key: &[u8]) -> String {
+    let mut part = String::with_capacity(key.len());
+    for (i, &b) in key.iter().enumerate() {
+        let hex_prefix = i == 1 && key[0] == b'x' && b == b':';
+        if !hex_prefix && (b.is_ascii_alphanumeric() || b"-_.~$&+=:@".contains(&b)) {
+            part.push(b as char);
+        } else {
+            part.push_str(&format!("%{:02X}", b));
+        }
+    }
+    part
+}
+
+fn path_unescape(part: &str) -> Option<Vec<u8>> {
+    let bytes = part.as_bytes();
+    let mut key = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            key.push(from_hex(part.get(i + 1..i + 3)?)?[0]);
+            i += 3;
+        } else {
+            key.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(key)
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 == 1 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_round_trip_every_byte() {
+        let all: Vec<u8> = (0..=255).collect();
+        for encoding in &[KeyEncoding::Url, KeyEncoding::Hex] {
+            let path = KeyPath::new()
+                .append_key(all.clone(), *encoding)
+                .append_key(b"x:0a".to_vec(), *encoding)
+                .append_key(Vec::new(), *encoding);
+            assert_eq!(path.to_string().parse::<KeyPath>().unwrap(), path);
+        }
+    }
+
+    #[test]
+    fn should_format_like_go() {
+        let path = KeyPath::new()
+            .append_key(b"a b/c+d:e,f;g".to_vec(), KeyEncoding::Url)
+            .append_key(vec![0xDE, 0xAD, 0xBE, 0xEF], KeyEncoding::Hex)
+            .append_key(b"x:0a".to_vec(), KeyEncoding::Url);
+        assert_eq!(path.to_string(), "/a%20b%2Fc+d:e%2Cf%3Bg/x:DEADBEEF/x%3A0a");
+        assert_eq!(KeyPath::new().to_string(), "");
+
+        let parsed: KeyPath = "/x:deadbeef/store".parse().unwrap();
+        assert_eq!(
+            parsed.names(),
+            vec![&[0xDE, 0xAD, 0xBE, 0xEF][..], b"store"]
+        );
+        assert_eq!(parsed.keys()[0].encoding, KeyEncoding::Hex);
+        assert_eq!(parsed.to_string(), "/x:DEADBEEF/store");
+        assert_eq!(
+            "/".parse::<KeyPath>().unwrap().into_names(),
+            vec![Vec::<u8>::new()]
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_key_paths() {
+        for path in &["", "store", "/x:0", "/x:+1", "/%", "/%2", "/%zz", "/%+1"] {
+            assert!(path.parse::<KeyPath>().is_err(), "{:?}", path);
+        }
+        assert_eq!(
+            "/ok/x:0g".parse::<KeyPath>(),
+            Err(ProofError::InvalidKeyPath(String::from(
+                "decoding part #1: /x:0g"
+            )))
+        );
+    }
+
+    #[test]
+    fn should_parse_query_paths() {
+        let mut request = RequestQuery::new();
+        request.set_path(String::from("/store/bank/key"));
+        let path = request.key_path().unwrap();
+        match &path.names()[..] {
+            [b"store", store, b"key"] => assert_eq!(store, b"bank"),
+            other => panic!("unexpected path {:?}", other),
+        }
+        request.set_path(String::from("store"));
+        assert!(request.key_path().is_err());
+    }
+}
@@ -0,0 +1,543 @@
+//! Verification of the Merkle proofs in `ResponseQuery.proof`, mirroring Tendermint's
+//! `ProofRuntime`.
+//!
+//! A `Proof` is a chain of `ProofOp`s. Each op is decoded by the decoder registered for its
+//! `type`, and the resulting operators are run in order, from the value at the leaf up to the
+//! root the proof commits to. Ops with a key consume the keypath from its last part, so a value
+//! stored under `key` in the `bank` store of a multistore is proven with keypath `/bank/key`.
+//! Query handlers assemble such proofs with `ProofBuilder`.
+//!
+//! ```rust
+//! use abci::merkle::{ProofError, ProofOperator, ProofRuntime};
+//! use abci::ProofOp;
+//!
+//! /// A toy op whose root is its key followed by the value.
+//! struct Prefix(Vec<u8>);
+//!
+//! impl ProofOperator for Prefix {
+//!     fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+//!         Ok(vec![[&self.0[..], &args[0][..]].concat()])
+//!     }
+//!
+//!     fn key(&self) -> &[u8] {
+//!         &self.0
+//!     }
+//!
+//!     fn proof_op(&self) -> ProofOp {
+//!         let mut op = ProofOp::new();
+//!         op.set_field_type(String::from("prefix"));
+//!         op.set_key(self.0.clone());
+//!         op
+//!     }
+//! }
+//!
+//! let mut runtime = ProofRuntime::new();
+//! runtime
+//!     .register_op_decoder("prefix", |op| Ok(Box::new(Prefix(op.get_key().to_vec()))))
+//!     .unwrap();
+//! let mut proof = abci::Proof::new();
+//! proof.mut_ops().push(Prefix(b"key".to_vec()).proof_op());
+//! runtime.verify_value(&proof, b"keyvalue", "/key", b"value").unwrap();
+//! ```
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::messages::abci::ResponseQuery;
+use crate::messages::merkle::{Proof, ProofOp};
+
+pub(crate) mod amino;
+pub mod iavl;
+pub mod ics23;
+mod key_path;
+pub mod multistore;
+pub mod simple;
+
+pub use self::key_path::{Key, KeyEncoding, KeyPath};
+
+/// A decoded `ProofOp`.
+pub trait ProofOperator {
+    /// Computes the op's output from the outputs of the previous op, or from the proven value
+    /// (or nothing, for absence proofs) for the first op. The last op must output the root.
+    fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError>;
+
+    /// The keypath part the op proves, or an empty slice if it does not consume one.
+    fn key(&self) -> &[u8];
+
+    /// Encodes the operator back into a `ProofOp`.
+    fn proof_op(&self) -> ProofOp;
+}
+
+/// Turns a `ProofOp` of the type it is registered for into an operator.
+pub type OpDecoder = fn(&ProofOp) -> Result<Box<dyn ProofOperator>, ProofError>;
+
+/// Why a proof could not be decoded or did not verify.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    /// A decoder is already registered for the type.
+    DuplicateDecoder(String),
+    /// No decoder is registered for the type of an op.
+    UnknownOpType(String),
+    /// An op could not be decoded.
+    InvalidOp {
+        op_type: String,
+        reason: String,
+    },
+    /// An operator rejected its input, e.g. a value that does not hash to the proven leaf.
+    InvalidProof(String),
+    InvalidKeyPath(String),
+    /// The op at `index` has a key but the keypath has no parts left.
+    KeyPathExhausted {
+        index: usize,
+        key: Vec<u8>,
+    },
+    /// The op at `index` proves a different key than the keypath's.
+    KeyMismatch {
+        index: usize,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// The op at `index` failed.
+    Operation {
+        index: usize,
+        error: Box<ProofError>,
+    },
+    /// The ops did not compute the expected root.
+    RootMismatch {
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// Parts of the keypath were not proven by any op.
+    UnconsumedKeys(usize),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::DuplicateDecoder(op_type) => {
+                write!(f, "a decoder for {:?} is already registered", op_type)
+            }
+            ProofError::UnknownOpType(op_type) => {
+                write!(f, "unrecognized proof op type {:?}", op_type)
+            }
+            ProofError::InvalidOp { op_type, reason } => {
+                write!(f, "decoding {:?} proof op: {}", op_type, reason)
+            }
+            ProofError::InvalidProof(reason) => write!(f, "invalid proof: {}", reason),
+            ProofError::InvalidKeyPath(reason) => write!(f, "invalid key path: {}", reason),
+            ProofError::KeyPathExhausted { index, key } => write!(
+                f,
+                "key path has no part left for key {:?} of operation #{}",
+                String::from_utf8_lossy(key),
+                index
+            ),
+            ProofError::KeyMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "key mismatch on operation #{}: expected {:?} but got {:?}",
+                index,
+                String::from_utf8_lossy(expected),
+                String::from_utf8_lossy(actual)
+            ),
+            ProofError::Operation { index, error } => {
+                write!(f, "operation #{} failed: {}", index, error)
+            }
+            ProofError::RootMismatch { expected, actual } => write!(
+                f,
+                "calculated root hash is invalid: expected {} but got {}",
+                to_hex(expected),
+                to_hex(actual)
+            ),
+            ProofError::UnconsumedKeys(count) => {
+                write!(f, "{} key path parts were not consumed", count)
+            }
+        }
+    }
+}
+
+impl Error for ProofError {}
+
+/// Decoders for the op types an application accepts, keyed by `ProofOp.type`.
+#[derive(Clone, Default)]
+pub struct ProofRuntime {
+    decoders: HashMap<String, OpDecoder>,
+}
+
+impl fmt::Debug for ProofRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut types: Vec<&String> = self.decoders.keys().collect();
+        types.sort();
+        f.debug_struct("ProofRuntime")
+            .field("decoders", &types)
+            .finish()
+    }
+}
+
+impl ProofRuntime {
+    pub fn new() -> ProofRuntime {
+        ProofRuntime::default()
+    }
+
+    /// A runtime with the op types Tendermint registers by default, i.e. `simple:v`.
+    pub fn with_default_ops() -> ProofRuntime {
+        let mut runtime = ProofRuntime::new();
+        runtime
+            .register_op_decoder(simple::PROOF_OP_SIMPLE_VALUE, simple::SimpleValueOp::decode)
+            .unwrap();
+        runtime
+    }
+
+    /// A runtime with the default ops and those of Cosmos SDK store queries: `iavl:v`, `iavl:a`,
+    /// `multistore`, `ics23:iavl` and `ics23:simple`.
+    pub fn with_cosmos_ops() -> ProofRuntime {
+        let mut runtime = ProofRuntime::with_default_ops();
+        runtime
+            .register_op_decoder(iavl::PROOF_OP_IAVL_VALUE, iavl::IavlValueOp::decode)
+            .unwrap();
+        runtime
+            .register_op_decoder(iavl::PROOF_OP_IAVL_ABSENCE, iavl::IavlAbsenceOp::decode)
+            .unwrap();
+        runtime
+            .register_op_decoder(
+                multistore::PROOF_OP_MULTISTORE,
+                multistore::MultiStoreProofOp::decode,
+            )
+            .unwrap();
+        runtime
+            .register_op_decoder(ics23::PROOF_OP_ICS23_IAVL, ics23::CommitmentOp::decode)
+            .unwrap();
+        runtime
+            .register_op_decoder(ics23::PROOF_OP_ICS23_SIMPLE, ics23::CommitmentOp::decode)
+            .unwrap();
+        runtime
+    }
+
+    /// Registers `decoder` for ops of type `op_type`. Fails if the type already has one.
+    pub fn register_op_decoder(
+        &mut self,
+        op_type: &str,
+        decoder: OpDecoder,
+    ) -> Result<(), ProofError> {
+        if self.decoders.contains_key(op_type) {
+            return Err(ProofError::DuplicateDecoder(op_type.to_string()));
+        }
+        self.decoders.insert(op_type.to_string(), decoder);
+        Ok(())
+    }
+
+    pub fn decode_op(&self, op: &ProofOp) -> Result<Box<dyn ProofOperator>, ProofError> {
+        match self.decoders.get(op.get_field_type()) {
+            Some(decoder) => decoder(op),
+            None => Err(ProofError::UnknownOpType(op.get_field_type().to_string())),
+        }
+    }
+
+    pub fn decode(&self, proof: &Proof) -> Result<Vec<Box<dyn ProofOperator>>, ProofError> {
+        proof
+            .get_ops()
+            .iter()
+            .map(|op| self.decode_op(op))
+            .collect()
+    }
+
+    /// Checks that `proof` shows `value` stored at `keypath` under `root`.
+    pub fn verify_value(
+        &self,
+        proof: &Proof,
+        root: &[u8],
+        keypath: &str,
+        value: &[u8],
+    ) -> Result<(), ProofError> {
+        self.verify(proof, root, keypath, vec![value.to_vec()])
+    }
+
+    /// Checks that `proof` shows nothing is stored at `keypath` under `root`.
+    pub fn verify_absence(
+        &self,
+        proof: &Proof,
+        root: &[u8],
+        keypath: &str,
+    ) -> Result<(), ProofError> {
+        self.verify(proof, root, keypath, Vec::new())
+    }
+
+    /// Decodes `proof` and runs its ops on `args`, see `verify_operators`.
+    pub fn verify(
+        &self,
+        proof: &Proof,
+        root: &[u8],
+        keypath: &str,
+        args: Vec<Vec<u8>>,
+    ) -> Result<(), ProofError> {
+        verify_operators(&self.decode(proof)?, root, keypath, args)
+    }
+}
+
+/// Assembles a `Proof` from the ops of the stores a value is nested in, innermost first, and
+/// the keypath they prove.
+///
+/// ```rust
+/// use abci::merkle::simple::{self, SimpleValueOp};
+/// use abci::merkle::{ProofBuilder, ProofOperator, ProofRuntime};
+///
+/// let leaves = vec![SimpleValueOp::leaf(b"a/b", b"1")];
+/// let (root, mut proofs) = simple::proofs_from_byte_slices(&leaves);
+/// let op = SimpleValueOp::new(b"a/b".to_vec(), proofs.remove(0));
+///
+/// let builder = ProofBuilder::new().op(op.proof_op());
+/// assert_eq!(builder.key_path().to_string(), "/a%2Fb");
+/// let response = builder.into_response(Some(b"1".to_vec()), 5);
+/// ProofRuntime::with_default_ops()
+///     .verify_value(response.get_proof(), &root, "/a%2Fb", response.get_value())
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ProofBuilder {
+    ops: Vec<ProofOp>,
+}
+
+impl ProofBuilder {
+    pub fn new() -> ProofBuilder {
+        ProofBuilder::default()
+    }
+
+    /// Appends the op of the next store out, e.g. a multistore op after the op of a substore.
+    pub fn op(mut self, op: ProofOp) -> ProofBuilder {
+        self.ops.push(op);
+        self
+    }
+
+    /// The keypath of the ops' keys, outermost first and URL-escaped, as the runtime consumes it.
+    pub fn key_path(&self) -> KeyPath {
+        self.ops
+            .iter()
+            .rev()
+            .filter(|op| !op.get_key().is_empty())
+            .fold(KeyPath::new(), |path, op| {
+                path.append_key(op.get_key().to_vec(), KeyEncoding::Url)
+            })
+    }
+
+    pub fn build(self) -> Proof {
+        let mut proof = Proof::new();
+        proof.set_ops(self.ops.into());
+        proof
+    }
+
+    /// A response with the proof, `value` (empty if absent) and the `height` it was read at. Its
+    /// key is the key of the innermost op.
+    pub fn into_response(self, value: Option<Vec<u8>>, height: i64) -> ResponseQuery {
+        let mut response = ResponseQuery::new();
+        let key = self
+            .ops
+            .iter()
+            .map(|op| op.get_key())
+            .find(|key| !key.is_empty());
+        response.set_key(key.unwrap_or_default().to_vec());
+        response.set_value(value.unwrap_or_default());
+        response.set_height(height);
+        response.set_proof(self.build());
+        response
+    }
+}
+
+/// Runs `operators` in order, starting from `args`, and checks that they consume all of
+/// `keypath`, parsed as a `KeyPath`, and output `root`.
+pub fn verify_operators(
+    operators: &[Box<dyn ProofOperator>],
+    root: &[u8],
+    keypath: &str,
+    mut args: Vec<Vec<u8>>,
+) -> Result<(), ProofError> {
+    let mut keys = keypath.parse::<KeyPath>()?.into_names();
+    for (index, operator) in operators.iter().enumerate() {
+        let key = operator.key();
+        if !key.is_empty() {
+            let expected = keys.pop().ok_or_else(|| ProofError::KeyPathExhausted {
+                index,
+                key: key.to_vec(),
+            })?;
+            if expected != key {
+                return Err(ProofError::KeyMismatch {
+                    index,
+                    expected,
+                    actual: key.to_vec(),
+                });
+            }
+        }
+        args = operator.run(args).map_err(|error| ProofError::Operation {
+            index,
+            error: Box::new(error),
+        })?;
+    }
+    let actual = args.into_iter().next().unwrap_or_default();
+    if actual != root {
+        return Err(ProofError::RootMismatch {
+            expected: root.to_vec(),
+            actual,
+        });
+    }
+    if !keys.is_empty() {
+        return Err(ProofError::UnconsumedKeys(keys.len()));
+    }
+    Ok(())
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hashes nothing: the root is the key followed by the value, or just the key for absence.
+    struct Prefix(Vec<u8>);
+
+    impl ProofOperator for Prefix {
+        fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+            if args.len() > 1 {
+                return Err(ProofError::InvalidProof(String::from("expected one value")));
+            }
+            let value = args.into_iter().next().unwrap_or_default();
+            Ok(vec![[&self.0[..], &value[..]].concat()])
+        }
+
+        fn key(&self) -> &[u8] {
+            &self.0
+        }
+
+        fn proof_op(&self) -> ProofOp {
+            let mut op = ProofOp::new();
+            op.set_field_type(String::from("prefix"));
+            op.set_key(self.0.clone());
+            op
+        }
+    }
+
+    fn runtime() -> ProofRuntime {
+        let mut runtime = ProofRuntime::new();
+        runtime
+            .register_op_decoder("prefix", |op| Ok(Box::new(Prefix(op.get_key().to_vec()))))
+            .unwrap();
+        runtime
+    }
+
+    fn proof(keys: &[&[u8]]) -> Proof {
+        let mut proof = Proof::new();
+        for key in keys {
+            proof.mut_ops().push(Prefix(key.to_vec()).proof_op());
+        }
+        proof
+    }
+
+    #[test]
+    fn should_verify_chained_ops() {
+        let runtime = runtime();
+        let proof = proof(&[b"\x01key", b"store"]);
+        runtime
+            .verify_value(&proof, b"store\x01keyvalue", "/store/x:016b6579", b"value")
+            .unwrap();
+        runtime
+            .verify_value(&proof, b"store\x01keyvalue", "/store/%01key", b"value")
+            .unwrap();
+        runtime
+            .verify_absence(&proof, b"store\x01key", "/store/%01key")
+            .unwrap();
+    }
+
+    #[test]
+    fn should_report_why_proofs_fail() {
+        let runtime = runtime();
+        let proof = proof(&[b"key", b"store"]);
+        assert_eq!(
+            runtime.verify_value(&proof, b"storekeyvalue", "/other/key", b"value"),
+            Err(ProofError::KeyMismatch {
+                index: 1,
+                expected: b"other".to_vec(),
+                actual: b"store".to_vec(),
+            })
+        );
+        assert_eq!(
+            runtime.verify_value(&proof, b"storekeyvalue", "/key", b"value"),
+            Err(ProofError::KeyPathExhausted {
+                index: 1,
+                key: b"store".to_vec(),
+            })
+        );
+        assert_eq!(
+            runtime.verify_value(&proof, b"storekeyvalue", "/extra/store/key", b"value"),
+            Err(ProofError::UnconsumedKeys(1))
+        );
+        assert_eq!(
+            runtime
+                .verify_value(&proof, b"storekeyvalue", "/store/key", b"other")
+                .unwrap_err()
+                .to_string(),
+            "calculated root hash is invalid: expected 73746F72656B657976616C7565 \
+             but got 73746F72656B65796F74686572"
+        );
+        assert_eq!(
+            runtime.verify(&proof, b"", "/store/key", vec![vec![], vec![]]),
+            Err(ProofError::Operation {
+                index: 0,
+                error: Box::new(ProofError::InvalidProof(String::from("expected one value"))),
+            })
+        );
+        assert_eq!(
+            runtime.verify_value(&proof, b"", "store/key", b""),
+            Err(ProofError::InvalidKeyPath(String::from(
+                "key path string must start with a forward slash '/'"
+            )))
+        );
+        assert!(runtime
+            .verify_value(&proof, b"", "/store/x:0", b"")
+            .is_err());
+
+        let mut unknown = Proof::new();
+        unknown.mut_ops().push(ProofOp::new());
+        assert_eq!(
+            runtime.verify_absence(&unknown, b"", "/"),
+            Err(ProofError::UnknownOpType(String::new()))
+        );
+    }
+
+    #[test]
+    fn should_build_proofs_and_key_paths() {
+        let builder = ProofBuilder::new()
+            .op(Prefix(b"\x01a b/c".to_vec()).proof_op())
+            .op(ProofOp::new())
+            .op(Prefix(b"store".to_vec()).proof_op());
+        assert_eq!(builder.key_path().to_string(), "/store/%01a%20b%2Fc");
+        assert_eq!(builder.clone().build().get_ops().len(), 3);
+
+        let response = builder.into_response(None, 7);
+        assert_eq!(response.get_key(), b"\x01a b/c");
+        assert!(response.get_value().is_empty());
+        assert_eq!(response.get_height(), 7);
+
+        let mut runtime = runtime();
+        runtime
+            .register_op_decoder("", |_| Ok(Box::new(Prefix(Vec::new()))))
+            .unwrap();
+        runtime
+            .verify_absence(
+                response.get_proof(),
+                b"store\x01a b/c",
+                "/store/%01a%20b%2Fc",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn should_reject_duplicate_decoders() {
+        let mut runtime = runtime();
+        assert_eq!(
+            runtime.register_op_decoder("prefix", |op| Ok(Box::new(Prefix(op.get_key().to_vec())))),
+            Err(ProofError::DuplicateDecoder(String::from("prefix")))
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! The Cosmos SDK multistore proof op, of type `multistore`. It proves the root of a substore,
+//! e.g. the IAVL root of the `bank` store, under the app hash.
+//!
+//! The app hash is the root of a simple Merkle map from store names to the `SHA-256` of their
+//! roots. The map's leaves are `SimpleValueOp` leaves, so each root is hashed twice.
+use std::collections::BTreeMap;
+
+use super::amino::{Reader, Writer};
+use super::simple::{self, sha256, SimpleValueOp};
+use super::{to_hex, ProofError, ProofOperator};
+use crate::messages::merkle::ProofOp;
+
+/// `ProofOp.type` of `MultiStoreProofOp`.
+pub const PROOF_OP_MULTISTORE: &str = "multistore";
+
+/// The commit of one substore.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoreInfo {
+    pub name: String,
+    pub version: i64,
+    /// The root of the substore.
+    pub hash: Vec<u8>,
+}
+
+impl StoreInfo {
+    pub fn new(name: String, version: i64, hash: Vec<u8>) -> StoreInfo {
+        StoreInfo {
+            name,
+            version,
+            hash,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut commit_id = Writer::new();
+        commit_id.varint_field(1, self.version as u64);
+        commit_id.bytes_field(2, &self.hash);
+        let mut core = Writer::new();
+        core.bytes_field(1, &commit_id.into_vec());
+
+        let mut writer = Writer::new();
+        writer.bytes_field(1, self.name.as_bytes());
+        writer.bytes_field(2, &core.into_vec());
+        writer.into_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<StoreInfo, String> {
+        let mut info = StoreInfo::default();
+        let mut reader = Reader::new(bytes);
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => {
+                    let name = reader.bytes_value(field, wire_type)?;
+                    info.name = String::from_utf8(name.to_vec())
+                        .map_err(|_| String::from("store name is not UTF-8"))?;
+                }
+                2 => {
+                    let mut core = Reader::new(reader.bytes_value(field, wire_type)?);
+                    while !core.is_empty() {
+                        let (field, wire_type) = core.key()?;
+                        if field == 1 {
+                            info.decode_commit_id(core.bytes_value(field, wire_type)?)?;
+                        } else {
+                            core.skip(wire_type)?;
+                        }
+                    }
+                }
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(info)
+    }
+
+    fn decode_commit_id(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = Reader::new(bytes);
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => self.version = reader.varint_value(field, wire_type)? as i64,
+                2 => self.hash = reader.bytes_value(field, wire_type)?.to_vec(),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The commits of all the substores of a multistore.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MultiStoreProof {
+    pub store_infos: Vec<StoreInfo>,
+}
+
+impl MultiStoreProof {
+    pub fn new(store_infos: Vec<StoreInfo>) -> MultiStoreProof {
+        MultiStoreProof { store_infos }
+    }
+
+    /// The app hash committing to the substores. Of two stores with the same name, the last one
+    /// counts, as in the Cosmos SDK.
+    pub fn compute_root_hash(&self) -> Vec<u8> {
+        let stores: BTreeMap<&str, &[u8]> = self
+            .store_infos
+            .iter()
+            .map(|info| (info.name.as_str(), info.hash.as_slice()))
+            .collect();
+        let leaves: Vec<Vec<u8>> = stores
+            .into_iter()
+            .map(|(name, hash)| SimpleValueOp::leaf(name.as_bytes(), &sha256(hash)))
+            .collect();
+        simple::hash_from_byte_slices(&leaves)
+    }
+}
+
+/// Proves the root of the substore named by its key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiStoreProofOp {
+    pub key: Vec<u8>,
+    pub proof: MultiStoreProof,
+}
+
+impl MultiStoreProofOp {
+    pub fn new(key: Vec<u8>, proof: MultiStoreProof) -> MultiStoreProofOp {
+        MultiStoreProofOp { key, proof }
+    }
+
+    /// Decodes an op of type `multistore`. Registered with `ProofRuntime::register_op_decoder`.
+    pub fn decode(op: &ProofOp) -> Result<Box<dyn ProofOperator>, ProofError> {
+        let invalid = |reason: String| ProofError::InvalidOp {
+            op_type: op.get_field_type().to_string(),
+            reason,
+        };
+        if op.get_field_type() != PROOF_OP_MULTISTORE {
+            return Err(invalid(format!("expected type {}", PROOF_OP_MULTISTORE)));
+        }
+        let mut reader = Reader::length_prefixed(op.get_data()).map_err(invalid)?;
+        let mut proof = MultiStoreProof::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key().map_err(invalid)?;
+            if field != 1 {
+                reader.skip(wire_type).map_err(invalid)?;
+                continue;
+            }
+            let mut infos = Reader::new(reader.bytes_value(field, wire_type).map_err(invalid)?);
+            while !infos.is_empty() {
+                let (field, wire_type) = infos.key().map_err(invalid)?;
+                if field == 1 {
+                    let bytes = infos.bytes_value(field, wire_type).map_err(invalid)?;
+                    proof
+                        .store_infos
+                        .push(StoreInfo::decode(bytes).map_err(invalid)?);
+                } else {
+                    infos.skip(wire_type).map_err(invalid)?;
+                }
+            }
+        }
+        Ok(Box::new(MultiStoreProofOp::new(
+            op.get_key().to_vec(),
+            proof,
+        )))
+    }
+}
+
+impl ProofOperator for MultiStoreProofOp {
+    fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+        if args.len() != 1 {
+            return Err(ProofError::InvalidProof(format!(
+                "expected 1 arg, got {}",
+                args.len()
+            )));
+        }
+        let info = self
+            .proof
+            .store_infos
+            .iter()
+            .find(|info| info.name.as_bytes() == self.key.as_slice())
+            .ok_or_else(|| {
+                ProofError::InvalidProof(format!(
+                    "key {:?} not found in multistore proof",
+                    String::from_utf8_lossy(&self.key)
+                ))
+            })?;
+        if info.hash != args[0] {
+            return Err(ProofError::InvalidProof(format!(
+                "hash mismatch for substore {}: {} vs {}",
+                info.name,
+                to_hex(&info.hash),
+                to_hex(&args[0])
+            )));
+        }
+        Ok(vec![self.proof.compute_root_hash()])
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn proof_op(&self) -> ProofOp {
+        let mut infos = Writer::new();
+        for info in &self.proof.store_infos {
+            infos.repeated_bytes_field(1, &info.encode());
+        }
+        let mut writer = Writer::new();
+        writer.repeated_bytes_field(1, &infos.into_vec());
+        let mut op = ProofOp::new();
+        op.set_field_type(PROOF_OP_MULTISTORE.to_string());
+        op.set_key(self.key.clone());
+        op.set_data(writer.into_length_prefixed());
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::iavl::RangeProof;
+    use crate::merkle::iavl::{IavlAbsenceOp, IavlValueOp, ProofInnerNode, ProofLeafNode};
+    use crate::merkle::ProofRuntime;
+    use crate::messages::merkle::Proof;
+
+    fn store_infos(bank: Vec<u8>) -> Vec<StoreInfo> {
+        vec![
+            StoreInfo::new(String::from("bank"), 3, bank),
+            StoreInfo::new(String::from("acc"), 3, vec![0xAA; 32]),
+        ]
+    }
+
+    #[test]
+    fn should_hash_store_roots_twice() {
+        let proof = MultiStoreProof::new(store_infos(vec![0xBB; 32]));
+        assert_eq!(
+            proof.compute_root_hash(),
+            simple::hash_from_byte_slices(&[
+                SimpleValueOp::leaf(b"acc", &sha256(&[0xAA; 32])),
+                SimpleValueOp::leaf(b"bank", &sha256(&[0xBB; 32])),
+            ])
+        );
+    }
+
+    #[test]
+    fn should_verify_cosmos_query_proofs() {
+        let leaf = ProofLeafNode {
+            key: b"alice".to_vec(),
+            value_hash: sha256(b"100"),
+            version: 3,
+        };
+        let range = RangeProof {
+            left_path: Vec::new(),
+            inner_nodes: Vec::new(),
+            leaves: vec![leaf.clone()],
+        };
+        let multistore = MultiStoreProof::new(store_infos(leaf.hash()));
+        let app_hash = multistore.compute_root_hash();
+
+        let mut proof = Proof::new();
+        proof
+            .mut_ops()
+            .push(IavlValueOp::new(b"alice".to_vec(), range.clone()).proof_op());
+        proof
+            .mut_ops()
+            .push(MultiStoreProofOp::new(b"bank".to_vec(), multistore.clone()).proof_op());
+
+        let runtime = ProofRuntime::with_cosmos_ops();
+        runtime
+            .verify_value(&proof, &app_hash, "/bank/alice", b"100")
+            .unwrap();
+        assert!(runtime
+            .verify_value(&proof, &app_hash, "/bank/alice", b"101")
+            .is_err());
+        assert!(runtime
+            .verify_value(&proof, &app_hash, "/acc/alice", b"100")
+            .is_err());
+        let decoded = runtime.decode(&proof).unwrap();
+        assert_eq!(decoded[1].proof_op(), proof.get_ops()[1]);
+
+        let mut absence = Proof::new();
+        absence
+            .mut_ops()
+            .push(IavlAbsenceOp::new(b"bob".to_vec(), range).proof_op());
+        absence
+            .mut_ops()
+            .push(MultiStoreProofOp::new(b"bank".to_vec(), multistore).proof_op());
+        runtime
+            .verify_absence(&absence, &app_hash, "/bank/bob")
+            .unwrap();
+
+        let mut other = MultiStoreProof::new(store_infos(vec![0xCC; 32]));
+        other.store_infos[0].hash = ProofInnerNode::default().hash(&leaf.hash());
+        let op = MultiStoreProofOp::new(b"bank".to_vec(), other);
+        match op.run(vec![leaf.hash()]) {
+            Err(ProofError::InvalidProof(reason)) => {
+                assert!(reason.starts_with("hash mismatch for substore bank"))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
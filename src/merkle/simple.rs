@@ -0,0 +1,379 @@
+//! Tendermint's simple Merkle tree, used for `data_hash`, `last_results_hash` and validator set
+//! hashes. It follows RFC 6962: leaves are hashed as `SHA-256(0x00 || leaf)`, inner nodes as
+//! `SHA-256(0x01 || left || right)`, and a list is split at the largest power of two smaller
+//! than its length. The root of an empty list is empty.
+//!
+//! ```rust
+//! use abci::merkle::simple;
+//!
+//! let items: Vec<&[u8]> = vec![b"a", b"b", b"c"];
+//! let (root, proofs) = simple::proofs_from_byte_slices(&items);
+//! assert_eq!(root, simple::hash_from_byte_slices(&items));
+//! proofs[2].verify(&root, b"c").unwrap();
+//! ```
+use sha2::{Digest, Sha256};
+
+use super::amino::{Reader, Writer};
+use super::{to_hex, ProofError, ProofOperator};
+use crate::messages::merkle::ProofOp;
+
+/// `ProofOp.type` of `SimpleValueOp`.
+pub const PROOF_OP_SIMPLE_VALUE: &str = "simple:v";
+
+const LEAF_PREFIX: u8 = 0;
+const INNER_PREFIX: u8 = 1;
+
+pub(crate) fn sha256(bytes: &[u8]) -> Vec<u8> {
+    Sha256::digest(bytes).to_vec()
+}
+
+pub fn leaf_hash(leaf: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(leaf);
+    hasher.finalize().to_vec()
+}
+
+pub fn inner_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([INNER_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/// The largest power of two smaller than `len`, which must be at least 2.
+pub(crate) fn split_point(len: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < len {
+        k *= 2;
+    }
+    k
+}
+
+/// The root of the tree over `items`.
+pub fn hash_from_byte_slices<T: AsRef<[u8]>>(items: &[T]) -> Vec<u8> {
+    match items.len() {
+        0 => Vec::new(),
+        1 => leaf_hash(items[0].as_ref()),
+        len => {
+            let k = split_point(len);
+            inner_hash(
+                &hash_from_byte_slices(&items[..k]),
+                &hash_from_byte_slices(&items[k..]),
+            )
+        }
+    }
+}
+
+/// Proves that a leaf is at `index` of a list of `total` items.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimpleProof {
+    pub total: i64,
+    pub index: i64,
+    pub leaf_hash: Vec<u8>,
+    /// Hashes of the siblings on the path from the leaf to the root, starting at the leaf.
+    pub aunts: Vec<Vec<u8>>,
+}
+
+/// The root of the tree over `items` and a proof for each of them.
+pub fn proofs_from_byte_slices<T: AsRef<[u8]>>(items: &[T]) -> (Vec<u8>, Vec<SimpleProof>) {
+    let leaves: Vec<Vec<u8>> = items.iter().map(|item| leaf_hash(item.as_ref())).collect();
+    let (root, aunts) = trails(&leaves);
+    let proofs = leaves
+        .into_iter()
+        .zip(aunts)
+        .enumerate()
+        .map(|(index, (leaf_hash, aunts))| SimpleProof {
+            total: items.len() as i64,
+            index: index as i64,
+            leaf_hash,
+            aunts,
+        })
+        .collect();
+    (root, proofs)
+}
+
+/// The root of the tree over already hashed `leaves`, with the aunts of each leaf.
+fn trails(leaves: &[Vec<u8>]) -> (Vec<u8>, Vec<Vec<Vec<u8>>>) {
+    match leaves.len() {
+        0 => (Vec::new(), Vec::new()),
+        1 => (leaves[0].clone(), vec![Vec::new()]),
+        len => {
+            let k = split_point(len);
+            let (left, mut left_aunts) = trails(&leaves[..k]);
+            let (right, mut right_aunts) = trails(&leaves[k..]);
+            for aunts in &mut left_aunts {
+                aunts.push(right.clone());
+            }
+            for aunts in &mut right_aunts {
+                aunts.push(left.clone());
+            }
+            left_aunts.append(&mut right_aunts);
+            (inner_hash(&left, &right), left_aunts)
+        }
+    }
+}
+
+impl SimpleProof {
+    /// Checks that `leaf` is the proven item of the tree with root `root`.
+    pub fn verify(&self, root: &[u8], leaf: &[u8]) -> Result<(), ProofError> {
+        if self.total < 0 {
+            return Err(ProofError::InvalidProof(String::from("negative total")));
+        }
+        if self.index < 0 {
+            return Err(ProofError::InvalidProof(String::from("negative index")));
+        }
+        let leaf_hash = leaf_hash(leaf);
+        if leaf_hash != self.leaf_hash {
+            return Err(ProofError::InvalidProof(format!(
+                "leaf hash mismatch: want {} got {}",
+                to_hex(&leaf_hash),
+                to_hex(&self.leaf_hash)
+            )));
+        }
+        let computed = self.compute_root_hash().unwrap_or_default();
+        if computed != root {
+            return Err(ProofError::RootMismatch {
+                expected: root.to_vec(),
+                actual: computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// The root implied by the leaf hash and the aunts, or `None` if the aunts do not fit the
+    /// index and total.
+    pub fn compute_root_hash(&self) -> Option<Vec<u8>> {
+        if self.index < 0 || self.total <= 0 || self.index >= self.total {
+            return None;
+        }
+        hash_from_aunts(
+            self.index as usize,
+            self.total as usize,
+            &self.leaf_hash,
+            &self.aunts,
+        )
+    }
+
+    /// The Amino encoding of the proof.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.varint_field(1, self.total as u64);
+        writer.varint_field(2, self.index as u64);
+        writer.bytes_field(3, &self.leaf_hash);
+        for aunt in &self.aunts {
+            writer.repeated_bytes_field(4, aunt);
+        }
+        writer.into_vec()
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Result<SimpleProof, String> {
+        let mut reader = Reader::new(bytes);
+        let mut proof = SimpleProof::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key()?;
+            match field {
+                1 => proof.total = reader.varint_value(field, wire_type)? as i64,
+                2 => proof.index = reader.varint_value(field, wire_type)? as i64,
+                3 => proof.leaf_hash = reader.bytes_value(field, wire_type)?.to_vec(),
+                4 => proof
+                    .aunts
+                    .push(reader.bytes_value(field, wire_type)?.to_vec()),
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(proof)
+    }
+}
+
+fn hash_from_aunts(
+    index: usize,
+    total: usize,
+    leaf_hash: &[u8],
+    aunts: &[Vec<u8>],
+) -> Option<Vec<u8>> {
+    if total == 1 {
+        return if aunts.is_empty() {
+            Some(leaf_hash.to_vec())
+        } else {
+            None
+        };
+    }
+    let (last, rest) = aunts.split_last()?;
+    let k = split_point(total);
+    if index < k {
+        let left = hash_from_aunts(index, k, leaf_hash, rest)?;
+        Some(inner_hash(&left, last))
+    } else {
+        let right = hash_from_aunts(index - k, total - k, leaf_hash, rest)?;
+        Some(inner_hash(last, &right))
+    }
+}
+
+/// Proves a value stored under a key in a simple tree whose leaves are the Amino encodings of
+/// `(key, SHA-256(value))` pairs, as Tendermint's `SimpleValueOp` does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimpleValueOp {
+    pub key: Vec<u8>,
+    pub proof: SimpleProof,
+}
+
+impl SimpleValueOp {
+    pub fn new(key: Vec<u8>, proof: SimpleProof) -> SimpleValueOp {
+        SimpleValueOp { key, proof }
+    }
+
+    /// The leaf for `value` stored under `key`.
+    pub fn leaf(key: &[u8], value: &[u8]) -> Vec<u8> {
+        SimpleValueOp::leaf_from_value_hash(key, &sha256(value))
+    }
+
+    pub(crate) fn leaf_from_value_hash(key: &[u8], value_hash: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.uvarint(key.len() as u64);
+        let mut leaf = writer.into_vec();
+        leaf.extend_from_slice(key);
+        let mut writer = Writer::new();
+        writer.uvarint(value_hash.len() as u64);
+        leaf.extend_from_slice(&writer.into_vec());
+        leaf.extend_from_slice(value_hash);
+        leaf
+    }
+
+    /// Decodes an op of type `simple:v`. Registered with `ProofRuntime::register_op_decoder`.
+    pub fn decode(op: &ProofOp) -> Result<Box<dyn ProofOperator>, ProofError> {
+        let invalid = |reason: String| ProofError::InvalidOp {
+            op_type: op.get_field_type().to_string(),
+            reason,
+        };
+        if op.get_field_type() != PROOF_OP_SIMPLE_VALUE {
+            return Err(invalid(format!("expected type {}", PROOF_OP_SIMPLE_VALUE)));
+        }
+        let mut reader = Reader::length_prefixed(op.get_data()).map_err(invalid)?;
+        let mut proof = SimpleProof::default();
+        while !reader.is_empty() {
+            let (field, wire_type) = reader.key().map_err(invalid)?;
+            if field == 1 {
+                let bytes = reader.bytes_value(field, wire_type).map_err(invalid)?;
+                proof = SimpleProof::decode(bytes).map_err(invalid)?;
+            } else {
+                reader.skip(wire_type).map_err(invalid)?;
+            }
+        }
+        Ok(Box::new(SimpleValueOp::new(op.get_key().to_vec(), proof)))
+    }
+}
+
+impl ProofOperator for SimpleValueOp {
+    fn run(&self, args: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ProofError> {
+        if args.len() != 1 {
+            return Err(ProofError::InvalidProof(format!(
+                "expected 1 arg, got {}",
+                args.len()
+            )));
+        }
+        let leaf_hash = leaf_hash(&SimpleValueOp::leaf(&self.key, &args[0]));
+        if leaf_hash != self.proof.leaf_hash {
+            return Err(ProofError::InvalidProof(format!(
+                "leaf hash mismatch: want {} got {}",
+                to_hex(&self.proof.leaf_hash),
+                to_hex(&leaf_hash)
+            )));
+        }
+        let root = self.proof.compute_root_hash().ok_or_else(|| {
+            ProofError::InvalidProof(String::from("aunts do not match index and total"))
+        })?;
+        Ok(vec![root])
+    }
+
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn proof_op(&self) -> ProofOp {
+        let mut writer = Writer::new();
+        writer.repeated_bytes_field(1, &self.proof.encode());
+        let mut op = ProofOp::new();
+        op.set_field_type(PROOF_OP_SIMPLE_VALUE.to_string());
+        op.set_key(self.key.clone());
+        op.set_data(writer.into_length_prefixed());
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::ProofRuntime;
+    use crate::messages::merkle::Proof;
+
+    fn hex(bytes: &[u8]) -> String {
+        to_hex(bytes).to_lowercase()
+    }
+
+    #[test]
+    fn should_hash_like_tendermint() {
+        let empty: &[&[u8]] = &[];
+        assert_eq!(hash_from_byte_slices(empty), Vec::<u8>::new());
+        // The RFC 6962 leaf hash of the empty string.
+        assert_eq!(
+            hex(&hash_from_byte_slices(&[b""])),
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+        let items: Vec<&[u8]> = vec![b"a", b"b", b"c"];
+        assert_eq!(
+            hash_from_byte_slices(&items),
+            inner_hash(
+                &inner_hash(&leaf_hash(b"a"), &leaf_hash(b"b")),
+                &leaf_hash(b"c")
+            )
+        );
+    }
+
+    #[test]
+    fn should_prove_every_item() {
+        for len in 1..=9 {
+            let items: Vec<Vec<u8>> = (0..len).map(|i| vec![i as u8; i]).collect();
+            let (root, proofs) = proofs_from_byte_slices(&items);
+            assert_eq!(root, hash_from_byte_slices(&items));
+            for (item, proof) in items.iter().zip(&proofs) {
+                proof.verify(&root, item).unwrap();
+                assert!(proof.verify(&root, b"other").is_err());
+
+                let mut tampered = proof.clone();
+                tampered.index = (tampered.index + 1) % len as i64;
+                if len > 1 {
+                    assert!(tampered.verify(&root, item).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_verify_simple_value_ops() {
+        let pairs: Vec<(&[u8], &[u8])> = vec![(b"a", b"1"), (b"b", b"2"), (b"c", b"3")];
+        let leaves: Vec<Vec<u8>> = pairs
+            .iter()
+            .map(|(key, value)| SimpleValueOp::leaf(key, value))
+            .collect();
+        let (root, mut proofs) = proofs_from_byte_slices(&leaves);
+
+        let op = SimpleValueOp::new(b"b".to_vec(), proofs.remove(1)).proof_op();
+        assert_eq!(op.get_field_type(), "simple:v");
+        let mut proof = Proof::new();
+        proof.mut_ops().push(op);
+
+        let runtime = ProofRuntime::with_default_ops();
+        runtime.verify_value(&proof, &root, "/b", b"2").unwrap();
+        match runtime.verify_value(&proof, &root, "/b", b"3") {
+            Err(ProofError::Operation { index: 0, error }) => assert!(error
+                .to_string()
+                .starts_with("invalid proof: leaf hash mismatch")),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let decoded = runtime.decode(&proof).unwrap();
+        assert_eq!(decoded[0].proof_op(), proof.get_ops()[0]);
+    }
+}
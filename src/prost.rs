@@ -0,0 +1,360 @@
+//! The ABCI messages generated by prost, enabled by the `prost-messages` feature.
+//!
+//! These are plain structs with `Option` fields for nested messages, for applications whose other
+//! code already uses prost. They share the wire format with the rust-protobuf messages at the
+//! crate root, so `transcode` converts between the two:
+//!
+//! ```rust
+//! let mut echo = abci::RequestEcho::new();
+//! echo.set_message(String::from("hello"));
+//! let echo: abci::prost::RequestEcho = abci::transcode(&echo).unwrap();
+//! assert_eq!(echo.message, "hello");
+//! ```
+//!
+//! Applications can also be written against these messages directly, by implementing
+//! `Application<Prost>`:
+//!
+//! ```rust,no_run
+//! use abci::prost::{Prost, RequestCommit, ResponseCommit};
+//!
+//! struct App;
+//!
+//! impl abci::Application<Prost> for App {
+//!     fn commit(&mut self, _ctx: &abci::Context, _req: &RequestCommit) -> ResponseCommit {
+//!         ResponseCommit {
+//!             data: vec![0; 8],
+//!             ..Default::default()
+//!         }
+//!     }
+//! }
+//!
+//! let addr = "127.0.0.1:26658".parse().unwrap();
+//! abci::run_with_backend::<Prost, _>(addr, App, abci::ServerConfig::new());
+//! ```
+use std::io;
+
+use bytes::BytesMut;
+
+use crate::backend::{Backend, BlockHeader, Method, RequestSummary};
+use crate::codec::WireMessage;
+use crate::context::Context;
+use crate::error::{error_code, into_response, AbciError, ErrorResponse};
+use crate::{Application, CheckTxHandler};
+
+#[allow(clippy::all)]
+mod tendermint {
+    pub mod abci {
+        pub mod types {
+            include!(concat!(env!("OUT_DIR"), "/tendermint.abci.types.rs"));
+        }
+    }
+
+    pub mod crypto {
+        pub mod merkle {
+            include!(concat!(env!("OUT_DIR"), "/tendermint.crypto.merkle.rs"));
+        }
+    }
+
+    pub mod libs {
+        pub mod kv {
+            include!(concat!(env!("OUT_DIR"), "/tendermint.libs.kv.rs"));
+        }
+    }
+}
+
+pub use self::tendermint::abci::types::*;
+pub use self::tendermint::crypto::merkle::*;
+pub use self::tendermint::libs::kv::*;
+
+macro_rules! wire_messages {
+    ($($message:ident),* $(,)?) => {
+        $(
+            impl WireMessage for $message {
+                fn encoded_len(&self) -> usize {
+                    ::prost::Message::encoded_len(self)
+                }
+
+                fn encode_to(&self, buf: &mut BytesMut) -> io::Result<()> {
+                    ::prost::Message::encode(self, buf)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                }
+
+                fn decode_from(bytes: &[u8]) -> io::Result<$message> {
+                    ::prost::Message::decode(bytes)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                }
+            }
+        )*
+    };
+}
+
+wire_messages!(
+    Request,
+    RequestEcho,
+    RequestFlush,
+    RequestInfo,
+    RequestSetOption,
+    RequestInitChain,
+    RequestQuery,
+    RequestBeginBlock,
+    RequestCheckTx,
+    RequestDeliverTx,
+    RequestEndBlock,
+    RequestCommit,
+    Response,
+    ResponseException,
+    ResponseEcho,
+    ResponseFlush,
+    ResponseInfo,
+    ResponseSetOption,
+    ResponseInitChain,
+    ResponseQuery,
+    ResponseBeginBlock,
+    ResponseCheckTx,
+    ResponseDeliverTx,
+    ResponseEndBlock,
+    ResponseCommit,
+    ConsensusParams,
+    BlockParams,
+    EvidenceParams,
+    ValidatorParams,
+    LastCommitInfo,
+    Event,
+    Header,
+    Version,
+    BlockId,
+    PartSetHeader,
+    Validator,
+    ValidatorUpdate,
+    VoteInfo,
+    PubKey,
+    Evidence,
+    ProofOp,
+    Proof,
+    Pair,
+);
+
+macro_rules! error_responses {
+    ($($response:ident),*) => {
+        $(
+            impl ErrorResponse for $response {
+                fn from_error(error: &dyn AbciError) -> $response {
+                    $response {
+                        code: error_code(error),
+                        codespace: error.codespace().to_string(),
+                        log: error.log(),
+                        ..Default::default()
+                    }
+                }
+
+                fn error_parts(&self) -> (u32, &str, &str) {
+                    (self.code, &self.codespace, &self.log)
+                }
+            }
+        )*
+    };
+}
+
+error_responses!(ResponseCheckTx, ResponseDeliverTx, ResponseQuery);
+
+impl From<&Header> for BlockHeader {
+    fn from(header: &Header) -> BlockHeader {
+        let time = header.time.as_ref().and_then(|time| {
+            let mut wire = protobuf::well_known_types::Timestamp::new();
+            wire.set_seconds(time.seconds);
+            wire.set_nanos(time.nanos);
+            crate::time::to_system_time(&wire).ok()
+        });
+        BlockHeader {
+            chain_id: header.chain_id.clone(),
+            height: header.height,
+            time,
+            app_hash: header.app_hash.clone(),
+            proposer_address: header.proposer_address.clone(),
+        }
+    }
+}
+
+/// The prost messages of this module, as a `Backend` for the server and `Application`.
+#[derive(Clone, Copy, Debug)]
+pub struct Prost;
+
+impl Backend for Prost {
+    type Request = Request;
+    type Response = Response;
+
+    type RequestInfo = RequestInfo;
+    type ResponseInfo = ResponseInfo;
+    type RequestSetOption = RequestSetOption;
+    type ResponseSetOption = ResponseSetOption;
+    type RequestQuery = RequestQuery;
+    type ResponseQuery = ResponseQuery;
+    type RequestCheckTx = RequestCheckTx;
+    type ResponseCheckTx = ResponseCheckTx;
+    type RequestInitChain = RequestInitChain;
+    type ResponseInitChain = ResponseInitChain;
+    type RequestBeginBlock = RequestBeginBlock;
+    type ResponseBeginBlock = ResponseBeginBlock;
+    type RequestDeliverTx = RequestDeliverTx;
+    type ResponseDeliverTx = ResponseDeliverTx;
+    type RequestEndBlock = RequestEndBlock;
+    type ResponseEndBlock = ResponseEndBlock;
+    type RequestCommit = RequestCommit;
+    type ResponseCommit = ResponseCommit;
+
+    fn summarize(request: &Request) -> RequestSummary {
+        use self::request::Value;
+
+        let method = match request.value {
+            Some(Value::Echo(_)) => Method::Echo,
+            Some(Value::Flush(_)) => Method::Flush,
+            Some(Value::Info(_)) => Method::Info,
+            Some(Value::SetOption(_)) => Method::SetOption,
+            Some(Value::InitChain(_)) => Method::InitChain,
+            Some(Value::Query(_)) => Method::Query,
+            Some(Value::BeginBlock(_)) => Method::BeginBlock,
+            Some(Value::CheckTx(_)) => Method::CheckTx,
+            Some(Value::DeliverTx(_)) => Method::DeliverTx,
+            Some(Value::EndBlock(_)) => Method::EndBlock,
+            Some(Value::Commit(_)) => Method::Commit,
+            None => Method::Unknown,
+        };
+        let mut summary = RequestSummary::new(method);
+        match request.value {
+            Some(Value::BeginBlock(ref r)) => {
+                let header = r.header.clone().unwrap_or_default();
+                summary.header = Some(BlockHeader::from(&header));
+            }
+            Some(Value::EndBlock(ref r)) => summary.height = Some(r.height),
+            _ => {}
+        }
+        summary
+    }
+
+    fn dispatch<A>(app: &mut A, ctx: &Context, request: &Request) -> Response
+    where
+        A: Application<Prost> + ?Sized,
+    {
+        use self::request::Value as In;
+        use self::response::Value as Out;
+
+        let value = match request.value {
+            Some(In::Info(ref r)) => Out::Info(app.info(ctx, r)),
+            Some(In::InitChain(ref r)) => Out::InitChain(app.init_chain(ctx, r)),
+            Some(In::SetOption(ref r)) => Out::SetOption(app.set_option(ctx, r)),
+            Some(In::Query(ref r)) => Out::Query(into_response(app.query(ctx, r))),
+            Some(In::CheckTx(ref r)) => Out::CheckTx(into_response(app.check_tx(ctx, r))),
+            Some(In::BeginBlock(ref r)) => Out::BeginBlock(app.begin_block(ctx, r)),
+            Some(In::DeliverTx(ref r)) => Out::DeliverTx(into_response(app.deliver_tx(ctx, r))),
+            Some(In::EndBlock(ref r)) => Out::EndBlock(app.end_block(ctx, r)),
+            Some(In::Commit(ref r)) => Out::Commit(app.commit(ctx, r)),
+            Some(In::Flush(_)) => Out::Flush(ResponseFlush {}),
+            Some(In::Echo(ref r)) => Out::Echo(ResponseEcho {
+                message: r.message.clone(),
+            }),
+            None => return Prost::exception(String::from("Unrecognized request")),
+        };
+        Response { value: Some(value) }
+    }
+
+    fn check_tx(handler: &dyn CheckTxHandler<Prost>, ctx: &Context, request: &Request) -> Response {
+        let result = match request.value {
+            Some(request::Value::CheckTx(ref r)) => handler.check_tx(ctx, r),
+            _ => handler.check_tx(ctx, &RequestCheckTx::default()),
+        };
+        Response {
+            value: Some(response::Value::CheckTx(into_response(result))),
+        }
+    }
+
+    fn exception(error: String) -> Response {
+        Response {
+            value: Some(response::Value::Exception(ResponseException { error })),
+        }
+    }
+
+    fn is_flush(response: &Response) -> bool {
+        matches!(response.value, Some(response::Value::Flush(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{transcode, ABCICodec};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    #[test]
+    fn should_transcode_between_backends() {
+        let mut header = crate::Header::new();
+        header.set_chain_id(String::from("test-chain"));
+        header.set_height(7);
+        header.mut_time().set_seconds(1_595_000_000);
+        let mut begin_block = crate::RequestBeginBlock::new();
+        begin_block.set_header(header);
+        let mut request = crate::Request::new();
+        request.set_begin_block(begin_block);
+
+        let converted: Request = transcode(&request).unwrap();
+        match converted.value {
+            Some(request::Value::BeginBlock(ref r)) => {
+                let header = r.header.as_ref().unwrap();
+                assert_eq!(header.chain_id, "test-chain");
+                assert_eq!(header.height, 7);
+                assert_eq!(header.time.as_ref().unwrap().seconds, 1_595_000_000);
+            }
+            ref other => panic!("unexpected request {:?}", other),
+        }
+        assert_eq!(transcode::<_, crate::Request>(&converted).unwrap(), request);
+    }
+
+    #[test]
+    fn should_summarize_prost_requests() {
+        let request = Request {
+            value: Some(request::Value::BeginBlock(RequestBeginBlock {
+                header: Some(Header {
+                    chain_id: String::from("test-chain"),
+                    height: 7,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })),
+        };
+        let summary = Prost::summarize(&request);
+        assert_eq!(summary.method, Method::BeginBlock);
+        let header = summary.header.unwrap();
+        assert_eq!(header.chain_id, "test-chain");
+        assert_eq!(header.height, 7);
+        assert_eq!(header.time, None);
+
+        let request = Request {
+            value: Some(request::Value::EndBlock(RequestEndBlock { height: 7 })),
+        };
+        assert_eq!(Prost::summarize(&request).height, Some(7));
+        assert!(!Prost::is_flush(&Prost::exception(String::from("error"))));
+    }
+
+    #[test]
+    fn should_frame_prost_messages() {
+        let mut client: ABCICodec<Response, Request> = ABCICodec::new();
+        let mut server: ABCICodec<crate::Request, crate::Response> = ABCICodec::new();
+
+        let request = Request {
+            value: Some(request::Value::Echo(RequestEcho {
+                message: String::from("Helloworld"),
+            })),
+        };
+        let mut buf = BytesMut::new();
+        client.encode(request, &mut buf).unwrap();
+        let request = server.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(request.get_echo().get_message(), "Helloworld");
+
+        let mut response = crate::Response::new();
+        response.mut_echo().set_message(String::from("Helloworld"));
+        server.encode(response, &mut buf).unwrap();
+        match client.decode(&mut buf).unwrap().unwrap().value {
+            Some(response::Value::Echo(echo)) => assert_eq!(echo.message, "Helloworld"),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+}
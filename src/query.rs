@@ -0,0 +1,569 @@
+//! Tendermint's event query language, as used by `/subscribe` and `/tx_search`.
+//!
+//! A query is a list of conditions joined by `AND`, each comparing a composite key
+//! (`<event type>.<attribute key>`) with an operand:
+//!
+//! ```text
+//! tm.event = 'Tx' AND transfer.sender = 'abc' AND tx.height > 5
+//! transfer.amount >= 10.5 AND block.time < TIME 2020-07-17T15:33:20Z AND memo CONTAINS 'hi'
+//! rewards.validator EXISTS AND vesting.start <= DATE 2020-01-01
+//! ```
+//!
+//! Matching follows Tendermint: a condition holds if any value of its key satisfies it, and a
+//! numeric operand is compared with the first run of digits and dots in the value, so
+//! `transfer.amount > 5` matches `"10uatom"`.
+//!
+//! ```rust
+//! use abci::query::{Attributes, Query};
+//! use abci::types::Event;
+//!
+//! let query: Query = "tm.event = 'Tx' AND transfer.amount > 5".parse().unwrap();
+//! let mut attributes = Attributes::new();
+//! attributes.insert("tm.event", "Tx");
+//! attributes.extend_events(&[Event::new("transfer").attr("amount", "10uatom").into()]);
+//! assert!(query.matches(&attributes));
+//! ```
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::messages::abci as wire;
+use crate::time;
+
+/// Attribute values of a set of events, keyed by `<event type>.<attribute key>`. Besides the
+/// application's events, Tendermint adds keys such as `tm.event`, `tx.hash` and `tx.height`,
+/// which callers can `insert` themselves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    values: BTreeMap<String, Vec<String>>,
+}
+
+impl Attributes {
+    pub fn new() -> Attributes {
+        Attributes::default()
+    }
+
+    pub fn from_events<'a, I>(events: I) -> Attributes
+    where
+        I: IntoIterator<Item = &'a wire::Event>,
+    {
+        let mut attributes = Attributes::new();
+        attributes.extend_events(events);
+        attributes
+    }
+
+    /// Adds the attributes of `events`. Like Tendermint, events without a type and attributes
+    /// without a key are skipped, and values that are not UTF-8 are read lossily.
+    pub fn extend_events<'a, I>(&mut self, events: I)
+    where
+        I: IntoIterator<Item = &'a wire::Event>,
+    {
+        for event in events {
+            if event.get_field_type().is_empty() {
+                continue;
+            }
+            for pair in event.get_attributes() {
+                if pair.get_key().is_empty() {
+                    continue;
+                }
+                let key = format!(
+                    "{}.{}",
+                    event.get_field_type(),
+                    String::from_utf8_lossy(pair.get_key())
+                );
+                self.insert(key, String::from_utf8_lossy(pair.get_value()));
+            }
+        }
+    }
+
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.values
+            .entry(key.into())
+            .or_default()
+            .push(value.into());
+    }
+
+    /// The values recorded for `key`, in insertion order.
+    pub fn get(&self, key: &str) -> &[String] {
+        self.values.get(key).map_or(&[], |values| &values[..])
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    /// All keys with their values, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.values
+            .iter()
+            .map(|(key, values)| (key.as_str(), &values[..]))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Exists,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Eq => "=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Contains => "CONTAINS",
+            Operator::Exists => "EXISTS",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    /// A quoted string, e.g. `'abc'`.
+    String(String),
+    Int(i64),
+    Float(f64),
+    /// `TIME 2020-07-17T15:33:20Z`, compared with values in RFC 3339 format.
+    Time(SystemTime),
+    /// `DATE 2020-07-17`, compared with values in `YYYY-MM-DD` format.
+    Date(SystemTime),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::String(value) => write!(f, "'{}'", value),
+            Operand::Int(value) => write!(f, "{}", value),
+            Operand::Float(value) => {
+                let formatted = value.to_string();
+                if formatted.contains('.') {
+                    f.write_str(&formatted)
+                } else {
+                    write!(f, "{}.0", formatted)
+                }
+            }
+            Operand::Time(time) => {
+                let timestamp = time::from_system_time_unchecked(*time);
+                write!(f, "TIME {}", time::format_rfc3339(&timestamp))
+            }
+            Operand::Date(time) => {
+                let timestamp = time::from_system_time_unchecked(*time);
+                write!(f, "DATE {}", &time::format_rfc3339(&timestamp)[..10])
+            }
+        }
+    }
+}
+
+/// A single `<key> <operator> <operand>` condition of a query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    key: String,
+    operator: Operator,
+    operand: Option<Operand>,
+}
+
+impl Condition {
+    /// The composite key, e.g. `transfer.sender`.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn operator(&self) -> Operator {
+        self.operator
+    }
+
+    /// The operand, or `None` for `EXISTS`.
+    pub fn operand(&self) -> Option<&Operand> {
+        self.operand.as_ref()
+    }
+
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        if !attributes.contains_key(&self.key) {
+            return false;
+        }
+        match self.operand {
+            None => true,
+            Some(ref operand) => attributes
+                .get(&self.key)
+                .iter()
+                .any(|value| self.matches_value(operand, value)),
+        }
+    }
+
+    fn matches_value(&self, operand: &Operand, value: &str) -> bool {
+        match operand {
+            Operand::String(expected) => match self.operator {
+                Operator::Contains => value.contains(expected.as_str()),
+                _ => value == expected,
+            },
+            Operand::Int(expected) => {
+                let number = leading_number(value);
+                if number.contains('.') {
+                    matches!(number.parse::<f64>(), Ok(v) if self.compare(v, *expected as f64))
+                } else {
+                    matches!(number.parse::<i64>(), Ok(v) if self.compare(v, *expected))
+                }
+            }
+            Operand::Float(expected) => {
+                matches!(leading_number(value).parse::<f64>(), Ok(v) if self.compare(v, *expected))
+            }
+            Operand::Time(expected) => {
+                let time = time::parse_rfc3339(value)
+                    .and_then(|timestamp| time::to_system_time(&timestamp).ok());
+                matches!(time, Some(v) if self.compare(v, *expected))
+            }
+            Operand::Date(expected) => {
+                matches!(parse_date(value), Some(v) if self.compare(v, *expected))
+            }
+        }
+    }
+
+    fn compare<T: PartialOrd>(&self, value: T, operand: T) -> bool {
+        match self.operator {
+            Operator::Eq => value == operand,
+            Operator::Lt => value < operand,
+            Operator::Le => value <= operand,
+            Operator::Gt => value > operand,
+            Operator::Ge => value >= operand,
+            Operator::Contains | Operator::Exists => false,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.operand {
+            Some(ref operand) => write!(f, "{} {} {}", self.key, self.operator, operand),
+            None => write!(f, "{} {}", self.key, self.operator),
+        }
+    }
+}
+
+/// The first run of digits and dots in `value`, as Tendermint extracts numbers.
+fn leading_number(value: &str) -> &str {
+    let is_numeric = |c: char| c.is_ascii_digit() || c == '.';
+    match value.find(is_numeric) {
+        Some(start) => {
+            let len = value[start..]
+                .find(|c: char| !is_numeric(c))
+                .unwrap_or(value.len() - start);
+            &value[start..start + len]
+        }
+        None => "",
+    }
+}
+
+fn parse_date(text: &str) -> Option<SystemTime> {
+    if text.len() != 10 {
+        return None;
+    }
+    let timestamp = time::parse_rfc3339(&format!("{}T00:00:00Z", text))?;
+    time::to_system_time(&timestamp).ok()
+}
+
+/// A parsed query: all conditions must hold for events to match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+}
+
+impl Query {
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(attributes))
+    }
+
+    /// Shorthand for matching the events of a single response, e.g. a `ResponseDeliverTx`.
+    pub fn matches_events(&self, events: &[wire::Event]) -> bool {
+        self.matches(&Attributes::from_events(events))
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, condition) in self.conditions.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" AND ")?;
+            }
+            write!(f, "{}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Query {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Query, ParseError> {
+        Parser { text, position: 0 }.query()
+    }
+}
+
+/// Why a query could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte offset into the query where parsing failed.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ParseError {}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.position,
+            message: message.into(),
+        })
+    }
+
+    fn skip_spaces(&mut self) -> usize {
+        let rest = self.rest();
+        let skipped = rest.len() - rest.trim_start().len();
+        self.position += skipped;
+        skipped
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, accept: F) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !accept(c)).unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    fn query(mut self) -> Result<Query, ParseError> {
+        self.skip_spaces();
+        let mut conditions = vec![self.condition()?];
+        loop {
+            let spaces = self.skip_spaces();
+            if self.rest().is_empty() {
+                return Ok(Query { conditions });
+            }
+            if spaces == 0 || !self.eat("AND") || self.skip_spaces() == 0 {
+                return self.error("expected AND");
+            }
+            conditions.push(self.condition()?);
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, ParseError> {
+        let key = self.take_while(|c| !c.is_whitespace() && !"\\()\"'=<>".contains(c));
+        if key.is_empty() {
+            return self.error("expected a key");
+        }
+        let key = key.to_string();
+        self.skip_spaces();
+        let operator = if self.eat("<=") {
+            Operator::Le
+        } else if self.eat(">=") {
+            Operator::Ge
+        } else if self.eat("<") {
+            Operator::Lt
+        } else if self.eat(">") {
+            Operator::Gt
+        } else if self.eat("=") {
+            Operator::Eq
+        } else if self.eat("CONTAINS") {
+            Operator::Contains
+        } else if self.eat("EXISTS") {
+            return Ok(Condition {
+                key,
+                operator: Operator::Exists,
+                operand: None,
+            });
+        } else {
+            return self.error("expected an operator");
+        };
+        self.skip_spaces();
+        let start = self.position;
+        let operand = self.operand()?;
+        let valid = match (&operator, &operand) {
+            (Operator::Eq, _) => true,
+            (Operator::Contains, Operand::String(_)) => true,
+            (Operator::Contains, _) => false,
+            (_, Operand::String(_)) => false,
+            _ => true,
+        };
+        if !valid {
+            self.position = start;
+            return self.error(format!("{} does not apply to {}", operator, operand));
+        }
+        Ok(Condition {
+            key,
+            operator,
+            operand: Some(operand),
+        })
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        if self.eat("'") {
+            let value = self.take_while(|c| c != '\'' && c != '"');
+            if !self.eat("'") {
+                return self.error("unterminated string");
+            }
+            return Ok(Operand::String(value.to_string()));
+        }
+        if self.eat("TIME ") {
+            let value = self.take_while(|c| !c.is_whitespace());
+            return match time::parse_rfc3339(value).map(|t| time::to_system_time(&t)) {
+                Some(Ok(time)) => Ok(Operand::Time(time)),
+                _ => self.error(format!("invalid time {:?}", value)),
+            };
+        }
+        if self.eat("DATE ") {
+            let value = self.take_while(|c| !c.is_whitespace());
+            return match parse_date(value) {
+                Some(date) => Ok(Operand::Date(date)),
+                None => self.error(format!("invalid date {:?}", value)),
+            };
+        }
+        let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+        if number.starts_with(|c: char| c.is_ascii_digit()) {
+            let operand = if number.contains('.') {
+                number.parse().ok().map(Operand::Float)
+            } else {
+                number.parse().ok().map(Operand::Int)
+            };
+            if let Some(operand) = operand {
+                return Ok(operand);
+            }
+        }
+        self.position -= number.len();
+        self.error("expected a string, number, TIME or DATE")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn attributes() -> Attributes {
+        let events: Vec<wire::Event> = vec![
+            Event::new("transfer")
+                .attr("sender", "abc")
+                .attr("amount", "10uatom")
+                .into(),
+            Event::new("transfer")
+                .attr("sender", "def")
+                .attr("amount", "2.5uatom")
+                .into(),
+            Event::new("vesting")
+                .attr("start", "2020-01-01")
+                .attr("at", "2020-07-17T15:33:20Z")
+                .into(),
+        ];
+        let mut attributes = Attributes::from_events(&events);
+        attributes.insert("tm.event", "Tx");
+        attributes.insert("tx.height", "7");
+        attributes
+    }
+
+    fn matches(query: &str) -> bool {
+        query.parse::<Query>().unwrap().matches(&attributes())
+    }
+
+    #[test]
+    fn should_parse_and_format_queries() {
+        let query: Query = "tm.event='Tx'  AND transfer.sender = 'abc' AND tx.height>5"
+            .parse()
+            .unwrap();
+        assert_eq!(query.conditions().len(), 3);
+        assert_eq!(query.conditions()[2].operator(), Operator::Gt);
+        assert_eq!(query.conditions()[2].operand(), Some(&Operand::Int(5)));
+        assert_eq!(
+            query.to_string(),
+            "tm.event = 'Tx' AND transfer.sender = 'abc' AND tx.height > 5"
+        );
+
+        let query: Query = "a.b >= 1.0 AND a.t < TIME 2020-07-17T17:33:20+02:00 AND a.d EXISTS"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            query.conditions()[1].operand(),
+            Some(&Operand::Time(
+                UNIX_EPOCH + Duration::from_secs(1_595_000_000)
+            ))
+        );
+        assert_eq!(
+            query.to_string(),
+            "a.b >= 1.0 AND a.t < TIME 2020-07-17T15:33:20Z AND a.d EXISTS"
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_queries() {
+        let error = |query: &str| query.parse::<Query>().unwrap_err();
+        assert_eq!(error("").message, "expected a key");
+        assert_eq!(error("a.b = 'x' OR a.c = 'y'").position, 10);
+        assert_eq!(error("a.b = 'x").message, "unterminated string");
+        assert_eq!(error("a.b > 'x'").message, "> does not apply to 'x'");
+        assert_eq!(error("a.b CONTAINS 5").position, 13);
+        assert_eq!(
+            error("a.b < DATE 2020-13-01").message,
+            "invalid date \"2020-13-01\""
+        );
+        assert_eq!(error("a.b ~ 1").message, "expected an operator");
+    }
+
+    #[test]
+    fn should_match_like_tendermint() {
+        assert!(matches(
+            "tm.event = 'Tx' AND transfer.sender = 'abc' AND tx.height > 5"
+        ));
+        assert!(matches("transfer.sender = 'def'"));
+        assert!(!matches("transfer.sender = 'ghi'"));
+        assert!(matches("transfer.amount > 5"));
+        assert!(matches("transfer.amount < 3"));
+        assert!(!matches("transfer.amount > 10"));
+        assert!(matches("transfer.amount = 2.5"));
+        assert!(matches("transfer.sender CONTAINS 'bc'"));
+        assert!(matches("vesting.start EXISTS"));
+        assert!(!matches("vesting.end EXISTS"));
+        assert!(!matches("vesting.end < 5"));
+        assert!(matches("vesting.start <= DATE 2020-01-01"));
+        assert!(!matches("vesting.start > DATE 2020-01-01"));
+        assert!(matches("vesting.at >= TIME 2020-07-17T15:33:20Z"));
+        assert!(!matches("vesting.at < TIME 2020-07-17T15:33:20Z"));
+    }
+}
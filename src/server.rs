@@ -1,58 +1,426 @@
+use std::cell::Cell;
+use std::io;
+use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::ops::DerefMut;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
+use std::time::Duration;
 
 use env_logger::Env;
-use futures::sink::SinkExt;
-use futures::stream::StreamExt;
+use futures::future::{self, FutureExt};
+use futures::sink::{Sink, SinkExt};
+use futures::stream::{FuturesOrdered, StreamExt};
+use futures::{pin_mut, select};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
 use tokio::runtime;
 use tokio::sync::Mutex;
+use tokio::task::{self, JoinError};
+use tokio::time::{self, Instant};
 use tokio_util::codec::Decoder;
 
+use crate::backend::{method_of, Backend, Method};
 use crate::codec::ABCICodec;
+use crate::consensus::{ConsensusContext, OrderingPolicy};
+use crate::context::{CancellationToken, Context};
 use crate::messages::abci::*;
 use crate::Application;
 
-async fn serve_async<A>(app: A, addr: SocketAddr)
+thread_local! {
+    // Set while an application callback runs under `PanicPolicy::Exception`, so the process-wide
+    // panic hook knows the unwind will be caught. A `const` initializer needs Rust 1.59.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static ISOLATED: Cell<bool> = Cell::new(false);
+}
+
+/// The three connections Tendermint opens to the application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionRole {
+    /// Carries `init_chain`, `begin_block`, `deliver_tx`, `end_block` and `commit`.
+    Consensus,
+    /// Carries `check_tx`.
+    Mempool,
+    /// Carries `info`, `set_option` and `query`.
+    Query,
+}
+
+impl ConnectionRole {
+    /// The connection a request is expected on. `echo` and `flush` are valid on every
+    /// connection and return `None`.
+    pub fn of(request: &Request) -> Option<ConnectionRole> {
+        method_of(request).role()
+    }
+}
+
+/// What the server does when an application callback panics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Print the panic and exit the process. This is the default for every connection.
+    Abort,
+    /// Catch the unwind, log it along with the request and reply with a `ResponseException`.
+    Exception,
+}
+
+/// Settings for the ABCI server.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    mempool_panics: PanicPolicy,
+    query_panics: PanicPolicy,
+    flush_after: Option<usize>,
+    flush_interval: Option<Duration>,
+    pipeline_depth: Option<usize>,
+    enforce_roles: bool,
+    ordering: OrderingPolicy,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            mempool_panics: PanicPolicy::Abort,
+            query_panics: PanicPolicy::Abort,
+            flush_after: None,
+            flush_interval: None,
+            pipeline_depth: None,
+            enforce_roles: false,
+            ordering: OrderingPolicy::Off,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn new() -> ServerConfig {
+        ServerConfig::default()
+    }
+
+    /// Set the panic policy for callbacks served on the given connection. Panics on the
+    /// consensus connection always abort, since continuing after a half-applied block would
+    /// leave the application state diverged from the chain.
+    pub fn panic_policy(mut self, role: ConnectionRole, policy: PanicPolicy) -> ServerConfig {
+        match role {
+            ConnectionRole::Consensus => {
+                if policy != PanicPolicy::Abort {
+                    warn!(
+                        "Ignoring {:?} panic policy for the consensus connection",
+                        policy
+                    );
+                }
+            }
+            ConnectionRole::Mempool => self.mempool_panics = policy,
+            ConnectionRole::Query => self.query_panics = policy,
+        }
+        self
+    }
+
+    /// Flush buffered responses once `count` of them are waiting, without waiting for a
+    /// `RequestFlush`.
+    pub fn flush_after(mut self, count: usize) -> ServerConfig {
+        self.flush_after = Some(count);
+        self
+    }
+
+    /// Flush buffered responses at the latest `interval` after the first of them was buffered,
+    /// even while pipelined `check_tx` requests are still running.
+    pub fn flush_interval(mut self, interval: Duration) -> ServerConfig {
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Let up to `depth` mempool `check_tx` requests run concurrently on the blocking thread
+    /// pool. Only takes effect for applications that provide a `CheckTxHandler`.
+    pub fn pipeline_check_tx(mut self, depth: usize) -> ServerConfig {
+        self.pipeline_depth = Some(depth);
+        self
+    }
+
+    /// Reject requests that do not belong to the role a connection was classified as, replying
+    /// with a `ResponseException` instead of calling the application. Useful to catch clients
+    /// that send, say, `deliver_tx` over the mempool connection.
+    pub fn enforce_connection_roles(mut self, enforce: bool) -> ServerConfig {
+        self.enforce_roles = enforce;
+        self
+    }
+
+    /// Set how consensus requests that arrive out of order (e.g. `deliver_tx` before
+    /// `begin_block`, or `end_block` for the wrong height) are handled.
+    pub fn consensus_ordering(mut self, policy: OrderingPolicy) -> ServerConfig {
+        self.ordering = policy;
+        self
+    }
+
+    /// The panic policy in effect for callbacks served on the given connection.
+    pub fn panic_policy_for(&self, role: ConnectionRole) -> PanicPolicy {
+        match role {
+            ConnectionRole::Consensus => PanicPolicy::Abort,
+            ConnectionRole::Mempool => self.mempool_panics,
+            ConnectionRole::Query => self.query_panics,
+        }
+    }
+}
+
+async fn serve_async<B, A>(app: A, addr: SocketAddr, config: ServerConfig)
 where
-    A: Application + 'static + Send + Sync,
+    B: Backend,
+    A: Application<B> + 'static + Send + Sync,
 {
     let app = Arc::new(Mutex::new(app));
+    let config = Arc::new(config);
+    let consensus = ConsensusContext::new();
     let mut listener = TcpListener::bind(&addr).await.unwrap();
+    let mut connection_id = 0;
     while let Some(Ok(socket)) = listener.next().await {
         let app_instance = app.clone();
+        let config = config.clone();
+        let consensus = consensus.clone();
+        let id = connection_id;
+        connection_id += 1;
         tokio::spawn(async move {
             info!("Got connection! {:?}", socket);
-            let framed = ABCICodec::new().framed(socket);
-            let (mut writer, mut reader) = framed.split();
-            let mut mrequest = reader.next().await;
-            while let Some(Ok(ref request)) = mrequest {
-                debug!("Got Request! {:?}", request);
-                let response = respond(&app_instance, request).await;
-                debug!("Return Response! {:?}", response);
-                writer.send(response).await.expect("sending back response");
-                mrequest = reader.next().await;
-            }
-            match mrequest {
-                None => {
+            match serve_connection::<B, A, _>(app_instance, socket, config, consensus, id).await {
+                Ok(()) => {
                     panic!("connection dropped");
                 }
-                Some(Err(e)) => {
+                Err(e) => {
                     panic!("decoding error: {:?}", e);
                 }
-                _ => {
-                    unreachable!();
-                }
             }
         });
     }
 }
 
+/// Serves requests on a single connection until the peer hangs up. Responses are buffered and
+/// only written out when Tendermint sends a `RequestFlush`, or when one of the configured flush
+/// thresholds is reached.
+///
+/// The connection is classified by the first request that belongs to a single role (see
+/// `Method::role`). Requests for another role are logged, and answered with a
+/// `ResponseException` when the server enforces connection roles.
+///
+/// Requests on the consensus connection also drive the server's `ConsensusContext`, and
+/// out-of-order ones are handled according to the configured `OrderingPolicy`.
+///
+/// With mempool pipelining enabled, `check_tx` requests are handed to the application's
+/// `CheckTxHandler` on the blocking thread pool and reading carries on while they run. Any other
+/// request waits for the in-flight ones, so responses always go out in request order.
+pub(crate) async fn serve_connection<B, A, S>(
+    app: Arc<Mutex<A>>,
+    socket: S,
+    config: Arc<ServerConfig>,
+    consensus: ConsensusContext,
+    connection_id: usize,
+) -> io::Result<()>
+where
+    B: Backend,
+    A: Application<B> + 'static + Send + Sync,
+    S: AsyncRead + AsyncWrite,
+{
+    let cancellation = CancellationToken::new();
+    let _cancel_on_close = CancelOnDrop(cancellation.clone());
+    let framed = ABCICodec::<B::Request, B::Response>::new().framed(socket);
+    let (writer, mut reader) = framed.split();
+    let mut writer = ResponseWriter::<B, _>::new(writer, config.flush_after, config.flush_interval);
+    let handler = match config.pipeline_depth {
+        Some(_) => app.lock().await.check_tx_handler(),
+        None => None,
+    };
+    let depth = config.pipeline_depth.unwrap_or(1).max(1);
+    let mut pending = FuturesOrdered::new();
+    let mut role = None;
+    loop {
+        let full = pending.len() >= depth;
+        let event = {
+            let request = async {
+                if full {
+                    future::pending().await
+                } else {
+                    Event::Request(reader.next().await)
+                }
+            };
+            let response = async {
+                match pending.next().await {
+                    Some(response) => Event::Response(response),
+                    None => future::pending().await,
+                }
+            };
+            let flush_due = async {
+                match writer.deadline {
+                    Some(deadline) => time::delay_until(deadline).await,
+                    None => future::pending().await,
+                }
+                Event::FlushDue
+            };
+            pin_mut!(request, response, flush_due);
+            select! {
+                event = request.fuse() => event,
+                event = response.fuse() => event,
+                event = flush_due.fuse() => event,
+            }
+        };
+        let request = match event {
+            Event::Request(Some(request)) => request?,
+            Event::Request(None) => return Ok(()),
+            Event::Response(response) => {
+                writer.send(pipelined(response)).await;
+                continue;
+            }
+            Event::FlushDue => {
+                writer.flush().await;
+                continue;
+            }
+        };
+        debug!("Got Request! {:?}", request);
+        let summary = B::summarize(&request);
+        let method = summary.method;
+        let admitted = match (role, method.role()) {
+            (_, None) => Ok(()),
+            (None, Some(detected)) => {
+                info!("Connection classified as {:?}", detected);
+                role = Some(detected);
+                app.lock().await.connection_established(detected);
+                Ok(())
+            }
+            (Some(current), Some(expected)) if current != expected => {
+                warn!("Received {} on the {:?} connection", method.name(), current);
+                if config.enforce_roles {
+                    Err(format!(
+                        "{} is not allowed on the {:?} connection",
+                        method.name(),
+                        current
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        };
+        match handler {
+            Some(ref handler) if admitted.is_ok() && method == Method::CheckTx => {
+                let handler = handler.clone();
+                let policy = panic_policy(&config, role, method);
+                let ctx = Context::new(
+                    connection_id,
+                    role,
+                    &consensus,
+                    &summary,
+                    cancellation.clone(),
+                );
+                pending.push_back(task::spawn_blocking(move || {
+                    guard::<B, _>(policy, &request, || {
+                        B::check_tx(handler.as_ref(), &ctx, &request)
+                    })
+                }));
+            }
+            _ => {
+                while let Some(response) = pending.next().await {
+                    writer.send(pipelined(response)).await;
+                }
+                let admitted = admitted.and_then(|()| match role {
+                    Some(ConnectionRole::Consensus) => consensus.advance(&summary, config.ordering),
+                    _ => Ok(()),
+                });
+                let response = match admitted {
+                    Ok(()) => {
+                        let ctx = Context::new(
+                            connection_id,
+                            role,
+                            &consensus,
+                            &summary,
+                            cancellation.clone(),
+                        );
+                        respond(&app, &ctx, &request, &config).await
+                    }
+                    Err(error) => B::exception(error),
+                };
+                writer.send(response).await;
+            }
+        }
+    }
+}
+
+/// Cancels the connection's token however `serve_connection` returns.
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// What `serve_connection` waits for.
+enum Event<Q, R> {
+    Request(Option<io::Result<Q>>),
+    Response(Result<R, JoinError>),
+    /// The oldest buffered response has waited for the flush interval.
+    FlushDue,
+}
+
+fn pipelined<R>(result: Result<R, JoinError>) -> R {
+    // A panicking handler either exits the process or is caught by `guard`, depending on the
+    // panic policy, so the blocking task itself always completes.
+    result.expect("check_tx handler task")
+}
+
+/// Feeds responses into the connection and flushes them according to the server config.
+struct ResponseWriter<B, W> {
+    sink: W,
+    buffered: usize,
+    flush_after: Option<usize>,
+    flush_interval: Option<Duration>,
+    /// When the buffered responses are due to be flushed, if a flush interval is set.
+    deadline: Option<Instant>,
+    backend: PhantomData<B>,
+}
+
+impl<B, W> ResponseWriter<B, W>
+where
+    B: Backend,
+    W: Sink<B::Response, Error = io::Error> + Unpin,
+{
+    fn new(
+        sink: W,
+        flush_after: Option<usize>,
+        flush_interval: Option<Duration>,
+    ) -> ResponseWriter<B, W> {
+        ResponseWriter {
+            sink,
+            buffered: 0,
+            flush_after,
+            flush_interval,
+            deadline: None,
+            backend: PhantomData,
+        }
+    }
+
+    async fn send(&mut self, response: B::Response) {
+        debug!("Return Response! {:?}", response);
+        let flush = B::is_flush(&response);
+        self.sink
+            .feed(response)
+            .await
+            .expect("sending back response");
+        if self.buffered == 0 {
+            self.deadline = self
+                .flush_interval
+                .map(|interval| Instant::now() + interval);
+        }
+        self.buffered += 1;
+        if flush || matches!(self.flush_after, Some(limit) if self.buffered >= limit) {
+            self.flush().await;
+        }
+    }
+
+    async fn flush(&mut self) {
+        self.sink.flush().await.expect("flushing responses");
+        self.buffered = 0;
+        self.deadline = None;
+    }
+}
+
 /// Creates the TCP server and listens for connections from Tendermint
-pub fn serve<A>(app: A, addr: SocketAddr) -> std::io::Result<()>
+pub fn serve<B, A>(app: A, addr: SocketAddr, config: ServerConfig) -> io::Result<()>
 where
-    A: Application + 'static + Send + Sync,
+    B: Backend,
+    A: Application<B> + 'static + Send + Sync,
 {
     env_logger::from_env(Env::default().default_filter_or("info"))
         .try_init()
@@ -61,61 +429,628 @@ where
     let mut rt = runtime::Builder::new()
         .basic_scheduler()
         .enable_io()
+        .enable_time()
         .build()
         .unwrap();
-    let default_hook = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |info| {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
         default_hook(info);
-        std::process::exit(1);
+        if !ISOLATED.with(Cell::get) {
+            std::process::exit(1);
+        }
     }));
-    rt.block_on(serve_async(app, addr));
+    rt.block_on(serve_async::<B, A>(app, addr, config));
     Ok(())
 }
 
-async fn respond<A>(app: &Arc<Mutex<A>>, request: &Request) -> Response
+async fn respond<B, A>(
+    app: &Arc<Mutex<A>>,
+    ctx: &Context,
+    request: &B::Request,
+    config: &ServerConfig,
+) -> B::Response
 where
-    A: Application + 'static + Send + Sync,
+    B: Backend,
+    A: Application<B> + 'static + Send + Sync,
 {
     let mut guard = app.lock().await;
-    let app = guard.deref_mut();
-
-    let mut response = Response::new();
-
-    match request.value {
-        // Info
-        Some(Request_oneof_value::info(ref r)) => response.set_info(app.info(r)),
-        // Init chain
-        Some(Request_oneof_value::init_chain(ref r)) => response.set_init_chain(app.init_chain(r)),
-        // Set option
-        Some(Request_oneof_value::set_option(ref r)) => response.set_set_option(app.set_option(r)),
-        // Query
-        Some(Request_oneof_value::query(ref r)) => response.set_query(app.query(r)),
-        // Check tx
-        Some(Request_oneof_value::check_tx(ref r)) => response.set_check_tx(app.check_tx(r)),
-        // Begin block
-        Some(Request_oneof_value::begin_block(ref r)) => {
-            response.set_begin_block(app.begin_block(r))
-        }
-        // Deliver Tx
-        Some(Request_oneof_value::deliver_tx(ref r)) => response.set_deliver_tx(app.deliver_tx(r)),
-        // End block
-        Some(Request_oneof_value::end_block(ref r)) => response.set_end_block(app.end_block(r)),
-        // Commit
-        Some(Request_oneof_value::commit(ref r)) => response.set_commit(app.commit(r)),
-        // Flush
-        Some(Request_oneof_value::flush(_)) => response.set_flush(ResponseFlush::new()),
-        // Echo
-        Some(Request_oneof_value::echo(ref r)) => {
-            let echo_msg = r.get_message().to_string();
-            let mut echo = ResponseEcho::new();
-            echo.set_message(echo_msg);
-            response.set_echo(echo);
-        }
-        _ => {
-            let mut re = ResponseException::new();
-            re.set_error(String::from("Unrecognized request"));
-            response.set_exception(re)
-        }
-    }
-    response
+    handle::<B, A>(guard.deref_mut(), ctx, request, config)
+}
+
+/// Runs the callback for `request` under the panic policy of its connection, or `Abort` for a
+/// consensus method whatever the connection.
+fn handle<B, A>(
+    app: &mut A,
+    ctx: &Context,
+    request: &B::Request,
+    config: &ServerConfig,
+) -> B::Response
+where
+    B: Backend,
+    A: Application<B>,
+{
+    let policy = panic_policy(config, ctx.role(), ctx.method());
+    guard::<B, _>(policy, request, || B::dispatch(app, ctx, request))
+}
+
+/// The panic policy for `method` on a connection classified as `role`. Consensus methods and
+/// connections not classified yet abort.
+fn panic_policy(
+    config: &ServerConfig,
+    role: Option<ConnectionRole>,
+    method: Method,
+) -> PanicPolicy {
+    if method.role() == Some(ConnectionRole::Consensus) {
+        return PanicPolicy::Abort;
+    }
+    role.map(|role| config.panic_policy_for(role))
+        .unwrap_or(PanicPolicy::Abort)
+}
+
+/// Runs `callback` under `policy`, turning a caught panic into a `ResponseException`.
+fn guard<B, F>(policy: PanicPolicy, request: &B::Request, callback: F) -> B::Response
+where
+    B: Backend,
+    F: FnOnce() -> B::Response,
+{
+    if policy == PanicPolicy::Abort {
+        return callback();
+    }
+
+    ISOLATED.with(|isolated| isolated.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(callback));
+    ISOLATED.with(|isolated| isolated.set(false));
+
+    result.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown panic"));
+        error!("Application panicked: {} (request: {:?})", message, request);
+        B::exception(format!("application panicked: {}", message))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Protobuf;
+    use crate::{AbciError, CheckTxHandler};
+    use futures::Future;
+    use std::fmt;
+    use tokio::net::TcpStream;
+    use tokio_util::codec::Framed;
+
+    /// The Tendermint side of the wire: encodes requests and decodes responses.
+    type ClientCodec = ABCICodec<Response, Request>;
+
+    struct EmptyApp;
+
+    impl Application for EmptyApp {}
+
+    fn with_connection<A, F, T>(app: A, config: ServerConfig, test: F)
+    where
+        A: Application + 'static + Send + Sync,
+        F: FnOnce(Framed<TcpStream, ClientCodec>) -> T,
+        T: Future<Output = ()>,
+    {
+        with_backend_connection::<Protobuf, A, F, T>(app, config, test)
+    }
+
+    /// Serves `app` on a local connection, and runs `test` with the client side of it.
+    fn with_backend_connection<B, A, F, T>(app: A, config: ServerConfig, test: F)
+    where
+        B: Backend,
+        A: Application<B> + 'static + Send + Sync,
+        F: FnOnce(Framed<TcpStream, ABCICodec<B::Response, B::Request>>) -> T,
+        T: Future<Output = ()>,
+    {
+        let mut rt = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+            let mut listener = TcpListener::bind(&addr).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                let app = Arc::new(Mutex::new(app));
+                let consensus = ConsensusContext::new();
+                serve_connection::<B, A, _>(app, socket, Arc::new(config), consensus, 0).await
+            });
+            let client = TcpStream::connect(addr).await.unwrap();
+            test(ABCICodec::new().framed(client)).await;
+        });
+    }
+
+    fn echo(message: &str) -> Request {
+        let mut echo = RequestEcho::new();
+        echo.set_message(message.to_string());
+        let mut request = Request::new();
+        request.set_echo(echo);
+        request
+    }
+
+    fn check_tx(tx: u8) -> Request {
+        let mut check_tx = RequestCheckTx::new();
+        check_tx.set_tx(vec![tx]);
+        let mut request = Request::new();
+        request.set_check_tx(check_tx);
+        request
+    }
+
+    fn flush() -> Request {
+        let mut request = Request::new();
+        request.set_flush(RequestFlush::new());
+        request
+    }
+
+    /// Sleeps for `tx` * 10 milliseconds, so earlier transactions can finish last.
+    struct SlowVerifier;
+
+    impl CheckTxHandler for SlowVerifier {
+        fn check_tx(
+            &self,
+            _ctx: &Context,
+            req: &RequestCheckTx,
+        ) -> Result<ResponseCheckTx, Box<dyn AbciError>> {
+            std::thread::sleep(Duration::from_millis(u64::from(req.get_tx()[0]) * 10));
+            let mut response = ResponseCheckTx::new();
+            response.set_data(req.get_tx().to_vec());
+            Ok(response)
+        }
+    }
+
+    struct PipelinedApp;
+
+    impl Application for PipelinedApp {
+        fn check_tx_handler(&self) -> Option<Arc<dyn CheckTxHandler>> {
+            Some(Arc::new(SlowVerifier))
+        }
+    }
+
+    async fn next_response(client: &mut Framed<TcpStream, ClientCodec>) -> Option<Response> {
+        match time::timeout(Duration::from_millis(200), client.next()).await {
+            Ok(response) => Some(response.unwrap().unwrap()),
+            Err(_) => None,
+        }
+    }
+
+    #[test]
+    fn should_buffer_responses_until_flush() {
+        with_connection(EmptyApp, ServerConfig::new(), |mut client| async move {
+            client.send(echo("1")).await.unwrap();
+            client.send(echo("2")).await.unwrap();
+            client.send(echo("3")).await.unwrap();
+            assert!(next_response(&mut client).await.is_none());
+
+            client.send(flush()).await.unwrap();
+            for message in &["1", "2", "3"] {
+                let response = next_response(&mut client).await.unwrap();
+                assert_eq!(response.get_echo().get_message(), *message);
+            }
+            assert!(next_response(&mut client).await.unwrap().has_flush());
+        });
+    }
+
+    #[test]
+    fn should_keep_request_order_when_pipelined() {
+        let config = ServerConfig::new().pipeline_check_tx(4);
+        with_connection(PipelinedApp, config, |mut client| async move {
+            let start = std::time::Instant::now();
+            for tx in &[15, 10, 5, 1] {
+                client.send(check_tx(*tx)).await.unwrap();
+            }
+            client.send(echo("done")).await.unwrap();
+            client.send(flush()).await.unwrap();
+            for tx in &[15, 10, 5, 1] {
+                let response = next_response(&mut client).await.unwrap();
+                assert_eq!(response.get_check_tx().get_data(), &[*tx]);
+            }
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_echo().get_message(), "done");
+            assert!(next_response(&mut client).await.unwrap().has_flush());
+            // Run one after the other the checks would take 310ms.
+            assert!(start.elapsed() < Duration::from_millis(250));
+        });
+    }
+
+    #[derive(Clone, Default)]
+    struct RoleRecorder {
+        roles: Arc<std::sync::Mutex<Vec<ConnectionRole>>>,
+    }
+
+    impl Application for RoleRecorder {
+        fn connection_established(&mut self, role: ConnectionRole) {
+            self.roles.lock().unwrap().push(role);
+        }
+    }
+
+    fn query() -> Request {
+        let mut request = Request::new();
+        request.set_query(RequestQuery::new());
+        request
+    }
+
+    #[test]
+    fn should_classify_connection_by_traffic() {
+        let app = RoleRecorder::default();
+        let roles = app.roles.clone();
+        with_connection(app, ServerConfig::new(), |mut client| async move {
+            client.send(echo("hello")).await.unwrap();
+            client.send(check_tx(1)).await.unwrap();
+            client.send(query()).await.unwrap();
+            client.send(flush()).await.unwrap();
+            assert!(next_response(&mut client).await.unwrap().has_echo());
+            assert!(next_response(&mut client).await.unwrap().has_check_tx());
+            // Out of role, but only logged by default.
+            assert!(next_response(&mut client).await.unwrap().has_query());
+        });
+        assert_eq!(*roles.lock().unwrap(), vec![ConnectionRole::Mempool]);
+    }
+
+    #[test]
+    fn should_reject_out_of_role_requests() {
+        let config = ServerConfig::new().enforce_connection_roles(true);
+        with_connection(RoleRecorder::default(), config, |mut client| async move {
+            client.send(query()).await.unwrap();
+            client.send(check_tx(1)).await.unwrap();
+            client.send(flush()).await.unwrap();
+            assert!(next_response(&mut client).await.unwrap().has_query());
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(
+                response.get_exception().get_error(),
+                "check_tx is not allowed on the Query connection"
+            );
+            assert!(next_response(&mut client).await.unwrap().has_flush());
+        });
+    }
+
+    #[test]
+    fn should_track_consensus_on_the_consensus_connection_only() {
+        let config = ServerConfig::new().consensus_ordering(OrderingPolicy::Reject);
+        with_connection(EmptyApp, config, |mut client| async move {
+            client.send(check_tx(1)).await.unwrap();
+            let mut request = Request::new();
+            request.set_deliver_tx(RequestDeliverTx::new());
+            client.send(request).await.unwrap();
+            client.send(flush()).await.unwrap();
+            assert!(next_response(&mut client).await.unwrap().has_check_tx());
+            // Out of order for the consensus connection, but this is the mempool one.
+            assert!(next_response(&mut client).await.unwrap().has_deliver_tx());
+        });
+    }
+
+    #[derive(Clone, Default)]
+    struct ContextRecorder {
+        seen: Arc<std::sync::Mutex<Vec<String>>>,
+        cancellation: Arc<std::sync::Mutex<Option<CancellationToken>>>,
+    }
+
+    impl Application for ContextRecorder {
+        fn deliver_tx(
+            &mut self,
+            ctx: &Context,
+            _req: &RequestDeliverTx,
+        ) -> Result<ResponseDeliverTx, Box<dyn AbciError>> {
+            let header = ctx.header().unwrap();
+            self.seen.lock().unwrap().push(format!(
+                "{}/{}/{:?}",
+                header.chain_id,
+                ctx.height(),
+                ctx.tx_index()
+            ));
+            *self.cancellation.lock().unwrap() = Some(ctx.cancellation().clone());
+            Ok(ResponseDeliverTx::new())
+        }
+    }
+
+    #[test]
+    fn should_pass_block_context_to_callbacks() {
+        let app = ContextRecorder::default();
+        let seen = app.seen.clone();
+        let cancellation = app.cancellation.clone();
+        with_connection(app, ServerConfig::new(), |mut client| async move {
+            let mut begin_block = RequestBeginBlock::new();
+            begin_block
+                .mut_header()
+                .set_chain_id(String::from("test-chain"));
+            begin_block.mut_header().set_height(9);
+            let mut request = Request::new();
+            request.set_begin_block(begin_block);
+            client.send(request).await.unwrap();
+            for _ in 0..2 {
+                let mut request = Request::new();
+                request.set_deliver_tx(RequestDeliverTx::new());
+                client.send(request).await.unwrap();
+            }
+            client.send(flush()).await.unwrap();
+            for _ in 0..4 {
+                next_response(&mut client).await.unwrap();
+            }
+            let token = cancellation.lock().unwrap().clone().unwrap();
+            assert!(!token.is_cancelled());
+            drop(client);
+            time::delay_for(Duration::from_millis(50)).await;
+            assert!(token.is_cancelled());
+        });
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["test-chain/9/Some(0)", "test-chain/9/Some(1)"]
+        );
+    }
+
+    #[test]
+    fn should_flush_after_count() {
+        let config = ServerConfig::new().flush_after(2);
+        with_connection(EmptyApp, config, |mut client| async move {
+            client.send(echo("1")).await.unwrap();
+            assert!(next_response(&mut client).await.is_none());
+
+            client.send(echo("2")).await.unwrap();
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_echo().get_message(), "1");
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_echo().get_message(), "2");
+        });
+    }
+
+    #[test]
+    fn should_flush_after_interval() {
+        let config = ServerConfig::new().flush_interval(Duration::from_millis(10));
+        with_connection(EmptyApp, config, |mut client| async move {
+            client.send(echo("1")).await.unwrap();
+            client.send(echo("2")).await.unwrap();
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_echo().get_message(), "1");
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_echo().get_message(), "2");
+        });
+    }
+
+    #[test]
+    fn should_flush_after_interval_while_pipelined() {
+        let config = ServerConfig::new()
+            .pipeline_check_tx(4)
+            .flush_interval(Duration::from_millis(10));
+        with_connection(PipelinedApp, config, |mut client| async move {
+            let start = std::time::Instant::now();
+            client.send(echo("1")).await.unwrap();
+            client.send(check_tx(15)).await.unwrap();
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_echo().get_message(), "1");
+            // The check takes 150ms, and the echo does not wait for it.
+            assert!(start.elapsed() < Duration::from_millis(100));
+            let response = next_response(&mut client).await.unwrap();
+            assert_eq!(response.get_check_tx().get_data(), &[15]);
+        });
+    }
+
+    fn context(role: ConnectionRole, request: &Request) -> Context {
+        let consensus = ConsensusContext::new();
+        let summary = Protobuf::summarize(request);
+        Context::new(
+            0,
+            Some(role),
+            &consensus,
+            &summary,
+            CancellationToken::new(),
+        )
+    }
+
+    struct PanickingApp;
+
+    impl Application for PanickingApp {
+        fn query(
+            &mut self,
+            _ctx: &Context,
+            _req: &RequestQuery,
+        ) -> Result<ResponseQuery, Box<dyn AbciError>> {
+            panic!("bad query");
+        }
+
+        fn check_tx(
+            &mut self,
+            _ctx: &Context,
+            _req: &RequestCheckTx,
+        ) -> Result<ResponseCheckTx, Box<dyn AbciError>> {
+            panic!("bad tx");
+        }
+
+        fn deliver_tx(
+            &mut self,
+            _ctx: &Context,
+            _req: &RequestDeliverTx,
+        ) -> Result<ResponseDeliverTx, Box<dyn AbciError>> {
+            panic!("bad block");
+        }
+    }
+
+    #[test]
+    fn should_return_exception_on_isolated_panic() {
+        let config = ServerConfig::new()
+            .panic_policy(ConnectionRole::Query, PanicPolicy::Exception)
+            .panic_policy(ConnectionRole::Mempool, PanicPolicy::Exception);
+
+        let request = query();
+        let ctx = context(ConnectionRole::Query, &request);
+        let response = handle::<Protobuf, _>(&mut PanickingApp, &ctx, &request, &config);
+        assert!(response.has_exception());
+        assert_eq!(
+            response.get_exception().get_error(),
+            "application panicked: bad query"
+        );
+
+        let request = check_tx(1);
+        let ctx = context(ConnectionRole::Mempool, &request);
+        let response = handle::<Protobuf, _>(&mut PanickingApp, &ctx, &request, &config);
+        assert!(response.has_exception());
+        assert!(!ISOLATED.with(Cell::get));
+    }
+
+    #[test]
+    fn should_abort_on_panics_over_the_consensus_connection() {
+        let config =
+            ServerConfig::new().panic_policy(ConnectionRole::Query, PanicPolicy::Exception);
+        let request = query();
+        let ctx = context(ConnectionRole::Consensus, &request);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            handle::<Protobuf, _>(&mut PanickingApp, &ctx, &request, &config)
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_abort_on_consensus_panics_over_other_connections() {
+        let config =
+            ServerConfig::new().panic_policy(ConnectionRole::Query, PanicPolicy::Exception);
+        with_connection(PanickingApp, config, |mut client| async move {
+            client.send(query()).await.unwrap();
+            client.send(flush()).await.unwrap();
+            assert!(next_response(&mut client).await.unwrap().has_exception());
+            assert!(next_response(&mut client).await.unwrap().has_flush());
+
+            let mut request = Request::new();
+            request.set_deliver_tx(RequestDeliverTx::new());
+            client.send(request).await.unwrap();
+            client.send(flush()).await.unwrap();
+            // The panic takes the connection down instead of coming back as an exception.
+            let next = time::timeout(Duration::from_secs(1), client.next()).await;
+            if let Some(Ok(response)) = next.unwrap() {
+                panic!("unexpected response {:?}", response);
+            }
+        });
+    }
+
+    #[derive(Debug)]
+    struct Unauthorized;
+
+    impl fmt::Display for Unauthorized {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "signature verification failed")
+        }
+    }
+
+    impl AbciError for Unauthorized {
+        fn code(&self) -> u32 {
+            4
+        }
+
+        fn codespace(&self) -> &str {
+            "auth"
+        }
+    }
+
+    struct RejectingApp;
+
+    impl Application for RejectingApp {
+        fn check_tx(
+            &mut self,
+            _ctx: &Context,
+            _req: &RequestCheckTx,
+        ) -> Result<ResponseCheckTx, Box<dyn AbciError>> {
+            Err(Unauthorized.into())
+        }
+    }
+
+    #[test]
+    fn should_map_errors_to_response_fields() {
+        let request = check_tx(1);
+        let ctx = context(ConnectionRole::Mempool, &request);
+        let response =
+            handle::<Protobuf, _>(&mut RejectingApp, &ctx, &request, &ServerConfig::new());
+        let check_tx = response.get_check_tx();
+        assert_eq!(check_tx.get_code(), 4);
+        assert_eq!(check_tx.get_codespace(), "auth");
+        assert_eq!(check_tx.get_log(), "signature verification failed");
+    }
+
+    #[cfg(feature = "prost-messages")]
+    struct ProstApp;
+
+    #[cfg(feature = "prost-messages")]
+    impl Application<crate::prost::Prost> for ProstApp {
+        fn check_tx(
+            &mut self,
+            _ctx: &Context,
+            req: &crate::prost::RequestCheckTx,
+        ) -> Result<crate::prost::ResponseCheckTx, Box<dyn AbciError>> {
+            if req.tx.is_empty() {
+                return Err(Unauthorized.into());
+            }
+            Ok(crate::prost::ResponseCheckTx {
+                data: req.tx.clone(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "prost-messages")]
+    fn should_serve_prost_applications() {
+        use crate::prost::{self, request, response, Prost};
+
+        let request = |value| prost::Request { value: Some(value) };
+        let check_tx = |tx: Vec<u8>| {
+            request(request::Value::CheckTx(prost::RequestCheckTx {
+                tx,
+                ..Default::default()
+            }))
+        };
+        let config = ServerConfig::new();
+        with_backend_connection::<Prost, _, _, _>(ProstApp, config, |mut client| async move {
+            client.send(check_tx(vec![1])).await.unwrap();
+            client.send(check_tx(Vec::new())).await.unwrap();
+            let echo = prost::RequestEcho {
+                message: String::from("hello"),
+            };
+            client
+                .send(request(request::Value::Echo(echo)))
+                .await
+                .unwrap();
+            client
+                .send(request(request::Value::Flush(prost::RequestFlush {})))
+                .await
+                .unwrap();
+
+            let mut responses = Vec::new();
+            for _ in 0..4 {
+                responses.push(client.next().await.unwrap().unwrap().value.unwrap());
+            }
+            match &responses[..] {
+                [response::Value::CheckTx(ok), response::Value::CheckTx(rejected), response::Value::Echo(echo), response::Value::Flush(_)] =>
+                {
+                    assert_eq!(ok.code, 0);
+                    assert_eq!(ok.data, vec![1]);
+                    assert_eq!(rejected.code, 4);
+                    assert_eq!(rejected.codespace, "auth");
+                    assert_eq!(rejected.log, "signature verification failed");
+                    assert_eq!(echo.message, "hello");
+                }
+                other => panic!("unexpected responses {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn should_keep_consensus_panics_fatal() {
+        let config = ServerConfig::new()
+            .panic_policy(ConnectionRole::Consensus, PanicPolicy::Exception)
+            .panic_policy(ConnectionRole::Query, PanicPolicy::Exception);
+        assert_eq!(
+            config.panic_policy_for(ConnectionRole::Consensus),
+            PanicPolicy::Abort
+        );
+        assert_eq!(
+            config.panic_policy_for(ConnectionRole::Mempool),
+            PanicPolicy::Abort
+        );
+        assert_eq!(
+            config.panic_policy_for(ConnectionRole::Query),
+            PanicPolicy::Exception
+        );
+    }
 }
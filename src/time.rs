@@ -0,0 +1,457 @@
+//! Conversions between the protobuf `Timestamp` and `Duration` messages and `std::time` (or, with
+//! the `chrono` feature, `chrono`).
+//!
+//! Every conversion is checked: a `Timestamp` must lie between 0001-01-01 and 9999-12-31 and a
+//! `Duration` within 10,000 years, with nanos in range, as the protobuf documentation requires.
+//! The generated `Header`, `Evidence`, `RequestInitChain` and `EvidenceParams` messages also get
+//! accessors built on these functions:
+//!
+//! ```rust
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! let mut header = abci::Header::new();
+//! header.set_system_time(UNIX_EPOCH + Duration::from_secs(1_595_000_000)).unwrap();
+//! let age = header.system_time().unwrap().duration_since(UNIX_EPOCH).unwrap();
+//! assert_eq!(age.as_secs(), 1_595_000_000);
+//! ```
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use protobuf::well_known_types;
+
+use crate::messages::abci::*;
+
+/// Seconds of 0001-01-01T00:00:00Z, the earliest valid `Timestamp`.
+pub const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+/// Seconds of 9999-12-31T23:59:59Z, the latest valid `Timestamp`.
+pub const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+/// Longest valid `Duration`, about 10,000 years.
+pub const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+const NANOS_PER_SECOND: i32 = 1_000_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Why a time could not be converted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeError {
+    /// The seconds are outside the valid range of the message.
+    SecondsOutOfRange(i64),
+    /// The nanos are negative, a second or more, or (for a `Duration`) of a different sign than
+    /// the seconds.
+    NanosOutOfRange(i32),
+    /// A negative `Duration`, which `std::time::Duration` cannot hold.
+    Negative,
+    /// The time is valid, but does not fit in the target type on this platform.
+    Unrepresentable,
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeError::SecondsOutOfRange(seconds) => write!(f, "seconds {} out of range", seconds),
+            TimeError::NanosOutOfRange(nanos) => write!(f, "nanos {} out of range", nanos),
+            TimeError::Negative => write!(f, "negative duration"),
+            TimeError::Unrepresentable => write!(f, "time not representable"),
+        }
+    }
+}
+
+impl Error for TimeError {}
+
+fn check_timestamp(seconds: i64, nanos: i32) -> Result<(), TimeError> {
+    if !(MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
+        return Err(TimeError::SecondsOutOfRange(seconds));
+    }
+    if !(0..NANOS_PER_SECOND).contains(&nanos) {
+        return Err(TimeError::NanosOutOfRange(nanos));
+    }
+    Ok(())
+}
+
+pub fn to_system_time(timestamp: &well_known_types::Timestamp) -> Result<SystemTime, TimeError> {
+    let (seconds, nanos) = (timestamp.get_seconds(), timestamp.get_nanos());
+    check_timestamp(seconds, nanos)?;
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanos as u32))
+    } else {
+        UNIX_EPOCH
+            .checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+            .and_then(|time| time.checked_add(Duration::from_nanos(nanos as u64)))
+    };
+    time.ok_or(TimeError::Unrepresentable)
+}
+
+/// Seconds and nanos since the epoch, with the nanos always counting forwards.
+fn split(time: SystemTime) -> (i64, i32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (
+            i64::try_from(since.as_secs()).unwrap_or(i64::MAX),
+            since.subsec_nanos() as i32,
+        ),
+        Err(before) => {
+            let before = before.duration();
+            let seconds = i64::try_from(before.as_secs()).map_or(i64::MIN, |seconds| -seconds);
+            match before.subsec_nanos() as i32 {
+                0 => (seconds, 0),
+                nanos => (seconds.saturating_sub(1), NANOS_PER_SECOND - nanos),
+            }
+        }
+    }
+}
+
+pub fn from_system_time(time: SystemTime) -> Result<well_known_types::Timestamp, TimeError> {
+    let (seconds, nanos) = split(time);
+    check_timestamp(seconds, nanos)?;
+    Ok(timestamp(seconds, nanos))
+}
+
+/// Like `from_system_time`, for callers that cannot fail. Out of range times are written as is.
+pub(crate) fn from_system_time_unchecked(time: SystemTime) -> well_known_types::Timestamp {
+    let (seconds, nanos) = split(time);
+    timestamp(seconds, nanos)
+}
+
+fn timestamp(seconds: i64, nanos: i32) -> well_known_types::Timestamp {
+    let mut timestamp = well_known_types::Timestamp::new();
+    timestamp.set_seconds(seconds);
+    timestamp.set_nanos(nanos);
+    timestamp
+}
+
+pub fn to_duration(duration: &well_known_types::Duration) -> Result<Duration, TimeError> {
+    let (seconds, nanos) = (duration.get_seconds(), duration.get_nanos());
+    if !(-MAX_DURATION_SECONDS..=MAX_DURATION_SECONDS).contains(&seconds) {
+        return Err(TimeError::SecondsOutOfRange(seconds));
+    }
+    if nanos <= -NANOS_PER_SECOND
+        || nanos >= NANOS_PER_SECOND
+        || (seconds > 0 && nanos < 0)
+        || (seconds < 0 && nanos > 0)
+    {
+        return Err(TimeError::NanosOutOfRange(nanos));
+    }
+    if seconds < 0 || nanos < 0 {
+        return Err(TimeError::Negative);
+    }
+    Ok(Duration::new(seconds as u64, nanos as u32))
+}
+
+pub fn from_duration(duration: Duration) -> Result<well_known_types::Duration, TimeError> {
+    if duration.as_secs() > MAX_DURATION_SECONDS as u64 {
+        return Err(TimeError::SecondsOutOfRange(
+            i64::try_from(duration.as_secs()).unwrap_or(i64::MAX),
+        ));
+    }
+    Ok(from_duration_unchecked(duration))
+}
+
+/// Like `from_duration`, for callers that cannot fail.
+pub(crate) fn from_duration_unchecked(duration: Duration) -> well_known_types::Duration {
+    let mut wire = well_known_types::Duration::new();
+    wire.set_seconds(duration.as_secs() as i64);
+    wire.set_nanos(duration.subsec_nanos() as i32);
+    wire
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats like Go's `time.RFC3339Nano` in UTC: trailing zeros of the fraction are dropped.
+pub(crate) fn format_rfc3339(timestamp: &well_known_types::Timestamp) -> String {
+    let seconds = timestamp.get_seconds();
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+    let time_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let mut formatted = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60
+    );
+    if timestamp.get_nanos() != 0 {
+        let fraction = format!("{:09}", timestamp.get_nanos());
+        formatted.push('.');
+        formatted.push_str(fraction.trim_end_matches('0'));
+    }
+    formatted.push('Z');
+    formatted
+}
+
+/// Parses an RFC 3339 timestamp with an optional fraction of up to nine digits.
+pub(crate) fn parse_rfc3339(text: &str) -> Option<well_known_types::Timestamp> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = text.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let separators = text.as_bytes();
+    if separators.len() < 20
+        || separators[4] != b'-'
+        || separators[7] != b'-'
+        || !(separators[10] == b'T' || separators[10] == b't')
+        || separators[13] != b':'
+        || separators[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // Leap seconds are not representable, as in Go.
+    if second > 59 {
+        return None;
+    }
+
+    let mut rest = &text[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 || len > 9 {
+            return None;
+        }
+        nanos = fraction[..len].parse::<i64>().ok()? * 10_i64.pow(9 - len as u32);
+        rest = &fraction[len..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours = rest[1..3].parse::<i64>().ok()?;
+            let minutes = rest[4..6].parse::<i64>().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    Some(timestamp(
+        days * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second - offset,
+        nanos as i32,
+    ))
+}
+
+#[cfg(feature = "chrono")]
+pub fn to_date_time(
+    timestamp: &well_known_types::Timestamp,
+) -> Result<chrono::DateTime<chrono::Utc>, TimeError> {
+    use chrono::TimeZone;
+
+    let (seconds, nanos) = (timestamp.get_seconds(), timestamp.get_nanos());
+    check_timestamp(seconds, nanos)?;
+    chrono::Utc
+        .timestamp_opt(seconds, nanos as u32)
+        .single()
+        .ok_or(TimeError::Unrepresentable)
+}
+
+/// Fails for leap seconds, which chrono represents with nanos of a second or more.
+#[cfg(feature = "chrono")]
+pub fn from_date_time<Tz: chrono::TimeZone>(
+    time: &chrono::DateTime<Tz>,
+) -> Result<well_known_types::Timestamp, TimeError> {
+    let (seconds, nanos) = (time.timestamp(), time.timestamp_subsec_nanos() as i32);
+    check_timestamp(seconds, nanos)?;
+    Ok(timestamp(seconds, nanos))
+}
+
+#[cfg(feature = "chrono")]
+pub fn to_chrono_duration(
+    duration: &well_known_types::Duration,
+) -> Result<chrono::Duration, TimeError> {
+    // Reuse the range and sign checks of `to_duration`, which only objects to the sign.
+    match to_duration(duration) {
+        Ok(_) | Err(TimeError::Negative) => {}
+        Err(error) => return Err(error),
+    }
+    Ok(chrono::Duration::seconds(duration.get_seconds())
+        + chrono::Duration::nanoseconds(duration.get_nanos().into()))
+}
+
+#[cfg(feature = "chrono")]
+pub fn from_chrono_duration(
+    duration: chrono::Duration,
+) -> Result<well_known_types::Duration, TimeError> {
+    let seconds = duration.num_seconds();
+    if !(-MAX_DURATION_SECONDS..=MAX_DURATION_SECONDS).contains(&seconds) {
+        return Err(TimeError::SecondsOutOfRange(seconds));
+    }
+    let nanos = (duration - chrono::Duration::seconds(seconds))
+        .num_nanoseconds()
+        .expect("sub-second nanos") as i32;
+    let mut wire = well_known_types::Duration::new();
+    wire.set_seconds(seconds);
+    wire.set_nanos(nanos);
+    Ok(wire)
+}
+
+macro_rules! time_accessors {
+    ($($message:ident),*) => {
+        $(
+            impl $message {
+                /// The `time` field as a `SystemTime`. An unset field reads as the Unix epoch.
+                pub fn system_time(&self) -> Result<SystemTime, TimeError> {
+                    to_system_time(self.get_time())
+                }
+
+                pub fn set_system_time(&mut self, time: SystemTime) -> Result<(), TimeError> {
+                    self.set_time(from_system_time(time)?);
+                    Ok(())
+                }
+
+                #[cfg(feature = "chrono")]
+                pub fn date_time(&self) -> Result<chrono::DateTime<chrono::Utc>, TimeError> {
+                    to_date_time(self.get_time())
+                }
+
+                #[cfg(feature = "chrono")]
+                pub fn set_date_time<Tz: chrono::TimeZone>(
+                    &mut self,
+                    time: &chrono::DateTime<Tz>,
+                ) -> Result<(), TimeError> {
+                    self.set_time(from_date_time(time)?);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+time_accessors!(Header, Evidence, RequestInitChain);
+
+impl EvidenceParams {
+    /// The `max_age_duration` field as a `Duration`. An unset field reads as zero.
+    pub fn max_age(&self) -> Result<Duration, TimeError> {
+        to_duration(self.get_max_age_duration())
+    }
+
+    pub fn set_max_age(&mut self, max_age: Duration) -> Result<(), TimeError> {
+        self.set_max_age_duration(from_duration(max_age)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_times_before_epoch() {
+        let time = UNIX_EPOCH - Duration::new(10, 250);
+        let wire = from_system_time(time).unwrap();
+        assert_eq!(wire.get_seconds(), -11);
+        assert_eq!(wire.get_nanos(), NANOS_PER_SECOND - 250);
+        assert_eq!(to_system_time(&wire), Ok(time));
+    }
+
+    #[test]
+    fn should_reject_out_of_range_timestamps() {
+        assert_eq!(
+            to_system_time(&timestamp(MAX_TIMESTAMP_SECONDS + 1, 0)),
+            Err(TimeError::SecondsOutOfRange(MAX_TIMESTAMP_SECONDS + 1))
+        );
+        assert_eq!(
+            to_system_time(&timestamp(0, -1)),
+            Err(TimeError::NanosOutOfRange(-1))
+        );
+        let far_future = UNIX_EPOCH + Duration::from_secs(MAX_TIMESTAMP_SECONDS as u64 + 1);
+        assert!(from_system_time(far_future).is_err());
+        let last = UNIX_EPOCH + Duration::new(MAX_TIMESTAMP_SECONDS as u64, 999_999_999);
+        assert_eq!(to_system_time(&from_system_time(last).unwrap()), Ok(last));
+    }
+
+    #[test]
+    fn should_check_durations() {
+        let mut wire = from_duration(Duration::new(172_800, 5)).unwrap();
+        assert_eq!(to_duration(&wire), Ok(Duration::new(172_800, 5)));
+        wire.set_nanos(-5);
+        assert_eq!(to_duration(&wire), Err(TimeError::NanosOutOfRange(-5)));
+        wire.set_seconds(-1);
+        assert_eq!(to_duration(&wire), Err(TimeError::Negative));
+        assert!(from_duration(Duration::from_secs(MAX_DURATION_SECONDS as u64 + 1)).is_err());
+    }
+
+    #[test]
+    fn should_read_and_write_message_times() {
+        let time = UNIX_EPOCH + Duration::new(1_595_000_000, 42);
+        let mut evidence = Evidence::new();
+        evidence.set_system_time(time).unwrap();
+        assert_eq!(evidence.get_time().get_nanos(), 42);
+        assert_eq!(evidence.system_time(), Ok(time));
+
+        let mut params = EvidenceParams::new();
+        assert_eq!(params.max_age(), Ok(Duration::from_secs(0)));
+        params.set_max_age(Duration::from_secs(172_800)).unwrap();
+        assert_eq!(params.get_max_age_duration().get_seconds(), 172_800);
+    }
+
+    #[test]
+    fn should_format_rfc3339s_like_go() {
+        let mut timestamp = well_known_types::Timestamp::new();
+        assert_eq!(format_rfc3339(&timestamp), "1970-01-01T00:00:00Z");
+        timestamp.set_seconds(-62_135_596_800);
+        assert_eq!(format_rfc3339(&timestamp), "0001-01-01T00:00:00Z");
+        timestamp.set_seconds(951_782_400);
+        timestamp.set_nanos(5_000_000);
+        assert_eq!(format_rfc3339(&timestamp), "2000-02-29T00:00:00.005Z");
+        assert_eq!(parse_rfc3339("2000-02-29T00:00:00.005Z"), Some(timestamp));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn should_convert_to_chrono() {
+        use chrono::TimeZone;
+
+        let time = chrono::Utc.timestamp_opt(1_595_000_000, 7).unwrap();
+        assert_eq!(time.to_rfc3339(), "2020-07-17T15:33:20.000000007+00:00");
+        let mut header = Header::new();
+        header.set_date_time(&time).unwrap();
+        assert_eq!(header.get_time().get_seconds(), 1_595_000_000);
+        assert_eq!(header.date_time(), Ok(time));
+
+        let duration = chrono::Duration::milliseconds(-1_500);
+        let wire = from_chrono_duration(duration).unwrap();
+        assert_eq!((wire.get_seconds(), wire.get_nanos()), (-1, -500_000_000));
+        assert_eq!(to_chrono_duration(&wire), Ok(duration));
+    }
+}
@@ -0,0 +1,724 @@
+//! Plain Rust versions of the ABCI data types.
+//!
+//! The generated protobuf messages (re-exported at the crate root) mirror the wire format: every
+//! field is optional, nested messages sit behind `SingularPtrField` and lists behind
+//! `RepeatedField`. The types in this module hold the same data as ordinary structs, and the
+//! `TryFrom` conversions from the wire types check that fields Tendermint always sets are present
+//! and within range.
+//!
+//! ```rust
+//! use std::convert::TryFrom;
+//!
+//! fn proposer(req: &abci::RequestBeginBlock) -> Result<Vec<u8>, abci::types::ConversionError> {
+//!     let header = abci::types::Header::try_from(req.get_header())?;
+//!     Ok(header.proposer_address)
+//! }
+//! ```
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use protobuf::well_known_types;
+
+use crate::messages::abci as wire;
+use crate::messages::types as kv;
+use crate::time;
+
+/// Why a wire message could not be converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConversionError {
+    /// A field Tendermint always sets is missing.
+    MissingField(&'static str),
+    /// A field holds a value outside its valid range.
+    InvalidField { field: &'static str, reason: String },
+}
+
+impl ConversionError {
+    fn invalid<S: Into<String>>(field: &'static str, reason: S) -> ConversionError {
+        ConversionError::InvalidField {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::MissingField(field) => write!(f, "missing field {}", field),
+            ConversionError::InvalidField { field, reason } => {
+                write!(f, "invalid field {}: {}", field, reason)
+            }
+        }
+    }
+}
+
+impl Error for ConversionError {}
+
+/// Longest chain ID Tendermint accepts.
+pub const MAX_CHAIN_ID_LEN: usize = 50;
+
+fn system_time(
+    field: &'static str,
+    timestamp: &well_known_types::Timestamp,
+) -> Result<SystemTime, ConversionError> {
+    time::to_system_time(timestamp).map_err(|e| ConversionError::invalid(field, e.to_string()))
+}
+
+fn duration(
+    field: &'static str,
+    duration: &well_known_types::Duration,
+) -> Result<Duration, ConversionError> {
+    time::to_duration(duration).map_err(|e| ConversionError::invalid(field, e.to_string()))
+}
+
+fn non_negative(field: &'static str, value: i64) -> Result<i64, ConversionError> {
+    if value < 0 {
+        return Err(ConversionError::invalid(field, "must not be negative"));
+    }
+    Ok(value)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub block: u64,
+    pub app: u64,
+}
+
+impl From<&wire::Version> for Version {
+    fn from(version: &wire::Version) -> Version {
+        Version {
+            block: version.get_Block(),
+            app: version.get_App(),
+        }
+    }
+}
+
+impl From<Version> for wire::Version {
+    fn from(version: Version) -> wire::Version {
+        let mut wire = wire::Version::new();
+        wire.set_Block(version.block);
+        wire.set_App(version.app);
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PartSetHeader {
+    /// Number of parts, never negative. An `i32` as on the wire, so that any value converts back.
+    pub total: i32,
+    pub hash: Vec<u8>,
+}
+
+impl TryFrom<&wire::PartSetHeader> for PartSetHeader {
+    type Error = ConversionError;
+
+    fn try_from(parts: &wire::PartSetHeader) -> Result<PartSetHeader, ConversionError> {
+        if parts.get_total() < 0 {
+            return Err(ConversionError::invalid(
+                "part_set_header.total",
+                "negative",
+            ));
+        }
+        Ok(PartSetHeader {
+            total: parts.get_total(),
+            hash: parts.get_hash().to_vec(),
+        })
+    }
+}
+
+impl From<PartSetHeader> for wire::PartSetHeader {
+    fn from(parts: PartSetHeader) -> wire::PartSetHeader {
+        let mut wire = wire::PartSetHeader::new();
+        wire.set_total(parts.total);
+        wire.set_hash(parts.hash);
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockId {
+    pub hash: Vec<u8>,
+    pub parts: PartSetHeader,
+}
+
+impl TryFrom<&wire::BlockID> for BlockId {
+    type Error = ConversionError;
+
+    fn try_from(id: &wire::BlockID) -> Result<BlockId, ConversionError> {
+        Ok(BlockId {
+            hash: id.get_hash().to_vec(),
+            parts: PartSetHeader::try_from(id.get_parts_header())?,
+        })
+    }
+}
+
+impl From<BlockId> for wire::BlockID {
+    fn from(id: BlockId) -> wire::BlockID {
+        let mut wire = wire::BlockID::new();
+        wire.set_hash(id.hash);
+        wire.set_parts_header(id.parts.into());
+        wire
+    }
+}
+
+/// A block header, as passed to `begin_block`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: Version,
+    pub chain_id: String,
+    pub height: i64,
+    pub time: SystemTime,
+    pub last_block_id: BlockId,
+    pub last_commit_hash: Vec<u8>,
+    pub data_hash: Vec<u8>,
+    pub validators_hash: Vec<u8>,
+    pub next_validators_hash: Vec<u8>,
+    pub consensus_hash: Vec<u8>,
+    pub app_hash: Vec<u8>,
+    pub last_results_hash: Vec<u8>,
+    pub evidence_hash: Vec<u8>,
+    pub proposer_address: Vec<u8>,
+}
+
+impl TryFrom<&wire::Header> for Header {
+    type Error = ConversionError;
+
+    fn try_from(header: &wire::Header) -> Result<Header, ConversionError> {
+        if !header.has_version() {
+            return Err(ConversionError::MissingField("header.version"));
+        }
+        if !header.has_time() {
+            return Err(ConversionError::MissingField("header.time"));
+        }
+        if header.get_chain_id().len() > MAX_CHAIN_ID_LEN {
+            return Err(ConversionError::invalid(
+                "header.chain_id",
+                format!("longer than {} bytes", MAX_CHAIN_ID_LEN),
+            ));
+        }
+        Ok(Header {
+            version: Version::from(header.get_version()),
+            chain_id: header.get_chain_id().to_string(),
+            height: non_negative("header.height", header.get_height())?,
+            time: system_time("header.time", header.get_time())?,
+            last_block_id: BlockId::try_from(header.get_last_block_id())?,
+            last_commit_hash: header.get_last_commit_hash().to_vec(),
+            data_hash: header.get_data_hash().to_vec(),
+            validators_hash: header.get_validators_hash().to_vec(),
+            next_validators_hash: header.get_next_validators_hash().to_vec(),
+            consensus_hash: header.get_consensus_hash().to_vec(),
+            app_hash: header.get_app_hash().to_vec(),
+            last_results_hash: header.get_last_results_hash().to_vec(),
+            evidence_hash: header.get_evidence_hash().to_vec(),
+            proposer_address: header.get_proposer_address().to_vec(),
+        })
+    }
+}
+
+impl From<Header> for wire::Header {
+    fn from(header: Header) -> wire::Header {
+        let mut wire = wire::Header::new();
+        wire.set_version(header.version.into());
+        wire.set_chain_id(header.chain_id);
+        wire.set_height(header.height);
+        wire.set_time(time::from_system_time_unchecked(header.time));
+        wire.set_last_block_id(header.last_block_id.into());
+        wire.set_last_commit_hash(header.last_commit_hash);
+        wire.set_data_hash(header.data_hash);
+        wire.set_validators_hash(header.validators_hash);
+        wire.set_next_validators_hash(header.next_validators_hash);
+        wire.set_consensus_hash(header.consensus_hash);
+        wire.set_app_hash(header.app_hash);
+        wire.set_last_results_hash(header.last_results_hash);
+        wire.set_evidence_hash(header.evidence_hash);
+        wire.set_proposer_address(header.proposer_address);
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Validator {
+    pub address: Vec<u8>,
+    pub power: i64,
+}
+
+impl TryFrom<&wire::Validator> for Validator {
+    type Error = ConversionError;
+
+    fn try_from(validator: &wire::Validator) -> Result<Validator, ConversionError> {
+        Ok(Validator {
+            address: validator.get_address().to_vec(),
+            power: non_negative("validator.power", validator.get_power())?,
+        })
+    }
+}
+
+impl From<Validator> for wire::Validator {
+    fn from(validator: Validator) -> wire::Validator {
+        let mut wire = wire::Validator::new();
+        wire.set_address(validator.address);
+        wire.set_power(validator.power);
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PubKey {
+    /// Key algorithm, e.g. `"ed25519"`.
+    pub key_type: String,
+    pub data: Vec<u8>,
+}
+
+impl From<&wire::PubKey> for PubKey {
+    fn from(key: &wire::PubKey) -> PubKey {
+        PubKey {
+            key_type: key.get_field_type().to_string(),
+            data: key.get_data().to_vec(),
+        }
+    }
+}
+
+impl From<PubKey> for wire::PubKey {
+    fn from(key: PubKey) -> wire::PubKey {
+        let mut wire = wire::PubKey::new();
+        wire.set_field_type(key.key_type);
+        wire.set_data(key.data);
+        wire
+    }
+}
+
+/// A change to the validator set. A power of zero removes the validator.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ValidatorUpdate {
+    pub pub_key: PubKey,
+    pub power: i64,
+}
+
+impl TryFrom<&wire::ValidatorUpdate> for ValidatorUpdate {
+    type Error = ConversionError;
+
+    fn try_from(update: &wire::ValidatorUpdate) -> Result<ValidatorUpdate, ConversionError> {
+        if !update.has_pub_key() {
+            return Err(ConversionError::MissingField("validator_update.pub_key"));
+        }
+        Ok(ValidatorUpdate {
+            pub_key: PubKey::from(update.get_pub_key()),
+            power: non_negative("validator_update.power", update.get_power())?,
+        })
+    }
+}
+
+impl From<ValidatorUpdate> for wire::ValidatorUpdate {
+    fn from(update: ValidatorUpdate) -> wire::ValidatorUpdate {
+        let mut wire = wire::ValidatorUpdate::new();
+        wire.set_pub_key(update.pub_key.into());
+        wire.set_power(update.power);
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VoteInfo {
+    pub validator: Validator,
+    pub signed_last_block: bool,
+}
+
+impl TryFrom<&wire::VoteInfo> for VoteInfo {
+    type Error = ConversionError;
+
+    fn try_from(vote: &wire::VoteInfo) -> Result<VoteInfo, ConversionError> {
+        if !vote.has_validator() {
+            return Err(ConversionError::MissingField("vote_info.validator"));
+        }
+        Ok(VoteInfo {
+            validator: Validator::try_from(vote.get_validator())?,
+            signed_last_block: vote.get_signed_last_block(),
+        })
+    }
+}
+
+impl From<VoteInfo> for wire::VoteInfo {
+    fn from(vote: VoteInfo) -> wire::VoteInfo {
+        let mut wire = wire::VoteInfo::new();
+        wire.set_validator(vote.validator.into());
+        wire.set_signed_last_block(vote.signed_last_block);
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LastCommitInfo {
+    pub round: i32,
+    pub votes: Vec<VoteInfo>,
+}
+
+impl TryFrom<&wire::LastCommitInfo> for LastCommitInfo {
+    type Error = ConversionError;
+
+    fn try_from(info: &wire::LastCommitInfo) -> Result<LastCommitInfo, ConversionError> {
+        Ok(LastCommitInfo {
+            round: info.get_round(),
+            votes: info
+                .get_votes()
+                .iter()
+                .map(VoteInfo::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<LastCommitInfo> for wire::LastCommitInfo {
+    fn from(info: LastCommitInfo) -> wire::LastCommitInfo {
+        let mut wire = wire::LastCommitInfo::new();
+        wire.set_round(info.round);
+        wire.set_votes(info.votes.into_iter().map(Into::into).collect());
+        wire
+    }
+}
+
+/// Misbehaviour by a validator, as reported in `RequestBeginBlock.byzantine_validators`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Evidence {
+    /// Kind of misbehaviour, e.g. `"duplicate/vote"`.
+    pub evidence_type: String,
+    pub validator: Validator,
+    pub height: i64,
+    pub time: SystemTime,
+    pub total_voting_power: i64,
+}
+
+impl TryFrom<&wire::Evidence> for Evidence {
+    type Error = ConversionError;
+
+    fn try_from(evidence: &wire::Evidence) -> Result<Evidence, ConversionError> {
+        if !evidence.has_validator() {
+            return Err(ConversionError::MissingField("evidence.validator"));
+        }
+        if !evidence.has_time() {
+            return Err(ConversionError::MissingField("evidence.time"));
+        }
+        Ok(Evidence {
+            evidence_type: evidence.get_field_type().to_string(),
+            validator: Validator::try_from(evidence.get_validator())?,
+            height: non_negative("evidence.height", evidence.get_height())?,
+            time: system_time("evidence.time", evidence.get_time())?,
+            total_voting_power: non_negative(
+                "evidence.total_voting_power",
+                evidence.get_total_voting_power(),
+            )?,
+        })
+    }
+}
+
+impl From<Evidence> for wire::Evidence {
+    fn from(evidence: Evidence) -> wire::Evidence {
+        let mut wire = wire::Evidence::new();
+        wire.set_field_type(evidence.evidence_type);
+        wire.set_validator(evidence.validator.into());
+        wire.set_height(evidence.height);
+        wire.set_time(time::from_system_time_unchecked(evidence.time));
+        wire.set_total_voting_power(evidence.total_voting_power);
+        wire
+    }
+}
+
+/// A key/value attribute of an `Event`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Attribute {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl From<&kv::Pair> for Attribute {
+    fn from(pair: &kv::Pair) -> Attribute {
+        Attribute {
+            key: pair.get_key().to_vec(),
+            value: pair.get_value().to_vec(),
+        }
+    }
+}
+
+impl From<Attribute> for kv::Pair {
+    fn from(attribute: Attribute) -> kv::Pair {
+        let mut pair = kv::Pair::new();
+        pair.set_key(attribute.key);
+        pair.set_value(attribute.value);
+        pair
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// The event type, e.g. `"transfer"`.
+    pub kind: String,
+    pub attributes: Vec<Attribute>,
+}
+
+impl From<&wire::Event> for Event {
+    fn from(event: &wire::Event) -> Event {
+        Event {
+            kind: event.get_field_type().to_string(),
+            attributes: event.get_attributes().iter().map(Attribute::from).collect(),
+        }
+    }
+}
+
+impl From<Event> for wire::Event {
+    fn from(event: Event) -> wire::Event {
+        let mut wire = wire::Event::new();
+        wire.set_field_type(event.kind);
+        wire.set_attributes(event.attributes.into_iter().map(Into::into).collect());
+        wire
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockParams {
+    /// Maximum block size in bytes.
+    pub max_bytes: i64,
+    /// Maximum gas per block, or -1 for no limit.
+    pub max_gas: i64,
+}
+
+impl TryFrom<&wire::BlockParams> for BlockParams {
+    type Error = ConversionError;
+
+    fn try_from(params: &wire::BlockParams) -> Result<BlockParams, ConversionError> {
+        if params.get_max_gas() < -1 {
+            return Err(ConversionError::invalid(
+                "block_params.max_gas",
+                "must be -1 or more",
+            ));
+        }
+        Ok(BlockParams {
+            max_bytes: non_negative("block_params.max_bytes", params.get_max_bytes())?,
+            max_gas: params.get_max_gas(),
+        })
+    }
+}
+
+impl From<BlockParams> for wire::BlockParams {
+    fn from(params: BlockParams) -> wire::BlockParams {
+        let mut wire = wire::BlockParams::new();
+        wire.set_max_bytes(params.max_bytes);
+        wire.set_max_gas(params.max_gas);
+        wire
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EvidenceParams {
+    pub max_age_num_blocks: i64,
+    pub max_age_duration: Duration,
+}
+
+impl TryFrom<&wire::EvidenceParams> for EvidenceParams {
+    type Error = ConversionError;
+
+    fn try_from(params: &wire::EvidenceParams) -> Result<EvidenceParams, ConversionError> {
+        if !params.has_max_age_duration() {
+            return Err(ConversionError::MissingField(
+                "evidence_params.max_age_duration",
+            ));
+        }
+        Ok(EvidenceParams {
+            max_age_num_blocks: non_negative(
+                "evidence_params.max_age_num_blocks",
+                params.get_max_age_num_blocks(),
+            )?,
+            max_age_duration: duration(
+                "evidence_params.max_age_duration",
+                params.get_max_age_duration(),
+            )?,
+        })
+    }
+}
+
+impl From<EvidenceParams> for wire::EvidenceParams {
+    fn from(params: EvidenceParams) -> wire::EvidenceParams {
+        let mut wire = wire::EvidenceParams::new();
+        wire.set_max_age_num_blocks(params.max_age_num_blocks);
+        wire.set_max_age_duration(time::from_duration_unchecked(params.max_age_duration));
+        wire
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ValidatorParams {
+    /// Key algorithms validators may use, e.g. `["ed25519"]`.
+    pub pub_key_types: Vec<String>,
+}
+
+impl From<&wire::ValidatorParams> for ValidatorParams {
+    fn from(params: &wire::ValidatorParams) -> ValidatorParams {
+        ValidatorParams {
+            pub_key_types: params.get_pub_key_types().to_vec(),
+        }
+    }
+}
+
+impl From<ValidatorParams> for wire::ValidatorParams {
+    fn from(params: ValidatorParams) -> wire::ValidatorParams {
+        let mut wire = wire::ValidatorParams::new();
+        wire.set_pub_key_types(params.pub_key_types.into());
+        wire
+    }
+}
+
+/// Consensus parameters. Each group is optional, since `ResponseEndBlock` only carries the
+/// groups that change.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConsensusParams {
+    pub block: Option<BlockParams>,
+    pub evidence: Option<EvidenceParams>,
+    pub validator: Option<ValidatorParams>,
+}
+
+impl TryFrom<&wire::ConsensusParams> for ConsensusParams {
+    type Error = ConversionError;
+
+    fn try_from(params: &wire::ConsensusParams) -> Result<ConsensusParams, ConversionError> {
+        Ok(ConsensusParams {
+            block: match params.block.as_ref() {
+                Some(block) => Some(BlockParams::try_from(block)?),
+                None => None,
+            },
+            evidence: match params.evidence.as_ref() {
+                Some(evidence) => Some(EvidenceParams::try_from(evidence)?),
+                None => None,
+            },
+            validator: params.validator.as_ref().map(ValidatorParams::from),
+        })
+    }
+}
+
+impl From<ConsensusParams> for wire::ConsensusParams {
+    fn from(params: ConsensusParams) -> wire::ConsensusParams {
+        let mut wire = wire::ConsensusParams::new();
+        if let Some(block) = params.block {
+            wire.set_block(block.into());
+        }
+        if let Some(evidence) = params.evidence {
+            wire.set_evidence(evidence.into());
+        }
+        if let Some(validator) = params.validator {
+            wire.set_validator(validator.into());
+        }
+        wire
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn header() -> Header {
+        Header {
+            version: Version { block: 10, app: 1 },
+            chain_id: String::from("test-chain"),
+            height: 5,
+            time: UNIX_EPOCH + Duration::new(1_595_000_000, 123),
+            last_block_id: BlockId {
+                hash: vec![1; 32],
+                parts: PartSetHeader {
+                    total: 1,
+                    hash: vec![2; 32],
+                },
+            },
+            last_commit_hash: vec![3; 32],
+            data_hash: vec![4; 32],
+            validators_hash: vec![5; 32],
+            next_validators_hash: vec![6; 32],
+            consensus_hash: vec![7; 32],
+            app_hash: vec![8; 8],
+            last_results_hash: vec![9; 32],
+            evidence_hash: vec![],
+            proposer_address: vec![10; 20],
+        }
+    }
+
+    #[test]
+    fn should_round_trip_header() {
+        let wire = wire::Header::from(header());
+        assert_eq!(wire.get_time().get_seconds(), 1_595_000_000);
+        assert_eq!(wire.get_time().get_nanos(), 123);
+        assert_eq!(Header::try_from(&wire), Ok(header()));
+    }
+
+    #[test]
+    fn should_reject_missing_and_invalid_fields() {
+        let mut wire = wire::Header::from(header());
+        wire.clear_time();
+        assert_eq!(
+            Header::try_from(&wire),
+            Err(ConversionError::MissingField("header.time"))
+        );
+
+        let mut wire = wire::Header::from(header());
+        wire.mut_time().set_nanos(1_000_000_000);
+        assert_eq!(
+            Header::try_from(&wire),
+            Err(ConversionError::invalid(
+                "header.time",
+                "nanos 1000000000 out of range"
+            ))
+        );
+
+        let mut wire = wire::Header::from(header());
+        wire.set_height(-1);
+        assert!(Header::try_from(&wire).is_err());
+
+        let mut wire = wire::Header::from(header());
+        wire.mut_last_block_id().mut_parts_header().set_total(-1);
+        assert_eq!(
+            Header::try_from(&wire),
+            Err(ConversionError::invalid(
+                "part_set_header.total",
+                "negative"
+            ))
+        );
+
+        let mut update = wire::ValidatorUpdate::new();
+        update.set_power(10);
+        assert_eq!(
+            ValidatorUpdate::try_from(&update),
+            Err(ConversionError::MissingField("validator_update.pub_key"))
+        );
+    }
+
+    #[test]
+    fn should_round_trip_consensus_params() {
+        let params = ConsensusParams {
+            block: Some(BlockParams {
+                max_bytes: 22_020_096,
+                max_gas: -1,
+            }),
+            evidence: Some(EvidenceParams {
+                max_age_num_blocks: 100_000,
+                max_age_duration: Duration::from_secs(172_800),
+            }),
+            validator: None,
+        };
+        let wire = wire::ConsensusParams::from(params.clone());
+        assert!(!wire.has_validator());
+        assert_eq!(ConsensusParams::try_from(&wire), Ok(params));
+    }
+
+    #[test]
+    fn should_round_trip_events() {
+        let event = Event {
+            kind: String::from("transfer"),
+            attributes: vec![Attribute {
+                key: b"sender".to_vec(),
+                value: b"abc".to_vec(),
+            }],
+        };
+        let wire = wire::Event::from(event.clone());
+        assert_eq!(wire.get_field_type(), "transfer");
+        assert_eq!(wire.get_attributes()[0].get_key(), b"sender");
+        assert_eq!(Event::from(&wire), event);
+    }
+}
@@ -0,0 +1,362 @@
+//! Tracks an application's validator set and computes the `ValidatorUpdate`s that move
+//! Tendermint to a desired set, checked the way Tendermint checks them: powers are not negative,
+//! no key appears twice, key types are allowed by `ValidatorParams.pub_key_types`, only validators
+//! in the set are removed, and the set neither empties nor exceeds the maximum total voting power.
+//!
+//! ```rust
+//! use abci::types::{PubKey, ValidatorParams, ValidatorUpdate};
+//! use abci::validators::ValidatorSet;
+//!
+//! let key = |byte| PubKey {
+//!     key_type: String::from("ed25519"),
+//!     data: vec![byte; 32],
+//! };
+//! let update = |byte, power| ValidatorUpdate {
+//!     pub_key: key(byte),
+//!     power,
+//! };
+//!
+//! let mut validators =
+//!     ValidatorSet::from_updates(ValidatorParams::default(), &[update(1, 10), update(2, 10)])
+//!         .unwrap();
+//! let updates = validators.update_to(&[update(1, 10), update(3, 5)]).unwrap();
+//! assert_eq!(updates, vec![update(2, 0), update(3, 5)]);
+//!
+//! let mut response = abci::ResponseEndBlock::new();
+//! response.set_validator_updates(updates.into_iter().map(Into::into).collect());
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::merkle::to_hex;
+use crate::types::{PubKey, ValidatorParams, ValidatorUpdate};
+
+/// The largest total voting power Tendermint accepts.
+pub const MAX_TOTAL_VOTING_POWER: i64 = i64::MAX / 8;
+
+/// The key type Tendermint allows when `ValidatorParams.pub_key_types` is empty.
+pub const DEFAULT_PUB_KEY_TYPE: &str = "ed25519";
+
+/// Why validator updates were rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidatorSetError {
+    NegativePower {
+        pub_key: PubKey,
+        power: i64,
+    },
+    DuplicateKey(PubKey),
+    /// The key's type is not in `ValidatorParams.pub_key_types`.
+    UnsupportedKeyType {
+        pub_key: PubKey,
+        allowed: Vec<String>,
+    },
+    /// A zero-power update for a validator not in the set.
+    UnknownValidator(PubKey),
+    /// The updates would remove every validator.
+    EmptySet,
+    /// The updates would take the total voting power above `MAX_TOTAL_VOTING_POWER`.
+    TotalPowerTooHigh,
+}
+
+impl fmt::Display for ValidatorSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidatorSetError::NegativePower { pub_key, power } => write!(
+                f,
+                "validator {} has negative power {}",
+                key_name(pub_key),
+                power
+            ),
+            ValidatorSetError::DuplicateKey(pub_key) => {
+                write!(f, "duplicate validator {}", key_name(pub_key))
+            }
+            ValidatorSetError::UnsupportedKeyType { pub_key, allowed } => write!(
+                f,
+                "validator {} has key type {:?}, expected one of {:?}",
+                key_name(pub_key),
+                pub_key.key_type,
+                allowed
+            ),
+            ValidatorSetError::UnknownValidator(pub_key) => {
+                write!(f, "cannot remove unknown validator {}", key_name(pub_key))
+            }
+            ValidatorSetError::EmptySet => {
+                write!(f, "applying the updates would leave no validators")
+            }
+            ValidatorSetError::TotalPowerTooHigh => write!(
+                f,
+                "total voting power would exceed {}",
+                MAX_TOTAL_VOTING_POWER
+            ),
+        }
+    }
+}
+
+impl Error for ValidatorSetError {}
+
+fn key_name(pub_key: &PubKey) -> String {
+    format!("{}:{}", pub_key.key_type, to_hex(&pub_key.data))
+}
+
+/// The validators of an application with their powers, and the key types it accepts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidatorSet {
+    powers: BTreeMap<PubKey, i64>,
+    params: ValidatorParams,
+}
+
+impl ValidatorSet {
+    /// An empty set accepting the key types of `params`, or only `ed25519` keys if it lists
+    /// none.
+    pub fn new(params: ValidatorParams) -> ValidatorSet {
+        ValidatorSet {
+            powers: BTreeMap::new(),
+            params,
+        }
+    }
+
+    /// The set of `RequestInitChain.validators` or `ResponseInitChain.validators`.
+    pub fn from_updates(
+        params: ValidatorParams,
+        updates: &[ValidatorUpdate],
+    ) -> Result<ValidatorSet, ValidatorSetError> {
+        let mut set = ValidatorSet::new(params);
+        set.apply(updates)?;
+        Ok(set)
+    }
+
+    /// Replaces the parameters, e.g. after `ResponseEndBlock.consensus_param_updates` changes
+    /// them. Current validators are kept.
+    pub fn set_params(&mut self, params: ValidatorParams) {
+        self.params = params;
+    }
+
+    /// The power of the validator with `pub_key`, zero if it is not in the set.
+    pub fn power(&self, pub_key: &PubKey) -> i64 {
+        self.powers.get(pub_key).copied().unwrap_or(0)
+    }
+
+    pub fn total_power(&self) -> i64 {
+        self.powers.values().sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.powers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.powers.is_empty()
+    }
+
+    /// The validators, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&PubKey, i64)> {
+        self.powers.iter().map(|(pub_key, power)| (pub_key, *power))
+    }
+
+    /// Checks powers, key types and duplicates, without applying the updates.
+    pub fn validate(&self, updates: &[ValidatorUpdate]) -> Result<(), ValidatorSetError> {
+        let mut seen = BTreeSet::new();
+        for update in updates {
+            if update.power < 0 {
+                return Err(ValidatorSetError::NegativePower {
+                    pub_key: update.pub_key.clone(),
+                    power: update.power,
+                });
+            }
+            if !self.allows(&update.pub_key.key_type) {
+                return Err(ValidatorSetError::UnsupportedKeyType {
+                    pub_key: update.pub_key.clone(),
+                    allowed: self.allowed_key_types(),
+                });
+            }
+            if !seen.insert(&update.pub_key) {
+                return Err(ValidatorSetError::DuplicateKey(update.pub_key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn allows(&self, key_type: &str) -> bool {
+        if self.params.pub_key_types.is_empty() {
+            key_type == DEFAULT_PUB_KEY_TYPE
+        } else {
+            self.params.pub_key_types.iter().any(|t| t == key_type)
+        }
+    }
+
+    fn allowed_key_types(&self) -> Vec<String> {
+        if self.params.pub_key_types.is_empty() {
+            vec![DEFAULT_PUB_KEY_TYPE.to_string()]
+        } else {
+            self.params.pub_key_types.clone()
+        }
+    }
+
+    /// The fewest updates that turn the set into `desired`, ordered by key: a zero power for each
+    /// validator missing from `desired`, and the new power of each one added or changed.
+    /// Validators with zero power in `desired` count as missing.
+    pub fn diff(
+        &self,
+        desired: &[ValidatorUpdate],
+    ) -> Result<Vec<ValidatorUpdate>, ValidatorSetError> {
+        self.validate(desired)?;
+        let desired: BTreeMap<&PubKey, i64> = desired
+            .iter()
+            .filter(|update| update.power > 0)
+            .map(|update| (&update.pub_key, update.power))
+            .collect();
+        let removed = self
+            .powers
+            .keys()
+            .filter(|pub_key| !desired.contains_key(pub_key))
+            .map(|pub_key| (pub_key, 0));
+        let changed = desired
+            .iter()
+            .filter(|(pub_key, power)| self.power(pub_key) != **power)
+            .map(|(pub_key, power)| (*pub_key, *power));
+        let updates: BTreeMap<&PubKey, i64> = removed.chain(changed).collect();
+        Ok(updates
+            .into_iter()
+            .map(|(pub_key, power)| ValidatorUpdate {
+                pub_key: pub_key.clone(),
+                power,
+            })
+            .collect())
+    }
+
+    /// Applies updates as Tendermint does: a zero power removes the validator, which must be in
+    /// the set, any other power sets it. Nothing is applied if the updates are rejected.
+    pub fn apply(&mut self, updates: &[ValidatorUpdate]) -> Result<(), ValidatorSetError> {
+        self.validate(updates)?;
+        let mut powers = self.powers.clone();
+        for update in updates {
+            if update.power == 0 {
+                if powers.remove(&update.pub_key).is_none() {
+                    return Err(ValidatorSetError::UnknownValidator(update.pub_key.clone()));
+                }
+            } else {
+                powers.insert(update.pub_key.clone(), update.power);
+            }
+        }
+        if powers.is_empty() {
+            return Err(ValidatorSetError::EmptySet);
+        }
+        let total = powers
+            .values()
+            .try_fold(0i64, |total, power| total.checked_add(*power));
+        if !matches!(total, Some(total) if total <= MAX_TOTAL_VOTING_POWER) {
+            return Err(ValidatorSetError::TotalPowerTooHigh);
+        }
+        self.powers = powers;
+        Ok(())
+    }
+
+    /// Computes the updates to `desired` and applies them, returning them for
+    /// `ResponseEndBlock.validator_updates`.
+    pub fn update_to(
+        &mut self,
+        desired: &[ValidatorUpdate],
+    ) -> Result<Vec<ValidatorUpdate>, ValidatorSetError> {
+        let updates = self.diff(desired)?;
+        self.apply(&updates)?;
+        Ok(updates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key_type: &str, byte: u8) -> PubKey {
+        PubKey {
+            key_type: key_type.to_string(),
+            data: vec![byte; 32],
+        }
+    }
+
+    fn update(byte: u8, power: i64) -> ValidatorUpdate {
+        ValidatorUpdate {
+            pub_key: key("ed25519", byte),
+            power,
+        }
+    }
+
+    fn set() -> ValidatorSet {
+        ValidatorSet::from_updates(
+            ValidatorParams::default(),
+            &[update(1, 10), update(2, 20), update(3, 30)],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_emit_minimal_updates() {
+        let mut set = set();
+        assert_eq!(set.total_power(), 60);
+        assert_eq!(
+            set.diff(&[update(3, 30), update(2, 20), update(1, 10)]),
+            Ok(vec![])
+        );
+        assert_eq!(
+            set.update_to(&[update(4, 5), update(2, 25), update(1, 10), update(3, 0)]),
+            Ok(vec![update(2, 25), update(3, 0), update(4, 5)])
+        );
+        assert_eq!(set.power(&key("ed25519", 2)), 25);
+        assert_eq!(set.power(&key("ed25519", 3)), 0);
+        assert_eq!(
+            set.iter().map(|(_, power)| power).collect::<Vec<_>>(),
+            vec![10, 25, 5]
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_updates() {
+        let mut set = set();
+        assert_eq!(
+            set.diff(&[update(1, -1)]),
+            Err(ValidatorSetError::NegativePower {
+                pub_key: key("ed25519", 1),
+                power: -1,
+            })
+        );
+        assert_eq!(
+            set.apply(&[update(1, 5), update(1, 0)]),
+            Err(ValidatorSetError::DuplicateKey(key("ed25519", 1)))
+        );
+        assert_eq!(set.update_to(&[]), Err(ValidatorSetError::EmptySet));
+        assert_eq!(
+            set.apply(&[update(9, 0)]),
+            Err(ValidatorSetError::UnknownValidator(key("ed25519", 9)))
+        );
+        assert_eq!(
+            set.apply(&[update(1, MAX_TOTAL_VOTING_POWER)]),
+            Err(ValidatorSetError::TotalPowerTooHigh)
+        );
+        assert_eq!(
+            set.apply(&[update(1, i64::MAX)]),
+            Err(ValidatorSetError::TotalPowerTooHigh)
+        );
+        assert_eq!(set, self::set());
+
+        let secp = ValidatorUpdate {
+            pub_key: key("secp256k1", 9),
+            power: 1,
+        };
+        match set.apply(std::slice::from_ref(&secp)) {
+            Err(error @ ValidatorSetError::UnsupportedKeyType { .. }) => assert_eq!(
+                error.to_string(),
+                format!(
+                    "validator secp256k1:{} has key type \"secp256k1\", expected one of [\"ed25519\"]",
+                    "09".repeat(32)
+                )
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        set.set_params(ValidatorParams {
+            pub_key_types: vec![String::from("ed25519"), String::from("secp256k1")],
+        });
+        set.apply(&[secp]).unwrap();
+        assert_eq!(set.len(), 4);
+    }
+}
@@ -0,0 +1,8 @@
+module github.com/tendermint/rust-abci/tests/fixtures/gen
+
+go 1.14
+
+require (
+	github.com/tendermint/go-amino v0.14.1
+	github.com/tendermint/tendermint v0.33.6
+)
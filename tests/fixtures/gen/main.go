@@ -0,0 +1,131 @@
+// Command gen writes the JSON fixtures in tests/fixtures from Tendermint v0.33.6 values, encoded
+// with the go-amino codec of Tendermint's RPC server. Amino defers to the jsonpb MarshalJSON of
+// ResponseDeliverTx and ResponseQuery, so those two come out as jsonpb.
+//
+// Run it from this directory:
+//
+//	go mod tidy && go run .
+package main
+
+import (
+	"bytes"
+	"io/ioutil"
+	"path/filepath"
+	"time"
+
+	amino "github.com/tendermint/go-amino"
+	abci "github.com/tendermint/tendermint/abci/types"
+	"github.com/tendermint/tendermint/crypto/merkle"
+	"github.com/tendermint/tendermint/libs/kv"
+)
+
+var cdc = amino.NewCodec()
+
+func repeat(b byte, n int) []byte {
+	return bytes.Repeat([]byte{b}, n)
+}
+
+// count gives the n bytes from start on.
+func count(start byte, n int) []byte {
+	bz := make([]byte, n)
+	for i := range bz {
+		bz[i] = start + byte(i)
+	}
+	return bz
+}
+
+func write(name string, v interface{}) {
+	bz, err := cdc.MarshalJSONIndent(v, "", "  ")
+	if err != nil {
+		panic(err)
+	}
+	if err := ioutil.WriteFile(filepath.Join("..", name), append(bz, '\n'), 0644); err != nil {
+		panic(err)
+	}
+}
+
+func main() {
+	proposer := repeat(0x7f, 20)
+	byzantine := repeat(0x3e, 20)
+
+	write("request_begin_block.json", &abci.RequestBeginBlock{
+		Hash: repeat(0xa1, 32),
+		Header: abci.Header{
+			Version: abci.Version{Block: 10, App: 1},
+			ChainID: "test-chain-Tz8qcC",
+			Height:  1204,
+			Time:    time.Date(2020, 7, 17, 15, 33, 20, 123450000, time.UTC),
+			LastBlockId: abci.BlockID{
+				Hash:        repeat(0xb2, 32),
+				PartsHeader: abci.PartSetHeader{Total: 1, Hash: repeat(0xc3, 32)},
+			},
+			LastCommitHash:     repeat(0x01, 32),
+			DataHash:           repeat(0x02, 32),
+			ValidatorsHash:     repeat(0x03, 32),
+			NextValidatorsHash: repeat(0x03, 32),
+			ConsensusHash:      repeat(0x04, 32),
+			AppHash:            count(0, 8),
+			LastResultsHash:    repeat(0x05, 32),
+			EvidenceHash:       repeat(0x06, 32),
+			ProposerAddress:    proposer,
+		},
+		LastCommitInfo: abci.LastCommitInfo{
+			Round: 1,
+			Votes: []abci.VoteInfo{
+				{Validator: abci.Validator{Address: proposer, Power: 10}, SignedLastBlock: true},
+				{Validator: abci.Validator{Address: byzantine, Power: 5}},
+			},
+		},
+		ByzantineValidators: []abci.Evidence{{
+			Type:             "duplicate/vote",
+			Validator:        abci.Validator{Address: byzantine, Power: 5},
+			Height:           1203,
+			Time:             time.Date(2020, 7, 17, 15, 33, 14, 0, time.UTC),
+			TotalVotingPower: 15,
+		}},
+	})
+
+	write("response_deliver_tx.json", &abci.ResponseDeliverTx{
+		Data:      []byte("ok"),
+		Log:       `[{"msg_index":0,"events":[{"type":"transfer"}]}]`,
+		GasWanted: 120000,
+		GasUsed:   53712,
+		Events: []abci.Event{
+			{Type: "transfer", Attributes: []kv.Pair{
+				{Key: []byte("recipient"), Value: []byte("cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu")},
+				{Key: []byte("sender"), Value: []byte("cosmos1yy3zxfp9ycnjs2f29vkz6t30xqcnyve5j4ep6w")},
+				{Key: []byte("amount"), Value: []byte("100uatom")},
+			}},
+			{Type: "message", Attributes: []kv.Pair{
+				{Key: []byte("action"), Value: []byte("send")},
+			}},
+		},
+	})
+
+	write("response_end_block.json", &abci.ResponseEndBlock{
+		ValidatorUpdates: []abci.ValidatorUpdate{
+			{PubKey: abci.PubKey{Type: "ed25519", Data: count(0, 32)}, Power: 7},
+			{PubKey: abci.PubKey{Type: "ed25519", Data: count(32, 32)}},
+		},
+		ConsensusParamUpdates: &abci.ConsensusParams{
+			Block:     &abci.BlockParams{MaxBytes: 22020096, MaxGas: -1},
+			Evidence:  &abci.EvidenceParams{MaxAgeNumBlocks: 100000, MaxAgeDuration: 48 * time.Hour},
+			Validator: &abci.ValidatorParams{PubKeyTypes: []string{"ed25519"}},
+		},
+		Events: []abci.Event{
+			{Type: "rewards", Attributes: []kv.Pair{
+				{Key: []byte("validator"), Value: proposer},
+			}},
+		},
+	})
+
+	write("response_query.json", &abci.ResponseQuery{
+		Key:   []byte("balance/alice"),
+		Value: []byte("100"),
+		Proof: &merkle.Proof{Ops: []merkle.ProofOp{
+			{Type: "iavl:v", Key: []byte("balance/alice"), Data: count(0, 40)},
+			{Type: "multistore", Key: []byte("bank"), Data: count(40, 32)},
+		}},
+		Height: 1204,
+	})
+}